@@ -0,0 +1,296 @@
+//! Animated images of the display, recorded a 60 Hz frame at a time: GIF, which everything can
+//! show but at no more than 50 frames a second, and APNG, which keeps every frame and colour.
+//!
+//! Frames are encoded as they're added, and a frame that's the same as the one before only makes
+//! that one last longer.
+
+use std::collections::HashMap;
+
+use crate::image::{write_chunk, zlib_compressed};
+
+/// The frame rate of the display.
+const FRAME_RATE: u16 = 60;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    Apng,
+}
+
+impl Format {
+    /// The format for a file name: `.gif` for GIF, `.png` or `.apng` for APNG.
+    pub fn from_path(path: &str) -> Option<Format> {
+        let ext = std::path::Path::new(path).extension()?.to_str()?;
+        match ext.to_ascii_lowercase().as_str() {
+            "gif" => Some(Format::Gif),
+            "png" | "apng" => Some(Format::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// An animation being recorded.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    format: Format,
+    width: usize,
+    height: usize,
+    /// Each distinct frame's encoded image data and how many 60 Hz frames it's shown for.
+    frames: Vec<(Vec<u8>, u32)>,
+    /// The RGB pixels of the last frame added.
+    last: Vec<u8>,
+}
+
+impl Animation {
+    /// Starts an animation of `width` by `height` frames.
+    pub fn new(format: Format, width: usize, height: usize) -> Self {
+        Self {
+            format,
+            width,
+            height,
+            frames: Vec::new(),
+            last: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// How many 60 Hz frames have been added.
+    pub fn frame_count(&self) -> u32 {
+        self.frames.iter().map(|(_, frames)| frames).sum()
+    }
+
+    /// Adds the next 60 Hz frame as 8-bit RGB pixels, row by row.
+    pub fn push(&mut self, rgb: &[u8]) {
+        assert_eq!(
+            rgb.len(),
+            self.width * self.height * 3,
+            "pixel data doesn't match the size"
+        );
+        if let Some((_, frames)) = self.frames.last_mut().filter(|_| self.last == rgb) {
+            *frames += 1;
+            return;
+        }
+        let data = match self.format {
+            Format::Gif => gif_image(self.width, self.height, rgb),
+            Format::Apng => {
+                let row_len = self.width * 3 + 1;
+                let mut scanlines = Vec::with_capacity(self.height * row_len);
+                for row in rgb.chunks(self.width * 3) {
+                    // No filter.
+                    scanlines.push(0);
+                    scanlines.extend_from_slice(row);
+                }
+                zlib_compressed(&scanlines, 3, row_len)
+            }
+        };
+        self.frames.push((data, 1));
+        self.last.clear();
+        self.last.extend_from_slice(rgb);
+    }
+
+    /// The finished file, looping forever.
+    pub fn encode(&self) -> Vec<u8> {
+        match self.format {
+            Format::Gif => self.gif(),
+            Format::Apng => self.apng(),
+        }
+    }
+
+    fn gif(&self) -> Vec<u8> {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&(self.width as u16).to_le_bytes());
+        gif.extend_from_slice(&(self.height as u16).to_le_bytes());
+        // No global colour table, every frame brings its own.
+        gif.extend_from_slice(&[0, 0, 0]);
+        // Loop forever.
+        gif.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+        // Delays are in whole hundredths of a second and players slow down anything shorter
+        // than two, so frames starting less than two hundredths after the last one shown are
+        // dropped, leaving at most 50 a second.
+        let mut shown: Vec<(&[u8], u32)> = Vec::new();
+        let mut elapsed = 0;
+        for (image, frames) in &self.frames {
+            let start = elapsed * 100 / FRAME_RATE as u32;
+            elapsed += frames;
+            if !matches!(shown.last(), Some((_, last)) if start < last + 2) {
+                shown.push((image, start));
+            }
+        }
+        let end = (elapsed * 100).div_ceil(FRAME_RATE as u32);
+        for (i, (image, start)) in shown.iter().enumerate() {
+            let next = shown.get(i + 1).map_or(end, |(_, next)| *next);
+            let delay = (next - start).max(2).min(u16::MAX as u32) as u16;
+            gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+            gif.extend_from_slice(&delay.to_le_bytes());
+            gif.extend_from_slice(&[0x00, 0x00]);
+            gif.extend_from_slice(image);
+        }
+        gif.push(0x3B);
+        gif
+    }
+
+    fn apng(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, truecolour, default compression and filtering, no interlacing.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        let mut control = Vec::with_capacity(8);
+        control.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+        // Loop forever.
+        control.extend_from_slice(&0u32.to_be_bytes());
+        write_chunk(&mut png, b"acTL", &control);
+
+        // Frame controls and frame data share one sequence.
+        let mut sequence = 0u32;
+        for (i, (data, frames)) in self.frames.iter().enumerate() {
+            let mut control = Vec::with_capacity(26);
+            control.extend_from_slice(&sequence.to_be_bytes());
+            control.extend_from_slice(&(self.width as u32).to_be_bytes());
+            control.extend_from_slice(&(self.height as u32).to_be_bytes());
+            control.extend_from_slice(&[0; 8]);
+            control.extend_from_slice(&(*frames).min(u16::MAX as u32).to_be_bytes()[2..]);
+            control.extend_from_slice(&FRAME_RATE.to_be_bytes());
+            // Leave the frame in place and replace the whole canvas with the next one.
+            control.extend_from_slice(&[0, 0]);
+            write_chunk(&mut png, b"fcTL", &control);
+            sequence += 1;
+
+            if i == 0 {
+                write_chunk(&mut png, b"IDAT", data);
+            } else {
+                let mut frame = sequence.to_be_bytes().to_vec();
+                frame.extend_from_slice(data);
+                write_chunk(&mut png, b"fdAT", &frame);
+                sequence += 1;
+            }
+        }
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// A GIF image descriptor, local colour table and LZW compressed pixels for a full frame.
+///
+/// A frame can only have 256 colours. Past that, pixels take the nearest colour already in the
+/// table, which doesn't come up with CHIP-8's palettes.
+fn gif_image(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    let mut lookup: HashMap<[u8; 3], u8> = HashMap::new();
+    let indices = rgb
+        .chunks(3)
+        .map(|pixel| {
+            let color = [pixel[0], pixel[1], pixel[2]];
+            *lookup.entry(color).or_insert_with(|| {
+                if colors.len() < 256 {
+                    colors.push(color);
+                    (colors.len() - 1) as u8
+                } else {
+                    nearest(&colors, color)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // The table holds a power of two colours, at least two.
+    let bits = (usize::BITS - (colors.len().max(2) - 1).leading_zeros()) as u8;
+    colors.resize(1 << bits, [0; 3]);
+
+    let mut image = vec![0x2C, 0, 0, 0, 0];
+    image.extend_from_slice(&(width as u16).to_le_bytes());
+    image.extend_from_slice(&(height as u16).to_le_bytes());
+    image.push(0x80 | (bits - 1));
+    image.extend(colors.iter().flatten());
+
+    let min_code_size = bits.max(2);
+    image.push(min_code_size);
+    for block in lzw(&indices, min_code_size).chunks(255) {
+        image.push(block.len() as u8);
+        image.extend_from_slice(block);
+    }
+    image.push(0);
+    image
+}
+
+fn nearest(colors: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |other: &[u8; 3]| -> u32 {
+        other
+            .iter()
+            .zip(color)
+            .map(|(a, b)| (*a as i32 - b as i32).pow(2) as u32)
+            .sum()
+    };
+    (0..colors.len())
+        .min_by_key(|i| distance(&colors[*i]))
+        .unwrap_or(0) as u8
+}
+
+/// GIF's variable code width LZW.
+fn lzw(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    const MAX_CODES: u16 = 4096;
+
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = min_code_size as u32 + 1;
+
+    let mut out = Vec::new();
+    let (mut pending, mut pending_len) = (0u32, 0u32);
+    let mut emit = |code: u16, width: u32| {
+        pending |= (code as u32) << pending_len;
+        pending_len += width;
+        while pending_len >= 8 {
+            out.push(pending as u8);
+            pending >>= 8;
+            pending_len -= 8;
+        }
+    };
+
+    emit(clear, width);
+    let mut indices = indices.iter();
+    if let Some(first) = indices.next() {
+        let mut prefix = *first as u16;
+        for index in indices {
+            if let Some(code) = codes.get(&(prefix, *index)) {
+                prefix = *code;
+                continue;
+            }
+            emit(prefix, width);
+            if next == MAX_CODES {
+                emit(clear, width);
+                codes.clear();
+                next = end + 1;
+                width = min_code_size as u32 + 1;
+            } else {
+                codes.insert((prefix, *index), next);
+                // The decoder widens its codes as soon as the table reaches the next power of two.
+                if next == 1 << width && width < 12 {
+                    width += 1;
+                }
+                next += 1;
+            }
+            prefix = *index as u16;
+        }
+        emit(prefix, width);
+        if next == 1 << width && width < 12 {
+            width += 1;
+        }
+    }
+    emit(end, width);
+    if pending_len > 0 {
+        out.push(pending as u8);
+    }
+    out
+}
@@ -0,0 +1,884 @@
+//! An assembler for [Octo](https://github.com/JohnEarnest/Octo) source.
+//!
+//! Covers Octo's statements for every CHIP-8, SUPER-CHIP and XO-CHIP instruction, labels,
+//! `if`/`loop` control flow, `:alias`, `:const`, `:calc`, `:macro`, `:org`, `:byte`, `:unpack`,
+//! `:call` and numbers as sprite data. Unlike Octo, programs start at `0x200` rather than at
+//! `main`, `:calc` only has a few functions and there are no `<`/`>` comparisons.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::{Instruction, VariableRegister, PROGRAM_START};
+
+/// The size of XO-CHIP's extended memory, the most a program can fill.
+const MEMORY_SIZE: usize = 0x10000;
+
+/// How deeply macros may expand into other macros, to catch ones that invoke themselves.
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// An error in the source, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// An assembled program along with where its labels and source lines ended up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    rom: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+    /// The source line of the statement that starts at each address.
+    lines: BTreeMap<u16, usize>,
+}
+
+impl Assembly {
+    /// The program, to be loaded at `0x200`.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn labels(&self) -> &BTreeMap<String, u16> {
+        &self.labels
+    }
+
+    /// The source line of the statement that assembled to the byte at `addr`.
+    pub fn line_at(&self, addr: u16) -> Option<usize> {
+        if addr as usize >= PROGRAM_START as usize + self.rom.len() {
+            return None;
+        }
+        self.lines.range(..=addr).next_back().map(|(_, line)| *line)
+    }
+
+    /// A text file with a `label <name> <address>` line for every label followed by a
+    /// `line <address> <source line>` line for every statement.
+    pub fn debug_info(&self) -> String {
+        let mut out = String::new();
+        for (name, addr) in &self.labels {
+            out.push_str(&format!("label {} {:#05x}\n", name, addr));
+        }
+        for (addr, line) in &self.lines {
+            out.push_str(&format!("line {:#05x} {}\n", addr, line));
+        }
+        out
+    }
+}
+
+/// Assembles Octo `source` into a ROM.
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |text| Token {
+                text: text.to_string(),
+                line: i + 1,
+                depth: 0,
+            })
+        })
+        .collect();
+
+    let mut asm = Assembler {
+        tokens,
+        pos: 0,
+        line: 1,
+        here: PROGRAM_START as usize,
+        rom: Vec::new(),
+        labels: BTreeMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        references: Vec::new(),
+        blocks: Vec::new(),
+        lines: BTreeMap::new(),
+        statement_recorded: false,
+    };
+    asm.run()?;
+    Ok(Assembly {
+        rom: asm.rom,
+        labels: asm.labels,
+        lines: asm.lines,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+    /// How many macro expansions produced the token.
+    depth: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Where a label that wasn't defined yet has to be filled in once it is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Field {
+    /// The `NNN` of an instruction word.
+    Address,
+    /// The word following `F000`.
+    LongAddress,
+    /// The pair of `6XNN` made by `:unpack`, at the offset of the second one, which takes the
+    /// low byte of the address while the first takes the high nibble.
+    Unpack,
+}
+
+#[derive(Debug, Clone)]
+struct Reference {
+    /// Offset into the ROM of the word to patch.
+    offset: usize,
+    field: Field,
+    label: String,
+    line: usize,
+}
+
+/// An open `if ... begin` or `loop`.
+#[derive(Debug, Clone)]
+enum Block {
+    /// The offset of the jump over the block.
+    If(usize),
+    Else(usize),
+    /// The address `again` jumps back to and the offsets of the jumps out of the loop made by
+    /// `while`.
+    Loop(usize, Vec<usize>),
+}
+
+/// The skips that implement a condition.
+struct Condition {
+    /// Skips the next instruction when the condition holds.
+    skip_if: Instruction,
+    /// Skips the next instruction unless the condition holds.
+    skip_unless: Instruction,
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The line of the statement being assembled.
+    line: usize,
+    here: usize,
+    rom: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, VariableRegister>,
+    macros: HashMap<String, Macro>,
+    references: Vec<Reference>,
+    blocks: Vec<Block>,
+    lines: BTreeMap<u16, usize>,
+    statement_recorded: bool,
+}
+
+impl Assembler {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), AsmError> {
+        while let Some(token) = self.tokens.get(self.pos).cloned() {
+            self.pos += 1;
+            self.line = token.line;
+            self.statement_recorded = false;
+            self.statement(&token.text)?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let message = match block {
+                Block::If(_) | Block::Else(_) => "`begin` without `end`",
+                Block::Loop(..) => "`loop` without `again`",
+            };
+            return Err(self.error(message));
+        }
+
+        for reference in std::mem::take(&mut self.references) {
+            self.line = reference.line;
+            let addr = *self
+                .labels
+                .get(&reference.label)
+                .ok_or_else(|| self.error(format!("undefined name `{}`", reference.label)))?;
+            match reference.field {
+                Field::Address => self.patch_address(reference.offset, addr as usize)?,
+                Field::LongAddress => {
+                    self.rom[reference.offset..reference.offset + 2]
+                        .copy_from_slice(&addr.to_be_bytes());
+                }
+                Field::Unpack => {
+                    self.check_reach(addr as usize)?;
+                    self.rom[reference.offset - 1] |= (addr >> 8) as u8;
+                    self.rom[reference.offset + 1] = addr as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.error("unexpected end of source"))?;
+        self.pos += 1;
+        Ok(token.text.clone())
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(self.error(format!("expected `{}`, found `{}`", expected, token)));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: &str) -> Result<(), AsmError> {
+        let ins = match token {
+            ":" => {
+                let name = self.new_name()?;
+                if self.here >= MEMORY_SIZE {
+                    return Err(self.error(format!("label `{}` is past the end of memory", name)));
+                }
+                self.labels.insert(name, self.here as u16);
+                return Ok(());
+            }
+            ":alias" => {
+                let name = self.new_name()?;
+                let token = self.next()?;
+                let reg = self.register(&token)?;
+                self.aliases.insert(name, reg);
+                return Ok(());
+            }
+            ":const" => {
+                let name = self.new_name()?;
+                let token = self.next()?;
+                let value = self.value(&token)?;
+                self.constants.insert(name, value);
+                return Ok(());
+            }
+            ":calc" => {
+                let name = self.new_name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+                return Ok(());
+            }
+            ":byte" => {
+                let value = match self.peek() {
+                    Some("{") => self.calc()?,
+                    _ => {
+                        let token = self.next()?;
+                        self.value(&token)?
+                    }
+                };
+                let byte = self.byte(value)?;
+                return self.emit_byte(byte);
+            }
+            ":org" => {
+                let token = self.next()?;
+                let addr = self.value(&token)? as i64;
+                if !(PROGRAM_START as i64..MEMORY_SIZE as i64).contains(&addr) {
+                    return Err(self.error(format!("`:org` address {:#x} is out of range", addr)));
+                }
+                self.here = addr as usize;
+                self.statement_recorded = false;
+                return Ok(());
+            }
+            ":macro" => return self.define_macro(),
+            ":unpack" => return self.unpack(),
+            ":call" => Instruction::Call(self.address(Field::Address)?),
+            "clear" => Instruction::ClearScreen,
+            "return" | ";" => Instruction::Pop,
+            "exit" => Instruction::Exit,
+            "lores" => Instruction::LowRes,
+            "hires" => Instruction::HighRes,
+            "scroll-left" => Instruction::ScrollLeft,
+            "scroll-right" => Instruction::ScrollRight,
+            "scroll-down" => Instruction::ScrollDown(self.nibble()?),
+            "scroll-up" => Instruction::ScrollUp(self.nibble()?),
+            "plane" => Instruction::SelectPlanes(self.nibble()?),
+            "audio" => Instruction::LoadAudioPattern,
+            "jump" => Instruction::Jump(self.address(Field::Address)?),
+            "jump0" => Instruction::JumpWithOffset(self.address(Field::Address)?),
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                Instruction::Display {
+                    x,
+                    y,
+                    n: self.nibble()?,
+                }
+            }
+            "save" | "load" => {
+                let x = self.next_register()?;
+                if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.next_register()?;
+                    match token {
+                        "save" => Instruction::SaveRange(x, y),
+                        _ => Instruction::LoadRange(x, y),
+                    }
+                } else if token == "save" {
+                    Instruction::Store(x as u8)
+                } else {
+                    Instruction::Load(x as u8)
+                }
+            }
+            "saveflags" => Instruction::SaveFlags(self.next_register()? as u8),
+            "loadflags" => Instruction::LoadFlags(self.next_register()? as u8),
+            "bcd" => Instruction::BinDecConversion(self.next_register()?),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let reg = self.next_register()?;
+                match token {
+                    "delay" => Instruction::SetDelayTimer(reg),
+                    "buzzer" => Instruction::SetSoundTimer(reg),
+                    _ => Instruction::SetPitch(reg),
+                }
+            }
+            "i" => return self.index_statement(),
+            "if" => return self.if_statement(),
+            "else" => {
+                let Some(Block::If(jump)) = self.blocks.pop() else {
+                    return Err(self.error("`else` without `if ... begin`"));
+                };
+                let offset = self.emit_placeholder(Instruction::Jump(0))?;
+                self.patch_address(jump, self.here)?;
+                self.blocks.push(Block::Else(offset));
+                return Ok(());
+            }
+            "end" => {
+                let Some(Block::If(jump) | Block::Else(jump)) = self.blocks.pop() else {
+                    return Err(self.error("`end` without `if ... begin`"));
+                };
+                return self.patch_address(jump, self.here);
+            }
+            "loop" => {
+                self.blocks.push(Block::Loop(self.here, Vec::new()));
+                return Ok(());
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(condition.skip_if)?;
+                let offset = self.emit_placeholder(Instruction::Jump(0))?;
+                let Some(Block::Loop(_, breaks)) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop(..)))
+                else {
+                    return Err(self.error("`while` outside of `loop`"));
+                };
+                breaks.push(offset);
+                return Ok(());
+            }
+            "again" => {
+                let Some(Block::Loop(start, breaks)) = self.blocks.pop() else {
+                    return Err(self.error("`again` without `loop`"));
+                };
+                self.check_reach(start)?;
+                self.emit(Instruction::Jump(start as u16))?;
+                for offset in breaks {
+                    self.patch_address(offset, self.here)?;
+                }
+                return Ok(());
+            }
+            _ if self.try_register(token).is_some() => return self.register_statement(token),
+            _ if self.macros.contains_key(token) => return self.expand_macro(token),
+            _ if self.number(token).is_some() && !self.labels.contains_key(token) => {
+                let value = self.value(token)?;
+                let byte = self.byte(value)?;
+                return self.emit_byte(byte);
+            }
+            // Anything else names a subroutine, which may not have been defined yet.
+            _ => {
+                self.pos -= 1;
+                Instruction::Call(self.address(Field::Address)?)
+            }
+        };
+        self.emit(ins)
+    }
+
+    /// `i := NNN`, `i := long NNNN`, `i := hex vx`, `i := bighex vx` and `i += vx`.
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        let ins = match (op.as_str(), self.peek()) {
+            ("+=", _) => Instruction::AddToIndex(self.next_register()?),
+            (":=", Some("hex")) => {
+                self.pos += 1;
+                Instruction::Font(self.next_register()?)
+            }
+            (":=", Some("bighex")) => {
+                self.pos += 1;
+                Instruction::BigFont(self.next_register()?)
+            }
+            (":=", Some("long")) => {
+                self.pos += 1;
+                self.emit(Instruction::SetIdxRegLong)?;
+                let addr = self.address(Field::LongAddress)?;
+                return self.emit_word(addr);
+            }
+            (":=", _) => Instruction::SetIdxRegImm(self.address(Field::Address)?),
+            _ => return Err(self.error(format!("unknown operator `i {}`", op))),
+        };
+        self.emit(ins)
+    }
+
+    /// Assignments and arithmetic on a register.
+    fn register_statement(&mut self, token: &str) -> Result<(), AsmError> {
+        let x = self.register(token)?;
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let ins = match (op.as_str(), rhs.as_str()) {
+            (":=", "random") => {
+                let token = self.next()?;
+                let value = self.value(&token)?;
+                Instruction::Random(x, self.byte(value)?)
+            }
+            (":=", "key") => Instruction::GetKey(x),
+            (":=", "delay") => Instruction::GetDelayTimer(x),
+            (op, rhs) => match (op, self.try_register(rhs)) {
+                (":=", Some(y)) => Instruction::Set(x, y),
+                ("|=", Some(y)) => Instruction::BinOr(x, y),
+                ("&=", Some(y)) => Instruction::BinAnd(x, y),
+                ("^=", Some(y)) => Instruction::Xor(x, y),
+                ("+=", Some(y)) => Instruction::Add(x, y),
+                ("-=", Some(y)) => Instruction::SubtractLR(x, y),
+                ("=-", Some(y)) => Instruction::SubtractRL(x, y),
+                (">>=", Some(y)) => Instruction::ShiftRight(x, y),
+                ("<<=", Some(y)) => Instruction::ShiftLeft(x, y),
+                (":=", None) => Instruction::SetRegImm(x, self.byte(self.value(rhs)?)?),
+                ("+=", None) => Instruction::AddRegImm(x, self.byte(self.value(rhs)?)?),
+                ("-=", None) => {
+                    let value = self.byte(self.value(rhs)?)?;
+                    Instruction::AddRegImm(x, value.wrapping_neg())
+                }
+                _ => return Err(self.error(format!("unknown operator `{} {}`", op, rhs))),
+            },
+        };
+        self.emit(ins)
+    }
+
+    /// `if <condition> then` skips the next statement unless the condition holds, while
+    /// `if <condition> begin ... else ... end` runs a block.
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        match self.next()?.as_str() {
+            "then" => self.emit(condition.skip_unless),
+            "begin" => {
+                self.emit(condition.skip_if)?;
+                let offset = self.emit_placeholder(Instruction::Jump(0))?;
+                self.blocks.push(Block::If(offset));
+                Ok(())
+            }
+            token => Err(self.error(format!("expected `then` or `begin`, found `{}`", token))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.next_register()?;
+        let op = self.next()?;
+        let (skip_if, skip_unless) = match op.as_str() {
+            "key" => (
+                Instruction::SkipIfKeyPressed(x),
+                Instruction::SkipIfKeyNotPressed(x),
+            ),
+            "-key" => (
+                Instruction::SkipIfKeyNotPressed(x),
+                Instruction::SkipIfKeyPressed(x),
+            ),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let (eq, neq) = match self.try_register(&rhs) {
+                    Some(y) => (
+                        Instruction::SkipIfEqReg(x, y),
+                        Instruction::SkipIfNeqReg(x, y),
+                    ),
+                    None => {
+                        let imm = self.byte(self.value(&rhs)?)?;
+                        (
+                            Instruction::SkipIfEqImm(x, imm),
+                            Instruction::SkipIfNeqImm(x, imm),
+                        )
+                    }
+                };
+                if op == "==" {
+                    (eq, neq)
+                } else {
+                    (neq, eq)
+                }
+            }
+            _ => return Err(self.error(format!("unsupported comparison `{}`", op))),
+        };
+        Ok(Condition {
+            skip_if,
+            skip_unless,
+        })
+    }
+
+    /// `:macro <name> <params>... { <body> }`
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.new_name()?;
+        let mut params = Vec::new();
+        loop {
+            match self.next()?.as_str() {
+                "{" => break,
+                param => params.push(param.to_string()),
+            }
+        }
+        let body = self.block_tokens()?;
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replaces a macro invocation with the macro's body, with the arguments substituted.
+    fn expand_macro(&mut self, name: &str) -> Result<(), AsmError> {
+        let depth = self.tokens[self.pos - 1].depth + 1;
+        if depth > MAX_MACRO_DEPTH {
+            return Err(self.error(format!(
+                "macro `{}` expands more than {} levels deep, does it invoke itself?",
+                name, MAX_MACRO_DEPTH
+            )));
+        }
+        let mac = self.macros[name].clone();
+        let mut args = HashMap::new();
+        for param in &mac.params {
+            args.insert(param.clone(), self.next()?);
+        }
+        let body = mac.body.iter().map(|token| Token {
+            text: args.get(&token.text).unwrap_or(&token.text).clone(),
+            line: self.line,
+            depth,
+        });
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    /// The tokens up to the `}` closing an already consumed `{`.
+    fn block_tokens(&mut self) -> Result<Vec<Token>, AsmError> {
+        let mut depth = 0;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self
+                .tokens
+                .get(self.pos)
+                .cloned()
+                .ok_or_else(|| self.error("`{` without `}`"))?;
+            self.pos += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// `:unpack <nibble> <label>` sets `v0` to the nibble and the high nibble of the address and
+    /// `v1` to its low byte.
+    fn unpack(&mut self) -> Result<(), AsmError> {
+        let nibble = self.nibble()?;
+        let token = self.next()?;
+        let addr = match self.number(&token) {
+            Some(value) => self.checked(value, 0xFFF)?,
+            None => {
+                self.references.push(Reference {
+                    offset: self.offset() + 2,
+                    field: Field::Unpack,
+                    label: token,
+                    line: self.line,
+                });
+                0
+            }
+        };
+        self.emit(Instruction::SetRegImm(
+            VariableRegister::V0,
+            nibble << 4 | (addr >> 8) as u8,
+        ))?;
+        self.emit(Instruction::SetRegImm(VariableRegister::V1, addr as u8))
+    }
+
+    /// `:calc`'s expression between braces. Like Octo, operators have no precedence and are
+    /// evaluated from right to left.
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        self.expect("{")?;
+        let tokens = self.block_tokens()?;
+        let mut pos = 0;
+        let value = self.expression(&tokens, &mut pos)?;
+        if pos < tokens.len() {
+            return Err(self.error(format!("unexpected `{}` in `:calc`", tokens[pos].text)));
+        }
+        Ok(value)
+    }
+
+    fn expression(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, AsmError> {
+        let lhs = self.term(tokens, pos)?;
+        let Some(op) = tokens.get(*pos) else {
+            return Ok(lhs);
+        };
+        let op = op.text.as_str();
+        // Shifts give `None` for negative amounts and ones past the 64 bits of an `i64`.
+        let apply: fn(f64, f64) -> Option<f64> = match op {
+            "+" => |a, b| Some(a + b),
+            "-" => |a, b| Some(a - b),
+            "*" => |a, b| Some(a * b),
+            "/" => |a, b| Some(a / b),
+            "%" => |a, b| Some(a % b),
+            "&" => |a, b| Some((a as i64 & b as i64) as f64),
+            "|" => |a, b| Some((a as i64 | b as i64) as f64),
+            "^" => |a, b| Some((a as i64 ^ b as i64) as f64),
+            "<<" => |a, b| {
+                shift_amount(b)
+                    .and_then(|b| (a as i64).checked_shl(b))
+                    .map(|v| v as f64)
+            },
+            ">>" => |a, b| {
+                shift_amount(b)
+                    .and_then(|b| (a as i64).checked_shr(b))
+                    .map(|v| v as f64)
+            },
+            "pow" => |a, b| Some(a.powf(b)),
+            "min" => |a, b| Some(a.min(b)),
+            "max" => |a, b| Some(a.max(b)),
+            ")" => return Ok(lhs),
+            _ => return Err(self.error(format!("unknown operator `{}` in `:calc`", op))),
+        };
+        *pos += 1;
+        let rhs = self.expression(tokens, pos)?;
+        apply(lhs, rhs).ok_or_else(|| self.error(format!("cannot shift by {} in `:calc`", rhs)))
+    }
+
+    fn term(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, AsmError> {
+        let token = tokens
+            .get(*pos)
+            .ok_or_else(|| self.error("incomplete `:calc` expression"))?;
+        *pos += 1;
+        let unary: fn(f64) -> f64 = match token.text.as_str() {
+            "(" => {
+                let value = self.expression(tokens, pos)?;
+                match tokens.get(*pos) {
+                    Some(token) if token.text == ")" => *pos += 1,
+                    _ => return Err(self.error("`(` without `)` in `:calc`")),
+                }
+                return Ok(value);
+            }
+            "-" => |a| -a,
+            "~" => |a| !(a as i64) as f64,
+            "!" => |a| (a == 0.0) as i64 as f64,
+            "abs" => f64::abs,
+            "sqrt" => f64::sqrt,
+            "floor" => f64::floor,
+            "ceil" => f64::ceil,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "HERE" => return Ok(self.here as f64),
+            "PI" => return Ok(std::f64::consts::PI),
+            "E" => return Ok(std::f64::consts::E),
+            text => return self.value(text),
+        };
+        Ok(unary(self.term(tokens, pos)?))
+    }
+
+    /// A number literal, constant or label that is already defined.
+    fn number(&self, token: &str) -> Option<f64> {
+        if let Some(value) = self.constants.get(token) {
+            return Some(*value);
+        }
+        if let Some(addr) = self.labels.get(token) {
+            return Some(*addr as f64);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2).ok()?
+        } else {
+            digits.parse::<i64>().ok()?
+        };
+        Some(if negative { -value } else { value } as f64)
+    }
+
+    fn value(&self, token: &str) -> Result<f64, AsmError> {
+        self.number(token)
+            .ok_or_else(|| self.error(format!("expected a number, found `{}`", token)))
+    }
+
+    /// `value` as a byte. Negative values down to -128 are stored as two's complement.
+    fn byte(&self, value: f64) -> Result<u8, AsmError> {
+        let value = value as i64;
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(format!("{} does not fit into a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn checked(&self, value: f64, max: u16) -> Result<u16, AsmError> {
+        let value = value as i64;
+        if !(0..=max as i64).contains(&value) {
+            return Err(self.error(format!(
+                "{:#x} is out of range, expected at most {:#x}",
+                value, max
+            )));
+        }
+        Ok(value as u16)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.value(&token)?;
+        Ok(self.checked(value, 0xF)? as u8)
+    }
+
+    /// An address operand. Names that aren't defined yet are assumed to be labels further down
+    /// and filled in at the end.
+    fn address(&mut self, field: Field) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        let max = if field == Field::LongAddress {
+            (MEMORY_SIZE - 1) as u16
+        } else {
+            0xFFF
+        };
+        if let Some(value) = self.number(&token) {
+            return self.checked(value, max);
+        }
+        if !is_name(&token) {
+            return Err(self.error(format!("expected an address, found `{}`", token)));
+        }
+        let offset = self.offset();
+        self.references.push(Reference {
+            offset,
+            field,
+            label: token,
+            line: self.line,
+        });
+        Ok(0)
+    }
+
+    fn try_register(&self, token: &str) -> Option<VariableRegister> {
+        if let Some(reg) = self.aliases.get(token) {
+            return Some(*reg);
+        }
+        let index = token.strip_prefix(['v', 'V'])?;
+        if index.len() != 1 {
+            return None;
+        }
+        let index = u8::from_str_radix(index, 16).ok()?;
+        VariableRegister::try_from(index).ok()
+    }
+
+    fn register(&self, token: &str) -> Result<VariableRegister, AsmError> {
+        self.try_register(token)
+            .ok_or_else(|| self.error(format!("expected a register, found `{}`", token)))
+    }
+
+    fn next_register(&mut self) -> Result<VariableRegister, AsmError> {
+        let token = self.next()?;
+        self.register(&token)
+    }
+
+    /// The name being defined by `:`, `:alias`, `:const`, `:calc` or `:macro`.
+    fn new_name(&mut self) -> Result<String, AsmError> {
+        let name = self.next()?;
+        if !is_name(&name) || self.try_register(&name).is_some() {
+            return Err(self.error(format!("`{}` can't be used as a name", name)));
+        }
+        if self.labels.contains_key(&name)
+            || self.constants.contains_key(&name)
+            || self.macros.contains_key(&name)
+        {
+            return Err(self.error(format!("`{}` is already defined", name)));
+        }
+        Ok(name)
+    }
+
+    fn offset(&self) -> usize {
+        self.here - PROGRAM_START as usize
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        if self.here >= MEMORY_SIZE {
+            return Err(self.error("program doesn't fit into memory"));
+        }
+        if !self.statement_recorded {
+            self.lines.insert(self.here as u16, self.line);
+            self.statement_recorded = true;
+        }
+        let offset = self.offset();
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AsmError> {
+        let [hi, lo] = word.to_be_bytes();
+        self.emit_byte(hi)?;
+        self.emit_byte(lo)
+    }
+
+    fn emit(&mut self, ins: Instruction) -> Result<(), AsmError> {
+        self.emit_word(ins.encode())
+    }
+
+    /// Emits `ins` with an address to be patched later, returning its offset.
+    fn emit_placeholder(&mut self, ins: Instruction) -> Result<usize, AsmError> {
+        let offset = self.offset();
+        self.emit(ins)?;
+        Ok(offset)
+    }
+
+    fn patch_address(&mut self, offset: usize, addr: usize) -> Result<(), AsmError> {
+        self.check_reach(addr)?;
+        self.rom[offset] |= (addr >> 8) as u8;
+        self.rom[offset + 1] |= addr as u8;
+        Ok(())
+    }
+
+    /// Checks that `addr` fits into the 12 bits of an `NNN` operand or `:unpack`.
+    fn check_reach(&self, addr: usize) -> Result<(), AsmError> {
+        if addr > 0xFFF {
+            return Err(self.error(format!(
+                "address {:#x} is out of reach of a 12-bit operand",
+                addr
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `token` can name a label, constant, alias or macro.
+fn is_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `amount` as a shift of an `i64`, or `None` if it's negative or too far.
+fn shift_amount(amount: f64) -> Option<u32> {
+    u32::try_from(amount as i64)
+        .ok()
+        .filter(|amount| *amount < i64::BITS)
+}
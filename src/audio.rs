@@ -0,0 +1,212 @@
+//! Sound generation for the beeper, which sounds while the sound timer is non-zero.
+//!
+//! Samples are produced here so that any output can play them: the raylib frontend streams them to
+//! the audio device and [`Capture`] renders them to a WAV file.
+
+use std::f32::consts::TAU;
+
+use crate::Chip8;
+
+/// The sample rate sound is generated at unless asked otherwise.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+/// The rate the sound timer ticks at, and so how often what's playing can change.
+const TICK_RATE: u32 = 60;
+
+/// The shape of the beep.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    /// Names accepted by [`Waveform::from_name`].
+    pub const NAMES: [&'static str; 4] = ["square", "triangle", "sawtooth", "sine"];
+
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+
+    /// The waveform's value between -1 and 1 at `phase`, which goes from 0 to 1 over a period.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * TAU).sin(),
+        }
+    }
+}
+
+/// How the beep sounds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tone {
+    /// Frequency in Hz.
+    pub frequency: f32,
+    /// Volume from 0 for silence to 1 for full scale.
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// What a machine is playing until its next timer tick.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sound {
+    on: bool,
+    /// The XO-CHIP pattern and the rate it's played back at, unless the beeper is used.
+    pattern: Option<([u8; 16], f64)>,
+}
+
+impl Sound {
+    pub const SILENCE: Sound = Sound {
+        on: false,
+        pattern: None,
+    };
+
+    /// What `chip8` is playing. An all-zero pattern would be silent, so it means no pattern has
+    /// been loaded and the beeper is used instead.
+    pub fn of(chip8: &Chip8) -> Self {
+        let pattern = chip8.audio_pattern();
+        Self {
+            on: chip8.sound_timer() > 0,
+            pattern: pattern
+                .iter()
+                .any(|b| *b != 0)
+                .then(|| (*pattern, chip8.audio_sample_rate())),
+        }
+    }
+}
+
+/// Generates the beep at a fixed sample rate, keeping the phase between calls so that consecutive
+/// buffers join up without clicks.
+#[derive(Debug, Clone)]
+pub struct Beeper {
+    tone: Tone,
+    sample_rate: u32,
+    phase: f32,
+    /// Position in the XO-CHIP pattern, in bits.
+    pattern_phase: f64,
+}
+
+impl Beeper {
+    pub fn new(tone: Tone, sample_rate: u32) -> Self {
+        Self {
+            tone,
+            sample_rate,
+            phase: 0.0,
+            pattern_phase: 0.0,
+        }
+    }
+
+    /// Fills `samples` with `sound`.
+    pub fn fill(&mut self, sound: &Sound, samples: &mut [f32]) {
+        let step = self.tone.frequency / self.sample_rate as f32;
+        for sample in samples.iter_mut() {
+            let level = match sound.pattern {
+                Some((pattern, _)) => {
+                    let bit = self.pattern_phase as usize;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => self.tone.waveform.sample(self.phase),
+            };
+            *sample = if sound.on {
+                level * self.tone.volume
+            } else {
+                0.0
+            };
+            self.phase = (self.phase + step).fract();
+            if let Some((_, rate)) = sound.pattern {
+                self.pattern_phase = (self.pattern_phase + rate / self.sample_rate as f64) % 128.0;
+            }
+        }
+    }
+}
+
+/// Renders the sound of a run frame by frame, for writing to a WAV file.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    beeper: Beeper,
+    samples: Vec<f32>,
+    frames: u64,
+}
+
+impl Capture {
+    pub fn new(tone: Tone, sample_rate: u32) -> Self {
+        Self {
+            beeper: Beeper::new(tone, sample_rate),
+            samples: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    /// Renders one 60 Hz frame of what `chip8` is playing. Call it right before each timer tick.
+    pub fn frame(&mut self, chip8: &Chip8) {
+        // Frame boundaries fall on the sample the frame starts at, so that rounding doesn't drift.
+        let rate = self.beeper.sample_rate as u64;
+        let start = self.frames * rate / TICK_RATE as u64;
+        self.frames += 1;
+        let end = self.frames * rate / TICK_RATE as u64;
+
+        let len = self.samples.len();
+        self.samples.resize(len + (end - start) as usize, 0.0);
+        self.beeper
+            .fill(&Sound::of(chip8), &mut self.samples[len..]);
+    }
+
+    /// The sound so far as a mono 16-bit PCM WAV file.
+    pub fn wav(&self) -> Vec<u8> {
+        wav(self.beeper.sample_rate, &self.samples)
+    }
+}
+
+/// Encodes samples between -1 and 1 as a mono 16-bit PCM WAV file.
+pub fn wav(sample_rate: u32, samples: &[f32]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel.
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    // Two bytes per frame, 16 bits per sample.
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
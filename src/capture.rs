@@ -0,0 +1,102 @@
+//! Screenshots and recordings of the display as it's shown, with the palette, filter and scaler in
+//! use, enlarged by a capture scale.
+
+use chip_8_interpreter::animation::{Animation, Format};
+use chip_8_interpreter::upscale;
+use chip_8_interpreter::{image, HIRES_HEIGHT, HIRES_WIDTH};
+use raylib::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+/// Saves a screenshot, or with shift starts or stops a recording.
+pub const CAPTURE_KEY: KeyboardKey = KeyboardKey::KEY_F12;
+pub const DEFAULT_SCALE: usize = 4;
+
+pub fn parse_scale(scale: &str) -> Result<usize, String> {
+    scale
+        .parse()
+        .ok()
+        .filter(|scale| (1..=64).contains(scale))
+        .ok_or_else(|| format!("invalid capture scale `{}`, expected 1 to 64", scale))
+}
+
+/// A frame as it's shown: filtered and run through the scaler, as 8-bit RGB pixels.
+#[derive(Debug, Clone, Default)]
+pub struct Picture {
+    pub rgb: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Picture {
+    /// The picture as a PNG, `scale` times as large.
+    pub fn png(&self, scale: usize) -> Vec<u8> {
+        let (width, height) = (self.width * scale, self.height * scale);
+        image::png(
+            width,
+            height,
+            &upscale::nearest(self.width, self.height, &self.rgb, width, height),
+        )
+    }
+}
+
+/// The first `game.<kind><n>.<extension>` next to the ROM that doesn't exist yet, such as
+/// `game.shot1.png` for `game.ch8`.
+pub fn next_path(rom_path: &str, kind: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|n| Path::new(rom_path).with_extension(format!("{}{}.{}", kind, n, extension)))
+        .find(|path| !path.exists())
+        .expect("ran out of file names")
+}
+
+/// An animation being recorded to a file.
+///
+/// Recordings are sized for the 128x64 mode times the scaler's factor and the capture scale, so that
+/// programs switching resolution keep every pixel. Frames in the 64x32 mode are drawn twice as
+/// large.
+pub struct Recording {
+    animation: Animation,
+    path: PathBuf,
+}
+
+impl Recording {
+    /// Starts recording to `path`, a GIF or APNG depending on its extension, at `scale` times the
+    /// size of frames `scaler_factor` times the size of the display.
+    pub fn start(path: &Path, scaler_factor: usize, scale: usize) -> Result<Self, String> {
+        let format = path
+            .to_str()
+            .and_then(Format::from_path)
+            .ok_or_else(|| format!("{}: recordings must be .gif or .png", path.display()))?;
+        let (width, height) = (
+            HIRES_WIDTH * scaler_factor * scale,
+            HIRES_HEIGHT * scaler_factor * scale,
+        );
+        Ok(Self {
+            animation: Animation::new(format, width, height),
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the next 60 Hz frame.
+    pub fn push(&mut self, picture: &Picture) {
+        let (width, height) = (self.animation.width(), self.animation.height());
+        self.animation.push(&upscale::nearest(
+            picture.width,
+            picture.height,
+            &picture.rgb,
+            width,
+            height,
+        ));
+    }
+
+    /// Writes the recording and returns how many frames it has.
+    pub fn finish(self) -> Result<u32, String> {
+        std::fs::write(&self.path, self.animation.encode())
+            .map_err(|err| format!("failed to write {}: {}", self.path.display(), err))?;
+        Ok(self.animation.frame_count())
+    }
+}
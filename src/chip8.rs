@@ -0,0 +1,867 @@
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::instruction::{decode, Instruction, VariableRegister};
+use crate::state::{self, Reader, StateError, Writer};
+use crate::{
+    Chip8Error, Keypad, Quirks, GRID_HEIGHT, GRID_WIDTH, HIRES_HEIGHT, HIRES_WIDTH, PROGRAM_START,
+};
+
+const MEMORY_SIZE: usize = 4096;
+const EXTENDED_MEMORY_SIZE: usize = 0x10000;
+const STACK_SIZE: usize = 16;
+
+const FONTS: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// 8x10 SUPER-CHIP digits for `FX30`, stored right after [`FONTS`]. A through F are the XO-CHIP
+/// extension used by Octo.
+const BIG_FONTS_START: usize = 0x50;
+const BIG_FONTS: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// A headless CHIP-8 machine.
+///
+/// The machine knows nothing about windows, input devices or wall-clock time: clients drive it by
+/// calling [`Chip8::step`] at their chosen instruction rate, [`Chip8::tick_timers`] at 60 Hz, and
+/// feeding key state through [`Chip8::set_key`].
+#[derive(Debug, Clone)]
+pub struct Chip8 {
+    quirks: Quirks,
+    memory: Vec<u8>,
+    /// One byte per pixel holding a bit for each XO-CHIP plane the pixel is lit in.
+    display: Vec<u8>,
+    /// Bitmask of the planes selected by `FN01` that drawing, clearing and scrolling affect.
+    planes: u8,
+    /// Whether the SUPER-CHIP 128x64 high resolution mode is active.
+    hires: bool,
+    program_counter: u16,
+    stack: Vec<u16>,
+    variable_registers: HashMap<VariableRegister, u8>,
+    index_register: u16,
+    keypad: Keypad,
+    /// The key `FX0A` saw pressed and is waiting to be released.
+    key_wait: Option<u8>,
+    /// Bitmask of the keys read by `EX9E`, `EXA1` and `FX0A` since the last
+    /// [`Chip8::take_polled_keys`]. Not part of save states.
+    polled_keys: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    /// XO-CHIP 1-bit, 128 sample audio pattern loaded by `F002`.
+    audio_pattern: [u8; 16],
+    /// XO-CHIP audio pitch register set by `FX3A`.
+    pitch: u8,
+    /// SUPER-CHIP "RPL user flags" saved and restored by `FX75` and `FX85`.
+    flag_registers: [u8; 16],
+    /// Set once `00FD` has been executed.
+    halted: bool,
+    /// Whether a vertical blank has happened since the last `DXYN`, for [`Quirks::display_wait`].
+    vblank: bool,
+    /// The seed `rng` was created from, so that a run can be reproduced.
+    seed: u64,
+    /// Source of `CXNN` random numbers. The same generator as `rand`'s `StdRng`, but one whose
+    /// position in the sequence can be saved and restored.
+    rng: ChaCha12Rng,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8 {
+    /// Creates a machine with the built-in font loaded, no program and the default quirks.
+    pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    /// Creates a machine with the built-in font loaded and no program, following `quirks`. Random
+    /// numbers come from a randomly chosen seed.
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let seed = rand::random();
+        let mut memory = vec![0u8; memory_size(quirks)];
+        memory[0x0..FONTS.len()].copy_from_slice(&FONTS);
+        memory[BIG_FONTS_START..(BIG_FONTS_START + BIG_FONTS.len())].copy_from_slice(&BIG_FONTS);
+
+        let mut variable_registers = HashMap::with_capacity(16);
+        for reg in 0x0..=0xF {
+            let reg = VariableRegister::try_from(reg).expect("register index is in range");
+            variable_registers.insert(reg, 0u8);
+        }
+
+        Self {
+            quirks,
+            memory,
+            display: vec![0; GRID_WIDTH * GRID_HEIGHT],
+            planes: 0b01,
+            hires: false,
+            program_counter: PROGRAM_START,
+            stack: Vec::new(),
+            variable_registers,
+            index_register: 0,
+            keypad: Keypad::default(),
+            key_wait: None,
+            polled_keys: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            audio_pattern: [0; 16],
+            pitch: 64,
+            flag_registers: [0; 16],
+            halted: false,
+            vblank: false,
+            seed,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Copies `rom` into memory at `0x200`, where CHIP-8 programs start executing.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let start = PROGRAM_START as usize;
+        let max = self.memory.len() - start;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                len: rom.len(),
+                max,
+            });
+        }
+        self.memory[start..(start + rom.len())].copy_from_slice(rom);
+        Ok(())
+    }
+
+    /// Runs a single fetch-decode-execute cycle and returns the executed instruction.
+    ///
+    /// On error the program counter is left pointing at the faulting instruction.
+    pub fn step(&mut self) -> Result<Instruction, Chip8Error> {
+        let addr = self.program_counter;
+        let result = self.fetch().and_then(|ins| {
+            let ins = decode(addr, ins)?;
+            self.execute(addr, ins)?;
+            Ok(ins)
+        });
+        if result.is_err() {
+            self.program_counter = addr;
+        }
+        result
+    }
+
+    /// Decrements the delay and sound timers. Should be called at 60 Hz, independently of
+    /// [`Chip8::step`].
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.vblank = true;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.memory.resize(memory_size(quirks), 0);
+    }
+
+    /// The display as [`Chip8::width`] by [`Chip8::height`] pixels in row-major order.
+    ///
+    /// Each pixel is a palette index from 0 to 3: bit 0 is set if the pixel is lit in the first
+    /// plane and bit 1 if it is lit in the second XO-CHIP plane.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.display
+    }
+
+    /// The width of the display in the current resolution mode.
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            GRID_WIDTH
+        }
+    }
+
+    /// The height of the display in the current resolution mode.
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            GRID_HEIGHT
+        }
+    }
+
+    /// Whether the program has exited through `00FD`. Further calls to [`Chip8::step`] keep
+    /// executing the `00FD`.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Sets whether hex key `key` (`0x0` through `0xF`) is held down.
+    pub fn set_key(&mut self, key: u8, is_pressed: bool) {
+        self.keypad.set_held(key, is_pressed);
+    }
+
+    pub fn keypad(&self) -> Keypad {
+        self.keypad
+    }
+
+    pub fn set_keypad(&mut self, keypad: Keypad) {
+        self.keypad = keypad;
+    }
+
+    /// The keys the program has read since the last call, as a bitmask with bit `n` for key `n`.
+    /// `FX0A` reads every key until one is pressed, and then only that one until it's released.
+    pub fn take_polled_keys(&mut self) -> u16 {
+        std::mem::take(&mut self.polled_keys)
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, addr: u16) {
+        self.program_counter = addr;
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn set_index_register(&mut self, addr: u16) {
+        self.index_register = addr;
+    }
+
+    /// The values of `V0` through `VF`, in order.
+    pub fn registers(&self) -> [u8; 16] {
+        let mut registers = [0u8; 16];
+        for (reg, val) in &self.variable_registers {
+            registers[*reg as usize] = *val;
+        }
+        registers
+    }
+
+    /// Sets `V0` through `VF`, in order.
+    pub fn set_registers(&mut self, registers: [u8; 16]) {
+        for (reg, val) in self.variable_registers.iter_mut() {
+            *val = registers[*reg as usize];
+        }
+    }
+
+    /// Return addresses, with the most recent call last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Replaces the return addresses, failing if there are more than fit on the stack.
+    pub fn set_stack(&mut self, stack: &[u16]) -> Result<(), Chip8Error> {
+        if stack.len() > STACK_SIZE {
+            return Err(Chip8Error::StackOverflow {
+                addr: self.program_counter,
+            });
+        }
+        self.stack = stack.to_vec();
+        Ok(())
+    }
+
+    /// All of memory, with the program at `0x200`.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Moves memory out of the machine, leaving it empty until [`Chip8::set_memory`].
+    pub(crate) fn take_memory(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.memory)
+    }
+
+    pub(crate) fn set_memory(&mut self, memory: Vec<u8>) {
+        self.memory = memory;
+    }
+
+    /// The bitmask of XO-CHIP planes selected by `FN01`, which `DXYN` draws a sprite to each of.
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, val: u8) {
+        self.delay_timer = val;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, val: u8) {
+        self.sound_timer = val;
+    }
+
+    /// The XO-CHIP audio pattern, played back one bit per sample, most significant bit first.
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// The sample rate the audio pattern is played back at, derived from the pitch register.
+    pub fn audio_sample_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// The seed random numbers are drawn from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the random number sequence from `seed`, so that a run with the same input can be
+    /// reproduced exactly.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    /// Writes everything but the key state, which belongs to the host, for a save state.
+    pub(crate) fn write_state(&self, w: &mut Writer) {
+        state::write_quirks(w, self.quirks);
+        w.bytes(&self.memory);
+        w.bool(self.hires);
+        w.bytes(&self.display);
+        w.u8(self.planes);
+        w.u16(self.program_counter);
+        let stack = self.stack.iter().flat_map(|addr| addr.to_le_bytes());
+        w.bytes(&stack.collect::<Vec<_>>());
+        w.bytes(&self.registers());
+        w.u16(self.index_register);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+        w.bytes(&self.flag_registers);
+        w.bool(self.halted);
+        w.bool(self.vblank);
+        w.u64(self.seed);
+        w.u64(self.rng.get_word_pos() as u64);
+        w.bool(self.key_wait.is_some());
+        w.u8(self.key_wait.unwrap_or(0));
+    }
+
+    /// Reads a machine written by [`Chip8::write_state`], with no keys held.
+    pub(crate) fn read_state(r: &mut Reader) -> Result<Self, StateError> {
+        let mut chip8 = Chip8::with_quirks(state::read_quirks(r)?);
+
+        let memory = r.bytes()?;
+        if memory.len() != chip8.memory.len() {
+            return Err(StateError::Invalid("memory size"));
+        }
+        chip8.memory.copy_from_slice(memory);
+        chip8.hires = r.bool()?;
+        let display = r.bytes()?;
+        if display.len() != chip8.width() * chip8.height() {
+            return Err(StateError::Invalid("display size"));
+        }
+        chip8.display = display.to_vec();
+        chip8.planes = r.u8()?;
+        chip8.program_counter = r.u16()?;
+        let stack = r.bytes()?;
+        if stack.len() % 2 != 0 || stack.len() / 2 > STACK_SIZE {
+            return Err(StateError::Invalid("stack"));
+        }
+        chip8.stack = stack
+            .chunks(2)
+            .map(|addr| u16::from_le_bytes([addr[0], addr[1]]))
+            .collect();
+        let registers = r
+            .bytes()?
+            .try_into()
+            .map_err(|_| StateError::Invalid("registers"))?;
+        chip8.set_registers(registers);
+        chip8.index_register = r.u16()?;
+        chip8.delay_timer = r.u8()?;
+        chip8.sound_timer = r.u8()?;
+        chip8.audio_pattern = r
+            .bytes()?
+            .try_into()
+            .map_err(|_| StateError::Invalid("audio pattern"))?;
+        chip8.pitch = r.u8()?;
+        chip8.flag_registers = r
+            .bytes()?
+            .try_into()
+            .map_err(|_| StateError::Invalid("flag registers"))?;
+        chip8.halted = r.bool()?;
+        chip8.vblank = r.bool()?;
+        chip8.reseed(r.u64()?);
+        chip8.rng.set_word_pos(r.u64()? as u128);
+        let waiting = r.bool()?;
+        let key = r.u8()?;
+        if key > 0xF {
+            return Err(StateError::Invalid("key"));
+        }
+        chip8.key_wait = waiting.then_some(key);
+        Ok(chip8)
+    }
+
+    fn random(&mut self) -> u8 {
+        self.rng.gen()
+    }
+
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let ins = u16::from_be_bytes([
+            self.read_memory(self.program_counter as usize)?,
+            self.read_memory(self.program_counter as usize + 1)?,
+        ]);
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(ins)
+    }
+
+    fn read_memory(&self, addr: usize) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(addr)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr })
+    }
+
+    fn write_memory(&mut self, addr: usize, val: u8) -> Result<(), Chip8Error> {
+        let byte = self
+            .memory
+            .get_mut(addr)
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr })?;
+        *byte = val;
+        Ok(())
+    }
+
+    fn register(&self, reg: VariableRegister) -> u8 {
+        *self.variable_registers.get(&reg).unwrap()
+    }
+
+    fn set_register(&mut self, reg: VariableRegister, val: u8) {
+        self.variable_registers.entry(reg).and_modify(|v| *v = val);
+    }
+
+    /// The value shifted by `8XY6` and `8XYE`, see [`Quirks::shift_ignores_vy`].
+    fn shift_source(&self, x_reg: VariableRegister, y_reg: VariableRegister) -> u8 {
+        if self.quirks.shift_ignores_vy {
+            self.register(x_reg)
+        } else {
+            self.register(y_reg)
+        }
+    }
+
+    /// Moves I past the registers `FX55` and `FX65` accessed, see
+    /// [`Quirks::load_store_increments_i`].
+    fn increment_index_after_load_store(&mut self, x: u8) {
+        if self.quirks.load_store_increments_i {
+            let len = x as u16 + !self.quirks.load_store_increments_i_by_x as u16;
+            self.index_register = self.index_register.wrapping_add(len);
+        }
+    }
+
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.set_register(VariableRegister::VF, 0);
+        }
+    }
+
+    /// Switches between the 64x32 and 128x64 display, clearing it like Octo does.
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display = vec![0; self.width() * self.height()];
+    }
+
+    /// Skips the next instruction, which is four bytes long if it is XO-CHIP's `F000 NNNN`.
+    fn skip(&mut self) -> Result<(), Chip8Error> {
+        let next = u16::from_be_bytes([
+            self.read_memory(self.program_counter as usize)?,
+            self.read_memory(self.program_counter as usize + 1)?,
+        ]);
+        let len = if next == 0xF000 { 4 } else { 2 };
+        self.program_counter = self.program_counter.wrapping_add(len);
+        Ok(())
+    }
+
+    /// Moves the selected planes by `dx` pixels right and `dy` pixels down.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let prev = self.display.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let src = if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    prev[(sy * width + sx) as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.display[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | (src & self.planes);
+            }
+        }
+    }
+
+    /// Executes `ins`, fetched from `addr`. The program counter has already moved past it.
+    fn execute(&mut self, addr: u16, ins: Instruction) -> Result<(), Chip8Error> {
+        match ins {
+            Instruction::MachineCall(target) => {
+                return Err(Chip8Error::MachineCode { addr, target });
+            }
+            Instruction::ClearScreen => {
+                for pixel in &mut self.display {
+                    *pixel &= !self.planes;
+                }
+            }
+            Instruction::Jump(loc) => {
+                self.program_counter = loc;
+            }
+            Instruction::JumpWithOffset(offset) => {
+                // The COSMAC VIP interpreter jumps to address `NNN` plus value in register V0,
+                // while CHIP-48 and SUPER-CHIP read the instruction as `BXNN` and add VX.
+                let reg = if self.quirks.jump_uses_vx {
+                    VariableRegister::try_from((offset >> 8 & 0x0F) as u8)?
+                } else {
+                    VariableRegister::V0
+                };
+                let loc = self.register(reg) as u16;
+                self.program_counter = loc.wrapping_add(offset);
+            }
+            Instruction::Pop => {
+                let Some(loc) = self.stack.pop() else {
+                    return Err(Chip8Error::StackUnderflow { addr });
+                };
+                self.program_counter = loc;
+            }
+            Instruction::Call(loc) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow { addr });
+                }
+                self.stack.push(self.program_counter);
+                self.program_counter = loc;
+            }
+            Instruction::SetRegImm(reg, imm) => {
+                self.set_register(reg, imm);
+            }
+            Instruction::AddRegImm(reg, imm) => {
+                let val = self.register(reg).wrapping_add(imm);
+                self.set_register(reg, val);
+            }
+            Instruction::SetIdxRegImm(imm) => {
+                self.index_register = imm;
+            }
+            Instruction::Display { x, y, n } => {
+                if self.quirks.display_wait && !self.vblank {
+                    // Retry until the next vertical blank, like the COSMAC VIP did.
+                    self.program_counter = addr;
+                    return Ok(());
+                }
+                self.vblank = false;
+
+                let (width, height) = (self.width(), self.height());
+                let x = self.register(x) as usize % width;
+                let y = self.register(y) as usize % height;
+                // SUPER-CHIP draws a 16x16 sprite, stored as two bytes per row, for `DXY0`.
+                let (rows, cols) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+                self.set_register(VariableRegister::VF, 0);
+                // With both XO-CHIP planes selected, the sprite for the second plane follows the
+                // one for the first in memory.
+                let mut sprite_addr = self.index_register as usize;
+                for plane in [0b01, 0b10] {
+                    if self.planes & plane == 0 {
+                        continue;
+                    }
+
+                    for j in 0..rows {
+                        if y + j >= height && !self.quirks.sprite_wrap {
+                            break;
+                        }
+                        let py = (y + j) % height;
+
+                        for i in 0..cols {
+                            if x + i >= width && !self.quirks.sprite_wrap {
+                                break;
+                            }
+                            let px = (x + i) % width;
+
+                            let sprite_byte =
+                                self.read_memory(sprite_addr + j * cols / 8 + i / 8)?;
+                            let sprite_pixel = (sprite_byte >> (7 - i % 8)) & 0x1;
+                            let pixel = py * width + px;
+                            if sprite_pixel == 1 {
+                                if self.display[pixel] & plane != 0 {
+                                    self.set_register(VariableRegister::VF, 1);
+                                }
+                                self.display[pixel] ^= plane;
+                            }
+                        }
+                    }
+                    sprite_addr += rows * cols / 8;
+                }
+            }
+            Instruction::SkipIfEqImm(reg, imm) => {
+                if self.register(reg) == imm {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipIfNeqImm(reg, imm) => {
+                if self.register(reg) != imm {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipIfEqReg(x_reg, y_reg) => {
+                if self.register(x_reg) == self.register(y_reg) {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipIfNeqReg(x_reg, y_reg) => {
+                if self.register(x_reg) != self.register(y_reg) {
+                    self.skip()?;
+                }
+            }
+            Instruction::Set(x_reg, y_reg) => {
+                let val = self.register(y_reg);
+                self.set_register(x_reg, val);
+            }
+            Instruction::BinOr(x_reg, y_reg) => {
+                let val = self.register(x_reg) | self.register(y_reg);
+                self.set_register(x_reg, val);
+                self.reset_flag_after_logic();
+            }
+            Instruction::BinAnd(x_reg, y_reg) => {
+                let val = self.register(x_reg) & self.register(y_reg);
+                self.set_register(x_reg, val);
+                self.reset_flag_after_logic();
+            }
+            Instruction::Xor(x_reg, y_reg) => {
+                let val = self.register(x_reg) ^ self.register(y_reg);
+                self.set_register(x_reg, val);
+                self.reset_flag_after_logic();
+            }
+            Instruction::Add(x_reg, y_reg) => {
+                // The flag is written last, so that it wins when VF is the destination.
+                let (val, carry) = self.register(x_reg).overflowing_add(self.register(y_reg));
+                self.set_register(x_reg, val);
+                self.set_register(VariableRegister::VF, carry as u8);
+            }
+            Instruction::SubtractLR(x_reg, y_reg) => {
+                let v1 = self.register(x_reg);
+                let v2 = self.register(y_reg);
+                self.set_register(x_reg, v1.wrapping_sub(v2));
+                self.set_register(VariableRegister::VF, (v1 >= v2) as u8);
+            }
+            Instruction::SubtractRL(x_reg, y_reg) => {
+                let v1 = self.register(y_reg);
+                let v2 = self.register(x_reg);
+                self.set_register(x_reg, v1.wrapping_sub(v2));
+                self.set_register(VariableRegister::VF, (v1 >= v2) as u8);
+            }
+            Instruction::ShiftLeft(x_reg, y_reg) => {
+                let val = self.shift_source(x_reg, y_reg);
+                self.set_register(x_reg, val << 1);
+                self.set_register(VariableRegister::VF, val >> 7);
+            }
+            Instruction::ShiftRight(x_reg, y_reg) => {
+                let val = self.shift_source(x_reg, y_reg);
+                self.set_register(x_reg, val >> 1);
+                self.set_register(VariableRegister::VF, val & 0x01);
+            }
+            Instruction::Random(reg, imm) => {
+                let r = self.random();
+                self.set_register(reg, r & imm);
+            }
+            Instruction::SkipIfKeyPressed(reg) => {
+                let key = self.register(reg);
+                self.polled_keys |= 1 << (key & 0x0F);
+                if self.keypad.is_held(key) {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipIfKeyNotPressed(reg) => {
+                let key = self.register(reg);
+                self.polled_keys |= 1 << (key & 0x0F);
+                if !self.keypad.is_held(key) {
+                    self.skip()?;
+                }
+            }
+            Instruction::GetDelayTimer(reg) => {
+                self.set_register(reg, self.delay_timer);
+            }
+            Instruction::SetDelayTimer(reg) => {
+                self.delay_timer = self.register(reg);
+            }
+            Instruction::SetSoundTimer(reg) => {
+                self.sound_timer = self.register(reg);
+            }
+            // Like the VIP, wait for a key to be pressed and then released again, so that the key
+            // isn't seen by the next `EX9E` or `FX0A` as well.
+            Instruction::GetKey(reg) => match self.key_wait {
+                Some(key) if !self.keypad.is_held(key) => {
+                    self.polled_keys |= 1 << key;
+                    self.key_wait = None;
+                    self.set_register(reg, key);
+                }
+                _ => {
+                    self.polled_keys |= self.key_wait.map_or(0xFFFF, |key| 1 << key);
+                    if self.key_wait.is_none() {
+                        self.key_wait = self.keypad.first_held();
+                    }
+                    self.program_counter = addr;
+                }
+            },
+            Instruction::Font(reg) => {
+                let digit = (self.register(reg) & 0x0F) as usize;
+                self.index_register = (digit * 5) as u16;
+            }
+            Instruction::BinDecConversion(reg) => {
+                let val = self.register(reg);
+                let d3 = val % 10;
+                let d2 = val / 10 % 10;
+                let d1 = val / 100;
+
+                let addr = self.index_register as usize;
+                self.write_memory(addr, d1)?;
+                self.write_memory(addr + 1, d2)?;
+                self.write_memory(addr + 2, d3)?;
+            }
+            Instruction::Store(x) => {
+                let addr = self.index_register as usize;
+                for i in 0..=x {
+                    let val = self.register(VariableRegister::try_from(i)?);
+                    self.write_memory(addr + i as usize, val)?;
+                }
+                self.increment_index_after_load_store(x);
+            }
+            Instruction::Load(x) => {
+                let addr = self.index_register as usize;
+                for i in 0..=x {
+                    let val = self.read_memory(addr + i as usize)?;
+                    self.set_register(VariableRegister::try_from(i)?, val);
+                }
+                self.increment_index_after_load_store(x);
+            }
+            Instruction::AddToIndex(reg) => {
+                // The Amiga interpreter sets VF to 1 if I overflows from 0x0FFF to above 0x1000,
+                // which at least Spacefight 2091! relies on.
+                let offset = self.register(reg) as u16;
+                if self.quirks.index_overflow_sets_vf {
+                    let overflow = self.index_register as u32 + offset as u32 > 0x0FFF;
+                    self.set_register(VariableRegister::VF, overflow as u8);
+                }
+                self.index_register = self.index_register.wrapping_add(offset);
+            }
+            Instruction::ScrollDown(n) => self.scroll(0, n as isize),
+            Instruction::ScrollUp(n) => self.scroll(0, -(n as isize)),
+            Instruction::ScrollRight => self.scroll(4, 0),
+            Instruction::ScrollLeft => self.scroll(-4, 0),
+            Instruction::Exit => {
+                // Stay on `00FD` so the machine doesn't run off into whatever follows it.
+                self.program_counter = addr;
+                self.halted = true;
+            }
+            Instruction::LowRes => self.set_hires(false),
+            Instruction::HighRes => self.set_hires(true),
+            Instruction::BigFont(reg) => {
+                let digit = (self.register(reg) & 0x0F) as usize;
+                self.index_register = (BIG_FONTS_START + digit * 10) as u16;
+            }
+            Instruction::SaveFlags(x) => {
+                for i in 0..=x {
+                    self.flag_registers[i as usize] = self.register(VariableRegister::try_from(i)?);
+                }
+            }
+            Instruction::LoadFlags(x) => {
+                for i in 0..=x {
+                    let val = self.flag_registers[i as usize];
+                    self.set_register(VariableRegister::try_from(i)?, val);
+                }
+            }
+            Instruction::SaveRange(x_reg, y_reg) => {
+                let addr = self.index_register as usize;
+                for (i, reg) in register_range(x_reg, y_reg)?.into_iter().enumerate() {
+                    self.write_memory(addr + i, self.register(reg))?;
+                }
+            }
+            Instruction::LoadRange(x_reg, y_reg) => {
+                let addr = self.index_register as usize;
+                for (i, reg) in register_range(x_reg, y_reg)?.into_iter().enumerate() {
+                    let val = self.read_memory(addr + i)?;
+                    self.set_register(reg, val);
+                }
+            }
+            Instruction::SetIdxRegLong => {
+                let addr = u16::from_be_bytes([
+                    self.read_memory(self.program_counter as usize)?,
+                    self.read_memory(self.program_counter as usize + 1)?,
+                ]);
+                self.index_register = addr;
+                self.program_counter = self.program_counter.wrapping_add(2);
+            }
+            Instruction::SelectPlanes(planes) => {
+                self.planes = planes;
+            }
+            Instruction::LoadAudioPattern => {
+                let addr = self.index_register as usize;
+                for i in 0..self.audio_pattern.len() {
+                    self.audio_pattern[i] = self.read_memory(addr + i)?;
+                }
+            }
+            Instruction::SetPitch(reg) => {
+                self.pitch = self.register(reg);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn memory_size(quirks: Quirks) -> usize {
+    if quirks.extended_memory {
+        EXTENDED_MEMORY_SIZE
+    } else {
+        MEMORY_SIZE
+    }
+}
+
+/// The registers from `x_reg` to `y_reg` inclusive, in descending order if `x_reg` is greater.
+fn register_range(
+    x_reg: VariableRegister,
+    y_reg: VariableRegister,
+) -> Result<Vec<VariableRegister>, Chip8Error> {
+    let (x, y) = (x_reg as u8, y_reg as u8);
+    let range = if x <= y {
+        (x..=y).collect::<Vec<_>>()
+    } else {
+        (y..=x).rev().collect::<Vec<_>>()
+    };
+    range.into_iter().map(VariableRegister::try_from).collect()
+}
@@ -0,0 +1,319 @@
+//! The in-window debugger enabled with `--debug`: side panels with the machine state and hotkeys
+//! for pausing, stepping and breakpoints.
+
+use chip_8_interpreter::{disasm, Chip8, Instruction};
+use raylib::prelude::*;
+
+use std::collections::BTreeSet;
+
+/// Window size in debug mode, which fits the display at half scale next to the panels.
+pub const WINDOW_WIDTH: i32 = 1040;
+pub const WINDOW_HEIGHT: i32 = 640;
+/// Where the display is drawn in debug mode.
+pub const DISPLAY_BOUNDS: Rectangle = Rectangle {
+    x: 0.0,
+    y: 0.0,
+    width: 640.0,
+    height: 320.0,
+};
+
+const FONT_SIZE: i32 = 20;
+const LINE_HEIGHT: i32 = FONT_SIZE + 2;
+const PANEL_X: i32 = 656;
+const DISASM_Y: i32 = 336;
+const DISASM_ROWS: usize = 12;
+const MEMORY_Y: i32 = 240;
+const MEMORY_ROWS: usize = 17;
+const MEMORY_ROW_LEN: u16 = 8;
+const STACK_ROW_LEN: usize = 7;
+
+const PAUSE_KEY: KeyboardKey = KeyboardKey::KEY_P;
+const STEP_KEY: KeyboardKey = KeyboardKey::KEY_N;
+const STEP_OVER_KEY: KeyboardKey = KeyboardKey::KEY_O;
+const RUN_TO_CURSOR_KEY: KeyboardKey = KeyboardKey::KEY_G;
+const BREAKPOINT_KEY: KeyboardKey = KeyboardKey::KEY_B;
+
+/// Runs until the program counter reaches `addr` with at most `depth` return addresses on the
+/// stack, so that stepping over a recursive call stops in the same invocation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct RunTo {
+    addr: u16,
+    depth: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    paused: bool,
+    /// Set for a single instruction while paused.
+    step_requested: bool,
+    /// Set when resuming so the breakpoint at the current instruction doesn't trigger right away.
+    resuming: bool,
+    breakpoints: BTreeSet<u16>,
+    run_to: Option<RunTo>,
+    /// The instruction selected in the disassembly panel, for breakpoints and running to it.
+    cursor: u16,
+    /// The first address of the memory panel, or `None` to follow the index register.
+    memory_start: Option<u16>,
+}
+
+impl Debugger {
+    /// A debugger that starts out paused at `chip8`'s first instruction.
+    pub fn new(chip8: &Chip8) -> Self {
+        Self {
+            paused: true,
+            step_requested: false,
+            resuming: false,
+            breakpoints: BTreeSet::new(),
+            run_to: None,
+            cursor: chip8.program_counter(),
+            memory_start: None,
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn handle_keys(&mut self, rl: &RaylibHandle, chip8: &Chip8) {
+        let pc = chip8.program_counter();
+        if rl.is_key_pressed(PAUSE_KEY) {
+            if self.paused {
+                self.resume();
+            } else {
+                self.pause(pc);
+            }
+        }
+        if rl.is_key_pressed(STEP_KEY) && self.paused {
+            self.step_requested = true;
+        }
+        if rl.is_key_pressed(STEP_OVER_KEY) && self.paused {
+            match current_instruction(chip8) {
+                Some(Instruction::Call(_)) => {
+                    self.run_to = Some(RunTo {
+                        addr: pc.wrapping_add(2),
+                        depth: chip8.stack().len(),
+                    });
+                    self.resume();
+                }
+                _ => self.step_requested = true,
+            }
+        }
+        if rl.is_key_pressed(RUN_TO_CURSOR_KEY) {
+            self.run_to = Some(RunTo {
+                addr: self.cursor,
+                depth: usize::MAX,
+            });
+            self.resume();
+        }
+        if rl.is_key_pressed(BREAKPOINT_KEY) && !self.breakpoints.remove(&self.cursor) {
+            self.breakpoints.insert(self.cursor);
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_UP) {
+            self.cursor = self.cursor.wrapping_sub(2);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_DOWN) {
+            self.cursor = self.cursor.wrapping_add(2);
+        }
+        let page = MEMORY_ROW_LEN * MEMORY_ROWS as u16;
+        let memory_start = self.memory_start(chip8);
+        if rl.is_key_pressed(KeyboardKey::KEY_PAGE_UP) {
+            self.memory_start = Some(memory_start.saturating_sub(page));
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_PAGE_DOWN) {
+            self.memory_start = Some(memory_start.saturating_add(page));
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_HOME) {
+            self.memory_start = None;
+        }
+    }
+
+    /// Whether to execute the next instruction now. `due` is whether it is time to according to
+    /// the instruction rate, which single steps ignore.
+    pub fn should_step(&mut self, chip8: &Chip8, due: bool) -> bool {
+        let pc = chip8.program_counter();
+        if self.paused {
+            return std::mem::take(&mut self.step_requested);
+        }
+        if !due {
+            return false;
+        }
+
+        let resuming = std::mem::take(&mut self.resuming);
+        let reached = self
+            .run_to
+            .is_some_and(|run_to| pc == run_to.addr && chip8.stack().len() <= run_to.depth);
+        if reached || (!resuming && self.breakpoints.contains(&pc)) {
+            self.pause(pc);
+            return false;
+        }
+        true
+    }
+
+    /// Keeps the cursor on the next instruction while stepping.
+    pub fn stepped(&mut self, chip8: &Chip8) {
+        if self.paused {
+            self.cursor = chip8.program_counter();
+        }
+    }
+
+    /// Stops execution, for instance on an error.
+    pub fn pause(&mut self, pc: u16) {
+        self.paused = true;
+        self.run_to = None;
+        self.cursor = pc;
+    }
+
+    fn resume(&mut self) {
+        self.paused = false;
+        self.resuming = true;
+    }
+
+    fn memory_start(&self, chip8: &Chip8) -> u16 {
+        self.memory_start
+            .unwrap_or(chip8.index_register() & !(MEMORY_ROW_LEN - 1))
+    }
+
+    pub fn draw(&self, chip8: &Chip8, d: &mut RaylibDrawHandle) {
+        d.draw_rectangle(
+            PANEL_X - 8,
+            0,
+            WINDOW_WIDTH - PANEL_X + 8,
+            WINDOW_HEIGHT,
+            Color::new(0x20, 0x20, 0x20, 0xFF),
+        );
+        d.draw_rectangle(
+            0,
+            DISPLAY_BOUNDS.height as i32,
+            PANEL_X - 8,
+            WINDOW_HEIGHT - DISPLAY_BOUNDS.height as i32,
+            Color::new(0x18, 0x18, 0x18, 0xFF),
+        );
+        self.draw_registers(chip8, d);
+        self.draw_memory(chip8, d);
+        self.draw_disassembly(chip8, d);
+    }
+
+    fn draw_registers(&self, chip8: &Chip8, d: &mut RaylibDrawHandle) {
+        let state = if self.paused { "paused" } else { "running" };
+        let mut lines = vec![
+            format!(
+                "PC {:03X}  I {:03X}  {}",
+                chip8.program_counter(),
+                chip8.index_register(),
+                state
+            ),
+            format!(
+                "DT {:02X}  ST {:02X}",
+                chip8.delay_timer(),
+                chip8.sound_timer()
+            ),
+        ];
+        for (row, regs) in chip8.registers().chunks(4).enumerate() {
+            let regs = regs
+                .iter()
+                .enumerate()
+                .map(|(i, val)| format!("V{:X} {:02X}", row * 4 + i, val))
+                .collect::<Vec<_>>();
+            lines.push(regs.join("  "));
+        }
+        lines.push(format!("SP {:X}", chip8.stack().len()));
+        // Up to 16 return addresses, most recent first.
+        for addrs in chip8
+            .stack()
+            .iter()
+            .rev()
+            .collect::<Vec<_>>()
+            .chunks(STACK_ROW_LEN)
+        {
+            let addrs = addrs
+                .iter()
+                .map(|addr| format!("{:03X}", addr))
+                .collect::<Vec<_>>();
+            lines.push(addrs.join(" "));
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            d.draw_text(
+                line,
+                PANEL_X,
+                8 + LINE_HEIGHT * i as i32,
+                FONT_SIZE,
+                Color::WHITE,
+            );
+        }
+    }
+
+    fn draw_memory(&self, chip8: &Chip8, d: &mut RaylibDrawHandle) {
+        let memory = chip8.memory();
+        let start = self.memory_start(chip8);
+        for row in 0..MEMORY_ROWS as u16 {
+            let addr = start.wrapping_add(row * MEMORY_ROW_LEN) as usize;
+            let Some(bytes) = memory.get(addr..(addr + MEMORY_ROW_LEN as usize).min(memory.len()))
+            else {
+                break;
+            };
+            let bytes = bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>();
+            d.draw_text(
+                &format!("{:04X}: {}", addr, bytes.join(" ")),
+                PANEL_X,
+                MEMORY_Y + LINE_HEIGHT * row as i32,
+                FONT_SIZE,
+                Color::LIGHTGRAY,
+            );
+        }
+    }
+
+    /// The instructions around the cursor, with the current instruction, the cursor and
+    /// breakpoints highlighted.
+    fn draw_disassembly(&self, chip8: &Chip8, d: &mut RaylibDrawHandle) {
+        d.draw_text(
+            "P pause  N step  O step over  G run to cursor  B breakpoint",
+            8,
+            DISASM_Y,
+            FONT_SIZE,
+            Color::GRAY,
+        );
+
+        let pc = chip8.program_counter();
+        let mut addr = self.cursor.saturating_sub(2 * (DISASM_ROWS as u16 / 2));
+        for row in 0..DISASM_ROWS {
+            let Some((text, len)) = disasm::mnemonic_at(chip8.memory(), addr, &chip8.quirks())
+            else {
+                break;
+            };
+            let y = DISASM_Y + LINE_HEIGHT * (row as i32 + 1) + 4;
+            if addr == self.cursor {
+                d.draw_rectangle(0, y - 1, PANEL_X - 8, LINE_HEIGHT, Color::DARKBLUE);
+            }
+            let marker = if self.breakpoints.contains(&addr) {
+                "*"
+            } else {
+                " "
+            };
+            let color = if addr == pc {
+                Color::YELLOW
+            } else {
+                Color::WHITE
+            };
+            d.draw_text(
+                &format!("{} {:04X}  {}", marker, addr, text),
+                8,
+                y,
+                FONT_SIZE,
+                color,
+            );
+            addr = addr.wrapping_add(len as u16);
+        }
+    }
+}
+
+/// The instruction at the program counter, if it decodes.
+fn current_instruction(chip8: &Chip8) -> Option<Instruction> {
+    let pc = chip8.program_counter() as usize;
+    let bytes = chip8.memory().get(pc..pc + 2)?;
+    chip_8_interpreter::decode(pc as u16, u16::from_be_bytes([bytes[0], bytes[1]])).ok()
+}
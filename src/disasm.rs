@@ -0,0 +1,261 @@
+//! Disassembly of CHIP-8 programs, either as a plain listing or as Octo source.
+//!
+//! The program is swept linearly from [`PROGRAM_START`] one word at a time. Words that don't
+//! decode to an instruction, or that wouldn't encode back to the same word, are shown as data, so
+//! sprites and other data mixed in with the code never stop the disassembly.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::{decode, Instruction, Quirks, VariableRegister, PROGRAM_START};
+
+/// One line of the disassembly.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Item {
+    /// An instruction, with the address that follows `F000` for
+    /// [`SetIdxRegLong`](Instruction::SetIdxRegLong).
+    Instruction(Instruction, Option<u16>),
+    Data,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Line<'a> {
+    addr: u16,
+    bytes: &'a [u8],
+    item: Item,
+}
+
+/// How an address is referenced, which decides the name of its label.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Jump,
+    Call,
+}
+
+fn sweep(rom: &[u8]) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let line = decode_line(&rom[offset..], PROGRAM_START.wrapping_add(offset as u16));
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+    lines
+}
+
+/// Decodes the line at the start of `bytes`, which are at `addr`.
+fn decode_line(bytes: &[u8], addr: u16) -> Line<'_> {
+    if bytes.len() < 2 {
+        return Line {
+            addr,
+            bytes,
+            item: Item::Data,
+        };
+    }
+
+    let word = u16::from_be_bytes([bytes[0], bytes[1]]);
+    match decode(addr, word) {
+        Ok(Instruction::SetIdxRegLong) if bytes.len() >= 4 => Line {
+            addr,
+            bytes: &bytes[..4],
+            item: Item::Instruction(
+                Instruction::SetIdxRegLong,
+                Some(u16::from_be_bytes([bytes[2], bytes[3]])),
+            ),
+        },
+        Ok(ins) if ins != Instruction::SetIdxRegLong && ins.encode() == word => Line {
+            addr,
+            bytes: &bytes[..2],
+            item: Item::Instruction(ins, None),
+        },
+        _ => Line {
+            addr,
+            bytes: &bytes[..2],
+            item: Item::Data,
+        },
+    }
+}
+
+/// The mnemonic for `line`. With [`Quirks::jump_uses_vx`], `BXNN` is shown with the register it
+/// actually adds.
+fn mnemonic(line: &Line, quirks: &Quirks) -> String {
+    match line.item {
+        Item::Instruction(Instruction::SetIdxRegLong, Some(addr)) => {
+            format!("LD I, {:#06X}", addr)
+        }
+        Item::Instruction(Instruction::JumpWithOffset(addr), _) if quirks.jump_uses_vx => {
+            format!("JP V{:X}, {:#05X}", addr >> 8, addr)
+        }
+        Item::Instruction(ins, _) => ins.to_string(),
+        Item::Data => {
+            let bytes = line.bytes.iter().map(|b| format!("{:#04X}", b));
+            format!("DB {}", bytes.collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+/// The mnemonic for the instruction at `addr` in `memory` along with its length in bytes, for
+/// disassembling a running program under `quirks`. Returns `None` past the end of memory.
+pub fn mnemonic_at(memory: &[u8], addr: u16, quirks: &Quirks) -> Option<(String, usize)> {
+    let bytes = memory
+        .get(addr as usize..)
+        .filter(|bytes| !bytes.is_empty())?;
+    let line = decode_line(bytes, addr);
+    Some((mnemonic(&line, quirks), line.bytes.len()))
+}
+
+/// Labels for the targets of jumps and calls that start a line. Other targets can't be labelled
+/// without changing the program and are left as numbers.
+fn labels(lines: &[Line]) -> BTreeMap<u16, String> {
+    let mut targets = BTreeMap::new();
+    for line in lines {
+        let (addr, target) = match line.item {
+            Item::Instruction(Instruction::Jump(addr), _)
+            | Item::Instruction(Instruction::JumpWithOffset(addr), _) => (addr, Target::Jump),
+            Item::Instruction(Instruction::Call(addr), _) => (addr, Target::Call),
+            _ => continue,
+        };
+        let entry = targets.entry(addr).or_insert(target);
+        *entry = (*entry).max(target);
+    }
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            let name = match targets.get(&line.addr)? {
+                Target::Jump => format!("label_{:03x}", line.addr),
+                Target::Call => format!("sub_{:03x}", line.addr),
+            };
+            Some((line.addr, name))
+        })
+        .collect()
+}
+
+/// A listing with one line per instruction word giving its address, the raw bytes and the
+/// mnemonic as the platform with `quirks` reads it.
+pub fn listing(rom: &[u8], quirks: &Quirks) -> String {
+    let lines = sweep(rom);
+    let labels = labels(&lines);
+    let mut out = String::new();
+    for line in &lines {
+        if let Some(label) = labels.get(&line.addr) {
+            writeln!(out, "{}:", label).unwrap();
+        }
+        let raw = line
+            .bytes
+            .chunks(2)
+            .map(|word| {
+                word.iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            out,
+            "{:04X}  {:<9}  {}",
+            line.addr,
+            raw,
+            mnemonic(line, quirks)
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Octo source that assembles back to `rom` byte for byte.
+pub fn octo(rom: &[u8]) -> String {
+    let lines = sweep(rom);
+    let labels = labels(&lines);
+    let target = |addr: u16| {
+        labels
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| format!("{:#05x}", addr))
+    };
+
+    let mut out = String::from(": main\n");
+    for line in &lines {
+        if let Some(label) = labels.get(&line.addr) {
+            writeln!(out, "\n: {}", label).unwrap();
+        }
+        let text = match line.item {
+            Item::Instruction(ins, long) => octo_instruction(ins, long, &target),
+            Item::Data => None,
+        };
+        let text = text.unwrap_or_else(|| {
+            let bytes = line.bytes.iter().map(|b| format!("{:#04x}", b));
+            bytes.collect::<Vec<_>>().join(" ")
+        });
+        writeln!(out, "\t{}", text).unwrap();
+    }
+    out
+}
+
+/// The Octo statement for `ins`, or `None` for machine code calls, which Octo has no syntax for.
+fn octo_instruction(
+    ins: Instruction,
+    long: Option<u16>,
+    target: &impl Fn(u16) -> String,
+) -> Option<String> {
+    // Octo writes registers in lowercase and skips as `if ... then` with the inverse condition.
+    let r = |reg: VariableRegister| format!("v{:x}", reg as u8);
+    let text = match ins {
+        Instruction::MachineCall(_) => return None,
+        Instruction::ClearScreen => "clear".to_string(),
+        Instruction::Jump(addr) => format!("jump {}", target(addr)),
+        Instruction::JumpWithOffset(addr) => format!("jump0 {}", target(addr)),
+        // A bare label is a call, a bare number a byte of data.
+        Instruction::Call(addr) => match target(addr) {
+            name if name.starts_with("0x") => format!(":call {}", name),
+            name => name,
+        },
+        Instruction::Pop => "return".to_string(),
+        Instruction::SetRegImm(x, imm) => format!("{} := {:#04x}", r(x), imm),
+        Instruction::AddRegImm(x, imm) => format!("{} += {:#04x}", r(x), imm),
+        Instruction::SetIdxRegImm(addr) => format!("i := {:#05x}", addr),
+        Instruction::Display { x, y, n } => format!("sprite {} {} {}", r(x), r(y), n),
+        Instruction::SkipIfEqImm(x, imm) => format!("if {} != {:#04x} then", r(x), imm),
+        Instruction::SkipIfNeqImm(x, imm) => format!("if {} == {:#04x} then", r(x), imm),
+        Instruction::SkipIfEqReg(x, y) => format!("if {} != {} then", r(x), r(y)),
+        Instruction::SkipIfNeqReg(x, y) => format!("if {} == {} then", r(x), r(y)),
+        Instruction::Set(x, y) => format!("{} := {}", r(x), r(y)),
+        Instruction::BinOr(x, y) => format!("{} |= {}", r(x), r(y)),
+        Instruction::BinAnd(x, y) => format!("{} &= {}", r(x), r(y)),
+        Instruction::Xor(x, y) => format!("{} ^= {}", r(x), r(y)),
+        Instruction::Add(x, y) => format!("{} += {}", r(x), r(y)),
+        Instruction::SubtractLR(x, y) => format!("{} -= {}", r(x), r(y)),
+        Instruction::SubtractRL(x, y) => format!("{} =- {}", r(x), r(y)),
+        Instruction::ShiftLeft(x, y) => format!("{} <<= {}", r(x), r(y)),
+        Instruction::ShiftRight(x, y) => format!("{} >>= {}", r(x), r(y)),
+        Instruction::Random(x, imm) => format!("{} := random {:#04x}", r(x), imm),
+        Instruction::SkipIfKeyPressed(x) => format!("if {} -key then", r(x)),
+        Instruction::SkipIfKeyNotPressed(x) => format!("if {} key then", r(x)),
+        Instruction::GetDelayTimer(x) => format!("{} := delay", r(x)),
+        Instruction::SetDelayTimer(x) => format!("delay := {}", r(x)),
+        Instruction::SetSoundTimer(x) => format!("buzzer := {}", r(x)),
+        Instruction::GetKey(x) => format!("{} := key", r(x)),
+        Instruction::Font(x) => format!("i := hex {}", r(x)),
+        Instruction::BinDecConversion(x) => format!("bcd {}", r(x)),
+        Instruction::Store(x) => format!("save v{:x}", x),
+        Instruction::Load(x) => format!("load v{:x}", x),
+        Instruction::AddToIndex(x) => format!("i += {}", r(x)),
+        Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+        Instruction::ScrollRight => "scroll-right".to_string(),
+        Instruction::ScrollLeft => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::LowRes => "lores".to_string(),
+        Instruction::HighRes => "hires".to_string(),
+        Instruction::BigFont(x) => format!("i := bighex {}", r(x)),
+        Instruction::SaveFlags(x) => format!("saveflags v{:x}", x),
+        Instruction::LoadFlags(x) => format!("loadflags v{:x}", x),
+        Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+        Instruction::SaveRange(x, y) => format!("save {} - {}", r(x), r(y)),
+        Instruction::LoadRange(x, y) => format!("load {} - {}", r(x), r(y)),
+        Instruction::SetIdxRegLong => format!("i := long {:#06x}", long?),
+        Instruction::SelectPlanes(n) => format!("plane {}", n),
+        Instruction::LoadAudioPattern => "audio".to_string(),
+        Instruction::SetPitch(x) => format!("pitch := {}", r(x)),
+    };
+    Some(text)
+}
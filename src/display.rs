@@ -0,0 +1,212 @@
+//! Display settings: the palette, the flicker filter, the pixel art scaler, the border around the scaled display, the
+//! pixel grid and fullscreen. They can be set from the command line or from a config file with one
+//! `<setting> = <value>` line per setting.
+
+use chip_8_interpreter::filter::Filter;
+use chip_8_interpreter::upscale::Scaler;
+use raylib::prelude::*;
+
+/// The original black and white, with greys for the second XO-CHIP plane.
+pub const CLASSIC: [Color; 4] = [
+    Color::BLACK,
+    Color::WHITE,
+    Color::new(0xAA, 0xAA, 0xAA, 0xFF),
+    Color::new(0x55, 0x55, 0x55, 0xFF),
+];
+/// Octo's default colours.
+const OCTO: [Color; 4] = [
+    Color::new(0x99, 0x66, 0x00, 0xFF),
+    Color::new(0xFF, 0xCC, 0x00, 0xFF),
+    Color::new(0xFF, 0x66, 0x00, 0xFF),
+    Color::new(0x66, 0x22, 0x00, 0xFF),
+];
+const AMBER: [Color; 4] = [
+    Color::new(0x1A, 0x0F, 0x00, 0xFF),
+    Color::new(0xFF, 0xB0, 0x00, 0xFF),
+    Color::new(0xB3, 0x6B, 0x00, 0xFF),
+    Color::new(0x66, 0x3D, 0x00, 0xFF),
+];
+const GREEN: [Color; 4] = [
+    Color::new(0x05, 0x14, 0x05, 0xFF),
+    Color::new(0x33, 0xFF, 0x66, 0xFF),
+    Color::new(0x1F, 0xA6, 0x42, 0xFF),
+    Color::new(0x12, 0x5C, 0x26, 0xFF),
+];
+
+pub const PALETTE_NAMES: [&str; 4] = ["classic", "octo", "amber", "green"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DisplayOptions {
+    /// Colours for the four palette indices in [`Chip8::framebuffer`]: off, first plane only,
+    /// second plane only and both planes.
+    ///
+    /// [`Chip8::framebuffer`]: chip_8_interpreter::Chip8::framebuffer
+    pub palette: [Color; 4],
+    pub filter: Filter,
+    pub scaler: Scaler,
+    /// The colour around the display where it doesn't fill the window.
+    pub border: Color,
+    /// Whether to draw lines between the pixels.
+    pub pixel_grid: bool,
+    /// Whether to start fullscreen.
+    pub fullscreen: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            palette: CLASSIC,
+            filter: Filter::None,
+            scaler: Scaler::None,
+            border: Color::BLACK,
+            pixel_grid: false,
+            fullscreen: false,
+        }
+    }
+}
+
+impl DisplayOptions {
+    /// Changes `setting` to `value`:
+    ///
+    /// - `palette`: one of [`PALETTE_NAMES`], or two or four comma separated hex colours. With two,
+    ///   the second plane gets shades in between.
+    /// - `filter`: one of [`Filter::NAMES`], with `persistence` optionally followed by the frames to
+    ///   fade over, such as `persistence:10`.
+    /// - `scaler`: one of [`Scaler::NAMES`].
+    /// - `border`: a hex colour.
+    /// - `pixel-grid` and `fullscreen`: `true` or `false`.
+    pub fn set(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        match setting {
+            "palette" => {
+                self.palette = palette_from_name(value)
+                    .or_else(|| custom_palette(value))
+                    .ok_or_else(|| {
+                        format!(
+                            "invalid palette `{}`, expected one of {} or hex colours such as \
+                             `000000,ffffff`",
+                            value,
+                            PALETTE_NAMES.join(", ")
+                        )
+                    })?;
+            }
+            "filter" => {
+                self.filter = Filter::from_name(value).ok_or_else(|| {
+                    format!(
+                        "unknown filter `{}`, expected one of: {}",
+                        value,
+                        Filter::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "scaler" => {
+                self.scaler = Scaler::from_name(value).ok_or_else(|| {
+                    format!(
+                        "unknown scaler `{}`, expected one of: {}",
+                        value,
+                        Scaler::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "border" => {
+                self.border =
+                    parse_color(value).ok_or_else(|| format!("invalid colour `{}`", value))?;
+            }
+            "pixel-grid" => self.pixel_grid = parse_bool(value)?,
+            "fullscreen" => self.fullscreen = parse_bool(value)?,
+            _ => return Err(format!("unknown display setting `{}`", setting)),
+        }
+        Ok(())
+    }
+
+    /// The palette as RGB triples, for [`FrameFilter`](chip_8_interpreter::filter::FrameFilter).
+    pub fn rgb_palette(&self) -> [[u8; 3]; 4] {
+        self.palette.map(|color| [color.r, color.g, color.b])
+    }
+
+    /// Applies each `<setting> = <value>` line of `config`. `#` starts a comment.
+    pub fn parse_config(&mut self, config: &str) -> Result<(), String> {
+        for (i, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (setting, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `<setting> = <value>`", i + 1))?;
+            self.set(setting.trim(), value.trim())
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+        }
+        Ok(())
+    }
+}
+
+fn palette_from_name(name: &str) -> Option<[Color; 4]> {
+    match name.to_ascii_lowercase().as_str() {
+        "classic" => Some(CLASSIC),
+        "octo" => Some(OCTO),
+        "amber" => Some(AMBER),
+        "green" => Some(GREEN),
+        _ => None,
+    }
+}
+
+fn custom_palette(colors: &str) -> Option<[Color; 4]> {
+    let colors = colors
+        .split(',')
+        .map(|color| parse_color(color.trim()))
+        .collect::<Option<Vec<_>>>()?;
+    match colors[..] {
+        [off, on] => {
+            let mix = |amount: f32| {
+                let channel =
+                    |off: u8, on: u8| (off as f32 + (on as f32 - off as f32) * amount) as u8;
+                Color::new(
+                    channel(off.r, on.r),
+                    channel(off.g, on.g),
+                    channel(off.b, on.b),
+                    0xFF,
+                )
+            };
+            Some([off, on, mix(2.0 / 3.0), mix(1.0 / 3.0)])
+        }
+        [off, first, second, both] => Some([off, first, second, both]),
+        _ => None,
+    }
+}
+
+/// Reads an `RRGGBB` colour, optionally starting with `#`.
+fn parse_color(hex: &str) -> Option<Color> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::new(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+        0xFF,
+    ))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected `true` or `false`, got `{}`", value)),
+    }
+}
+
+/// The largest whole multiple of a `width` by `height` display that fits in `area`, centred in it.
+pub fn letterbox(area: Rectangle, width: usize, height: usize) -> Rectangle {
+    let scale = (area.width as usize / width)
+        .min(area.height as usize / height)
+        .max(1);
+    let (scaled_width, scaled_height) = ((width * scale) as f32, (height * scale) as f32);
+    Rectangle::new(
+        area.x + ((area.width - scaled_width) / 2.0).floor(),
+        area.y + ((area.height - scaled_height) / 2.0).floor(),
+        scaled_width,
+        scaled_height,
+    )
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+/// Errors raised while loading or running a CHIP-8 program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The word at `addr` does not decode to any known instruction.
+    UnknownOpcode { addr: u16, word: u16 },
+    /// A register index outside of `0x0..=0xF`.
+    InvalidRegister(u8),
+    /// `0NNN` at `addr` calls a machine code subroutine at `target`, which only the low-level
+    /// [`CosmacVip`](crate::vip::CosmacVip) can run.
+    MachineCode { addr: u16, target: u16 },
+    /// `00EE` was executed at `addr` with no return address on the stack.
+    StackUnderflow { addr: u16 },
+    /// `2NNN` was executed at `addr` with the stack already full.
+    StackOverflow { addr: u16 },
+    /// An access to `addr`, which lies outside of memory.
+    MemoryOutOfBounds { addr: usize },
+    /// The ROM is `len` bytes but only `max` bytes fit into memory after `0x200`.
+    RomTooLarge { len: usize, max: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { addr, word } => {
+                write!(f, "unknown instruction {:04X} at {:03X}", word, addr)
+            }
+            Chip8Error::InvalidRegister(reg) => write!(f, "invalid register {:X}", reg),
+            Chip8Error::MachineCode { addr, target } => write!(
+                f,
+                "machine code subroutine call to {:03X} at {:03X} needs COSMAC VIP emulation",
+                target, addr
+            ),
+            Chip8Error::StackUnderflow { addr } => {
+                write!(
+                    f,
+                    "invalid pop at {:03X}: missing return address from stack",
+                    addr
+                )
+            }
+            Chip8Error::StackOverflow { addr } => {
+                write!(f, "invalid call at {:03X}: stack is full", addr)
+            }
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:X}", addr)
+            }
+            Chip8Error::RomTooLarge { len, max } => {
+                write!(
+                    f,
+                    "ROM is {} bytes but at most {} bytes fit in memory",
+                    len, max
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
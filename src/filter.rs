@@ -0,0 +1,117 @@
+//! Flicker reduction. CHIP-8 games erase sprites by drawing them again, so moving sprites spend
+//! every other frame switched off and flicker. These filters turn the frames a machine produces
+//! into RGB images that hide it, for the window, screenshots and recordings alike.
+
+/// How a frame is turned into colours.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Each frame as it is.
+    #[default]
+    None,
+    /// Pixels that switch off fade out over `frames` frames, like the phosphor of a CRT.
+    Persistence { frames: u8 },
+    /// Pixels are lit if they're lit in this frame or the one before.
+    Blend,
+}
+
+impl Filter {
+    pub const DEFAULT_PERSISTENCE_FRAMES: u8 = 6;
+
+    /// Names accepted by [`Filter::from_name`].
+    pub const NAMES: [&'static str; 3] = ["none", "persistence", "blend"];
+
+    /// Looks up a filter by name. `persistence` takes an optional number of frames to fade over,
+    /// such as `persistence:10`.
+    pub fn from_name(name: &str) -> Option<Filter> {
+        let (name, frames) = match name.split_once(':') {
+            Some((name, frames)) => (name, Some(frames)),
+            None => (name, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), frames) {
+            ("none", None) => Some(Filter::None),
+            ("blend", None) => Some(Filter::Blend),
+            ("persistence", None) => Some(Filter::Persistence {
+                frames: Self::DEFAULT_PERSISTENCE_FRAMES,
+            }),
+            ("persistence", Some(frames)) => frames
+                .parse()
+                .ok()
+                .filter(|frames| *frames > 0)
+                .map(|frames| Filter::Persistence { frames }),
+            _ => None,
+        }
+    }
+}
+
+/// Runs frames through a [`Filter`], keeping what it needs of the frames before.
+#[derive(Debug, Clone)]
+pub struct FrameFilter {
+    filter: Filter,
+    palette: [[u8; 3]; 4],
+    /// The last frame's palette indices.
+    previous: Vec<u8>,
+    /// For persistence, the palette index each pixel was last lit with and how many frames it
+    /// still glows for.
+    glow: Vec<(u8, u8)>,
+    rgb: Vec<u8>,
+}
+
+impl FrameFilter {
+    /// A filter colouring the palette indices of [`Chip8::framebuffer`] with `palette`: off, first
+    /// plane only, second plane only and both planes.
+    ///
+    /// [`Chip8::framebuffer`]: crate::Chip8::framebuffer
+    pub fn new(filter: Filter, palette: [[u8; 3]; 4]) -> Self {
+        Self {
+            filter,
+            palette,
+            previous: Vec::new(),
+            glow: Vec::new(),
+            rgb: Vec::new(),
+        }
+    }
+
+    /// Filters the next frame and returns it as 8-bit RGB pixels, row by row. Should be called
+    /// once per 60 Hz frame, since that's what the fading and blending count in.
+    pub fn apply(&mut self, framebuffer: &[u8]) -> &[u8] {
+        // A change of resolution starts over.
+        if self.previous.len() != framebuffer.len() {
+            self.previous = framebuffer.to_vec();
+            self.glow = framebuffer.iter().map(|pixel| (pixel & 0x03, 0)).collect();
+        }
+
+        self.rgb.clear();
+        match self.filter {
+            Filter::None => {
+                for pixel in framebuffer {
+                    self.rgb
+                        .extend_from_slice(&self.palette[(pixel & 0x03) as usize]);
+                }
+            }
+            Filter::Blend => {
+                for (pixel, previous) in framebuffer.iter().zip(&self.previous) {
+                    let index = (pixel | previous) & 0x03;
+                    self.rgb.extend_from_slice(&self.palette[index as usize]);
+                }
+            }
+            Filter::Persistence { frames } => {
+                for (pixel, (index, remaining)) in framebuffer.iter().zip(&mut self.glow) {
+                    if pixel & 0x03 != 0 {
+                        *index = pixel & 0x03;
+                        *remaining = frames;
+                    } else {
+                        *remaining = remaining.saturating_sub(1);
+                    }
+                    let level = (*remaining as u16 * 255 / frames as u16) as u8;
+                    let (off, on) = (self.palette[0], self.palette[*index as usize]);
+                    for (off, on) in off.iter().zip(on) {
+                        let mixed = *off as u16 * (255 - level as u16) + on as u16 * level as u16;
+                        self.rgb.push((mixed / 255) as u8);
+                    }
+                }
+            }
+        }
+        self.previous.copy_from_slice(framebuffer);
+        &self.rgb
+    }
+}
@@ -0,0 +1,224 @@
+//! Gamepad input for the keypad, through a profile per ROM that maps buttons to hex keys, and the
+//! in-window screen for remapping them.
+
+use chip_8_interpreter::Keypad;
+use raylib::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+use crate::keymap::Keymap;
+use GamepadButton::*;
+
+/// The gamepad that is read, the first one connected.
+const GAMEPAD: i32 = 0;
+
+/// Opens and closes the remapping screen.
+pub const REMAP_KEY: KeyboardKey = KeyboardKey::KEY_F10;
+
+/// The buttons that can be mapped, with their names in profile files and on the remapping screen.
+const BUTTONS: [(GamepadButton, &str, &str); 16] = [
+    (GAMEPAD_BUTTON_LEFT_FACE_UP, "up", "D-pad up"),
+    (GAMEPAD_BUTTON_LEFT_FACE_DOWN, "down", "D-pad down"),
+    (GAMEPAD_BUTTON_LEFT_FACE_LEFT, "left", "D-pad left"),
+    (GAMEPAD_BUTTON_LEFT_FACE_RIGHT, "right", "D-pad right"),
+    (GAMEPAD_BUTTON_RIGHT_FACE_DOWN, "a", "A / Cross"),
+    (GAMEPAD_BUTTON_RIGHT_FACE_RIGHT, "b", "B / Circle"),
+    (GAMEPAD_BUTTON_RIGHT_FACE_LEFT, "x", "X / Square"),
+    (GAMEPAD_BUTTON_RIGHT_FACE_UP, "y", "Y / Triangle"),
+    (GAMEPAD_BUTTON_LEFT_TRIGGER_1, "l1", "L1"),
+    (GAMEPAD_BUTTON_LEFT_TRIGGER_2, "l2", "L2"),
+    (GAMEPAD_BUTTON_RIGHT_TRIGGER_1, "r1", "R1"),
+    (GAMEPAD_BUTTON_RIGHT_TRIGGER_2, "r2", "R2"),
+    (GAMEPAD_BUTTON_MIDDLE_LEFT, "select", "Select"),
+    (GAMEPAD_BUTTON_MIDDLE_RIGHT, "start", "Start"),
+    (GAMEPAD_BUTTON_LEFT_THUMB, "l3", "Left stick"),
+    (GAMEPAD_BUTTON_RIGHT_THUMB, "r3", "Right stick"),
+];
+
+/// The hex key each of [`BUTTONS`] is mapped to, if any.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GamepadProfile {
+    keys: [Option<u8>; 16],
+}
+
+impl Default for GamepadProfile {
+    /// The D-pad on 5, 7, 8 and 9, which most games use as up, left, down and right, and the face
+    /// buttons on 4 and 6.
+    fn default() -> Self {
+        let mut keys = [None; 16];
+        for (i, key) in [0x5, 0x8, 0x7, 0x9, 0x6, 0x4].into_iter().enumerate() {
+            keys[i] = Some(key);
+        }
+        Self { keys }
+    }
+}
+
+impl GamepadProfile {
+    /// The profile file for the ROM at `rom_path`, such as `game.pad` for `game.ch8`.
+    pub fn path(rom_path: &str) -> PathBuf {
+        Path::new(rom_path).with_extension("pad")
+    }
+
+    /// Reads the profile at `path`, or the default one if there is no file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(profile) => {
+                Self::parse(&profile).map_err(|err| format!("{}: {}", path.display(), err))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("failed to read {}: {}", path.display(), err)),
+        }
+    }
+
+    /// Reads a profile with a `<button> = <hex key>` line for each mapped button, such as
+    /// `up = 5`. `#` starts a comment.
+    fn parse(profile: &str) -> Result<Self, String> {
+        let mut keys = [None; 16];
+        for (i, line) in profile.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (button, key) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `<button> = <hex key>`", i + 1))?;
+            let button = BUTTONS
+                .iter()
+                .position(|(_, name, _)| name.eq_ignore_ascii_case(button.trim()))
+                .ok_or_else(|| format!("line {}: unknown button `{}`", i + 1, button.trim()))?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(|| format!("line {}: invalid hex key `{}`", i + 1, key.trim()))?;
+            keys[button] = Some(key);
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let profile = BUTTONS
+            .iter()
+            .zip(self.keys)
+            .filter_map(|((_, name, _), key)| Some(format!("{} = {:X}\n", name, key?)))
+            .collect::<String>();
+        std::fs::write(path, profile)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err))
+    }
+
+    /// The hex keys whose buttons are held down on the gamepad.
+    pub fn held(&self, rl: &RaylibHandle) -> Keypad {
+        let mut keypad = Keypad::default();
+        if !rl.is_gamepad_available(GAMEPAD) {
+            return keypad;
+        }
+        for ((button, _, _), key) in BUTTONS.iter().zip(self.keys) {
+            if let Some(key) = key {
+                if rl.is_gamepad_button_down(GAMEPAD, *button) {
+                    keypad.set_held(key, true);
+                }
+            }
+        }
+        keypad
+    }
+}
+
+/// The remapping screen: pick a button by pressing it or with the arrow keys, then press the hex
+/// key to put on it, or Delete to unmap it.
+#[derive(Debug, Clone, Default)]
+pub struct Remapper {
+    open: bool,
+    selected: usize,
+}
+
+impl Remapper {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Handles the keys and buttons for the screen. Returns true when it's closed, at which point
+    /// the profile should be saved.
+    pub fn update(
+        &mut self,
+        rl: &RaylibHandle,
+        keymap: &Keymap,
+        profile: &mut GamepadProfile,
+    ) -> bool {
+        if rl.is_key_pressed(REMAP_KEY) {
+            self.open = !self.open;
+            return !self.open;
+        }
+        if !self.open {
+            return false;
+        }
+
+        if let Some(i) = BUTTONS
+            .iter()
+            .position(|(button, _, _)| rl.is_gamepad_button_pressed(GAMEPAD, *button))
+        {
+            self.selected = i;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_UP) {
+            self.selected = (self.selected + BUTTONS.len() - 1) % BUTTONS.len();
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_DOWN) {
+            self.selected = (self.selected + 1) % BUTTONS.len();
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_DELETE) {
+            profile.keys[self.selected] = None;
+        }
+        if let Some(key) = keymap.pressed(rl).first_held() {
+            profile.keys[self.selected] = Some(key);
+        }
+        false
+    }
+
+    /// Draws the screen over the top of `bounds`, with the buttons in two columns.
+    pub fn draw(&self, profile: &GamepadProfile, bounds: Rectangle, d: &mut RaylibDrawHandle) {
+        const FONT_SIZE: i32 = 20;
+        const LINE_HEIGHT: i32 = FONT_SIZE + 4;
+        const ROWS: usize = BUTTONS.len() / 2;
+
+        let help = [
+            "Press a button or use Up/Down to pick it,",
+            "then press its hex key. Delete unmaps it.",
+            "F10 saves the profile and goes back.",
+        ];
+        let height = LINE_HEIGHT * (help.len() + 1 + ROWS) as i32 + 8;
+        d.draw_rectangle(
+            bounds.x as i32,
+            bounds.y as i32,
+            bounds.width as i32,
+            height,
+            Color::new(0, 0, 0, 220),
+        );
+        let (x, y) = (bounds.x as i32 + 8, bounds.y as i32 + 4);
+        for (i, line) in help.iter().enumerate() {
+            d.draw_text(line, x, y + LINE_HEIGHT * i as i32, FONT_SIZE, Color::GRAY);
+        }
+        let y = y + LINE_HEIGHT * (help.len() + 1) as i32;
+        let column_width = bounds.width as i32 / 2;
+        for (i, ((_, _, label), key)) in BUTTONS.iter().zip(profile.keys).enumerate() {
+            let color = if i == self.selected {
+                Color::YELLOW
+            } else {
+                Color::WHITE
+            };
+            let (column, row) = ((i / ROWS) as i32, (i % ROWS) as i32);
+            let key = key.map_or("-".to_string(), |key| format!("{:X}", key));
+            d.draw_text(
+                label,
+                x + column * column_width,
+                y + LINE_HEIGHT * row,
+                FONT_SIZE,
+                color,
+            );
+            d.draw_text(
+                &key,
+                x + column * column_width + 180,
+                y + LINE_HEIGHT * row,
+                FONT_SIZE,
+                color,
+            );
+        }
+    }
+}
@@ -0,0 +1,594 @@
+//! A stub for GDB's remote serial protocol, so gdb, lldb and their scripting can debug CHIP-8
+//! programs over a local TCP socket.
+//!
+//! The registers are numbered `V0` to `VF` (0 to 15), then `I`, `PC`, `SP`, `DT` and `ST`. `I` and
+//! `PC` are 16 bits wide, the rest 8 bits, and they are described to the debugger through a target
+//! description. Software and hardware breakpoints, write, read and access watchpoints and single
+//! stepping are supported. Write watchpoints trigger when the watched memory changes.
+
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::ops::Range;
+
+use crate::{decode, Chip8, Chip8Error, Instruction};
+
+const REGISTER_COUNT: usize = 21;
+const I_REGISTER: usize = 16;
+const PC_REGISTER: usize = 17;
+const SP_REGISTER: usize = 18;
+const DT_REGISTER: usize = 19;
+const ST_REGISTER: usize = 20;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+impl WatchKind {
+    /// The name of the stop reason reported for this kind of watchpoint.
+    fn reason(self) -> &'static str {
+        match self {
+            WatchKind::Write => "watch",
+            WatchKind::Read => "rwatch",
+            WatchKind::Access => "awatch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Watchpoint {
+    kind: WatchKind,
+    range: Range<usize>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    /// Waiting for a command from the debugger.
+    Stopped,
+    /// Executing a single instruction.
+    Stepping,
+    Running,
+    /// The debugger has detached, leaving the program running without breakpoints.
+    Detached,
+    /// The debugger has asked to kill the program.
+    Killed,
+}
+
+/// A connection to a debugger that controls a [`Chip8`].
+///
+/// The client keeps driving the machine through its own loop, calling [`GdbStub::poll`] to handle
+/// commands and asking [`GdbStub::should_step`] before executing through [`GdbStub::step`].
+#[derive(Debug)]
+pub struct GdbStub<S = TcpStream> {
+    stream: S,
+    input: Vec<u8>,
+    state: State,
+    /// Set when continuing so the breakpoint at the current instruction doesn't stop it right
+    /// away.
+    resuming: bool,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl GdbStub {
+    /// Listens on `port` on the loopback interface and blocks until a debugger connects. The
+    /// machine starts out stopped.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: Read + Write> GdbStub<S> {
+    /// Talks to a debugger that is already connected over `stream`, which has to be non-blocking
+    /// and report that it has nothing to read with [`ErrorKind::WouldBlock`].
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            input: Vec::new(),
+            state: State::Stopped,
+            resuming: false,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    /// Whether the debugger has stopped the machine.
+    pub fn stopped(&self) -> bool {
+        self.state == State::Stopped
+    }
+
+    /// Whether the debugger has asked to kill the program, which the client should then exit.
+    pub fn killed(&self) -> bool {
+        self.state == State::Killed
+    }
+
+    /// Handles everything the debugger has sent without blocking.
+    pub fn poll(&mut self, chip8: &mut Chip8) -> io::Result<()> {
+        if matches!(self.state, State::Detached | State::Killed) {
+            return Ok(());
+        }
+
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.state = State::Detached;
+                    return Ok(());
+                }
+                Ok(len) => self.input.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        while let Some(packet) = self.next_packet()? {
+            let reply = self.handle(&packet, chip8)?;
+            if let Some(reply) = reply {
+                self.send(&reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether to execute the next instruction now. `due` is whether it is time to according to
+    /// the client's instruction rate, which single steps ignore.
+    pub fn should_step(&mut self, chip8: &Chip8, due: bool) -> io::Result<bool> {
+        match self.state {
+            State::Stopped | State::Killed => Ok(false),
+            State::Stepping => Ok(true),
+            State::Detached => Ok(due),
+            State::Running if !due => Ok(false),
+            State::Running => {
+                let resuming = std::mem::take(&mut self.resuming);
+                if !resuming && self.breakpoints.contains(&chip8.program_counter()) {
+                    self.stop(&format!("S{:02x}", SIGTRAP))?;
+                    return Ok(false);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Executes one instruction through [`Chip8::step`], telling the debugger when a single
+    /// step is done, a watchpoint is hit, the program exits or it fails.
+    pub fn step(&mut self, chip8: &mut Chip8) -> io::Result<Result<Instruction, Chip8Error>> {
+        if matches!(self.state, State::Detached | State::Killed) {
+            return Ok(chip8.step());
+        }
+
+        let reads = read_range(chip8);
+        let before = self
+            .watchpoints
+            .iter()
+            .map(|watch| memory_range(chip8, &watch.range).to_vec())
+            .collect::<Vec<_>>();
+
+        let result = chip8.step();
+
+        let hit = self
+            .watchpoints
+            .iter()
+            .zip(&before)
+            .find(|(watch, before)| {
+                let written = memory_range(chip8, &watch.range) != before.as_slice();
+                let read = reads
+                    .as_ref()
+                    .is_some_and(|reads| overlaps(reads, &watch.range));
+                match watch.kind {
+                    WatchKind::Write => written,
+                    WatchKind::Read => read,
+                    WatchKind::Access => written || read,
+                }
+            });
+
+        let stop = match (&result, hit) {
+            (Err(err), _) => Some(format!("S{:02x}", signal(err))),
+            (Ok(_), _) if chip8.halted() => Some("W00".to_string()),
+            (Ok(_), Some((watch, _))) => Some(format!(
+                "T{:02x}{}:{:x};",
+                SIGTRAP,
+                watch.kind.reason(),
+                watch.range.start
+            )),
+            (Ok(_), None) if self.state == State::Stepping => Some(format!("S{:02x}", SIGTRAP)),
+            (Ok(_), None) => None,
+        };
+        if let Some(stop) = stop {
+            self.stop(&stop)?;
+        }
+        Ok(result)
+    }
+
+    fn stop(&mut self, reply: &str) -> io::Result<()> {
+        self.state = State::Stopped;
+        self.send(reply)
+    }
+
+    /// Takes the next complete packet out of the input, acknowledging it. Packets with a bad
+    /// checksum are rejected and skipped. A Ctrl-C from the debugger is returned as a packet of
+    /// its own.
+    fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.input.first() {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(0x03) => {
+                    self.input.remove(0);
+                    return Ok(Some(vec![0x03]));
+                }
+                // Acknowledgements and noise between packets.
+                Some(_) => {
+                    self.input.remove(0);
+                    continue;
+                }
+            }
+
+            let Some(end) = self.input.iter().position(|b| *b == b'#') else {
+                return Ok(None);
+            };
+            if self.input.len() < end + 3 {
+                return Ok(None);
+            }
+            let packet = self.input[1..end].to_vec();
+            let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            self.input.drain(..end + 3);
+
+            if checksum == Some(checksum_of(&packet)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(packet));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, reply: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", reply, checksum_of(reply.as_bytes()));
+        // The socket is non-blocking, so keep trying until the whole packet is out.
+        let mut bytes = packet.as_bytes();
+        while !bytes.is_empty() {
+            match self.stream.write(bytes) {
+                Ok(len) => bytes = &bytes[len..],
+                Err(err) if err.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a packet, returning the reply or `None` if there is none yet.
+    fn handle(&mut self, packet: &[u8], chip8: &mut Chip8) -> io::Result<Option<String>> {
+        if packet == [0x03] {
+            if self.state == State::Running || self.state == State::Stepping {
+                self.state = State::Stopped;
+                return Ok(Some(format!("S{:02x}", SIGINT)));
+            }
+            return Ok(None);
+        }
+
+        let packet = String::from_utf8_lossy(packet);
+        let Some(command) = packet.get(..1) else {
+            return Ok(Some(String::new()));
+        };
+        let args = &packet[1..];
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTER_COUNT)
+                .map(|reg| read_register(chip8, reg))
+                .collect(),
+            "G" => {
+                let mut args = args;
+                for reg in 0..REGISTER_COUNT {
+                    let len = register_size(reg) * 2;
+                    if args.len() < len {
+                        break;
+                    }
+                    let (value, rest) = args.split_at(len);
+                    if write_register(chip8, reg, value).is_none() {
+                        return Ok(Some("E01".to_string()));
+                    }
+                    args = rest;
+                }
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < REGISTER_COUNT => read_register(chip8, reg),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(reg, value)| {
+                    let reg = usize::from_str_radix(reg, 16).ok()?;
+                    (reg < REGISTER_COUNT).then_some(())?;
+                    write_register(chip8, reg, value)
+                });
+                ok_or_error(written.is_some())
+            }
+            "m" => match parse_range(args) {
+                Some(range) if range.start < chip8.memory().len() => {
+                    let end = range.end.min(chip8.memory().len());
+                    hex(&chip8.memory()[range.start..end])
+                }
+                _ => "E01".to_string(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let range = parse_range(range)?;
+                    let bytes = from_hex(data)?;
+                    let memory = chip8.memory_mut().get_mut(range)?;
+                    (memory.len() == bytes.len()).then(|| memory.copy_from_slice(&bytes))
+                });
+                ok_or_error(written.is_some())
+            }
+            // A program that has exited can't run any further, so report the exit again.
+            "c" | "s" if chip8.halted() => "W00".to_string(),
+            "v" if chip8.halted() && args.starts_with("Cont;") => "W00".to_string(),
+            "c" => {
+                self.resume(chip8, args, State::Running);
+                return Ok(None);
+            }
+            "s" => {
+                self.resume(chip8, args, State::Stepping);
+                return Ok(None);
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "D" => {
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+                self.state = State::Detached;
+                "OK".to_string()
+            }
+            "k" => {
+                self.state = State::Killed;
+                return Ok(None);
+            }
+            "H" | "T" => "OK".to_string(),
+            "q" => query(args),
+            "v" => match args {
+                "Cont?" => "vCont;c;s".to_string(),
+                _ if args.starts_with("Cont;") => {
+                    let state = match args.as_bytes().get(5) {
+                        Some(b's') => State::Stepping,
+                        _ => State::Running,
+                    };
+                    self.resume(chip8, "", state);
+                    return Ok(None);
+                }
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    /// Continues or steps, optionally from the address in `args`.
+    fn resume(&mut self, chip8: &mut Chip8, args: &str, state: State) {
+        if let Ok(addr) = u16::from_str_radix(args, 16) {
+            chip8.set_program_counter(addr);
+        }
+        self.state = state;
+        self.resuming = true;
+    }
+
+    /// `Z<type>,<addr>,<kind>` inserts and `z<type>,<addr>,<kind>` removes a breakpoint or
+    /// watchpoint.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return "E01".to_string();
+        };
+        let (Ok(addr), Ok(len)) = (
+            usize::from_str_radix(addr, 16),
+            usize::from_str_radix(len, 16),
+        ) else {
+            return "E01".to_string();
+        };
+
+        let kind = match kind {
+            "0" | "1" => {
+                let addr = addr as u16;
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        let Some(end) = addr.checked_add(len.max(1)) else {
+            return "E01".to_string();
+        };
+        let watch = Watchpoint {
+            kind,
+            range: addr..end,
+        };
+        if insert {
+            self.watchpoints.push(watch);
+        } else {
+            self.watchpoints.retain(|other| *other != watch);
+        }
+        "OK".to_string()
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return "PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+".to_string();
+    }
+    if let Some(annex) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let Some(range) = parse_range(annex) else {
+            return "E01".to_string();
+        };
+        let xml = TARGET_XML.as_bytes();
+        let start = range.start.min(xml.len());
+        let end = range.end.min(xml.len());
+        let more = if end < xml.len() { "m" } else { "l" };
+        return format!("{}{}", more, String::from_utf8_lossy(&xml[start..end]));
+    }
+    match args {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn register_size(reg: usize) -> usize {
+    match reg {
+        I_REGISTER | PC_REGISTER => 2,
+        _ => 1,
+    }
+}
+
+/// The register as little endian hex, the byte order the target description implies.
+fn read_register(chip8: &Chip8, reg: usize) -> String {
+    match reg {
+        I_REGISTER => hex(&chip8.index_register().to_le_bytes()),
+        PC_REGISTER => hex(&chip8.program_counter().to_le_bytes()),
+        SP_REGISTER => hex(&[chip8.stack().len() as u8]),
+        DT_REGISTER => hex(&[chip8.delay_timer()]),
+        ST_REGISTER => hex(&[chip8.sound_timer()]),
+        _ => hex(&[chip8.registers()[reg]]),
+    }
+}
+
+/// Sets a register from little endian hex. Writing `SP` pushes zeros or pops return addresses.
+fn write_register(chip8: &mut Chip8, reg: usize, value: &str) -> Option<()> {
+    let bytes = from_hex(value)?;
+    if bytes.len() != register_size(reg) {
+        return None;
+    }
+    match reg {
+        I_REGISTER => chip8.set_index_register(u16::from_le_bytes([bytes[0], bytes[1]])),
+        PC_REGISTER => chip8.set_program_counter(u16::from_le_bytes([bytes[0], bytes[1]])),
+        SP_REGISTER => {
+            let mut stack = chip8.stack().to_vec();
+            stack.resize(bytes[0] as usize, 0);
+            chip8.set_stack(&stack).ok()?;
+        }
+        DT_REGISTER => chip8.set_delay_timer(bytes[0]),
+        ST_REGISTER => chip8.set_sound_timer(bytes[0]),
+        _ => {
+            let mut registers = chip8.registers();
+            registers[reg] = bytes[0];
+            chip8.set_registers(registers);
+        }
+    }
+    Some(())
+}
+
+/// The memory the instruction at the program counter is about to read as data.
+fn read_range(chip8: &Chip8) -> Option<Range<usize>> {
+    let pc = chip8.program_counter() as usize;
+    let word = chip8.memory().get(pc..pc + 2)?;
+    let ins = decode(pc as u16, u16::from_be_bytes([word[0], word[1]])).ok()?;
+    let len = match ins {
+        Instruction::Display { n: 0, .. } => 32 * chip8.selected_planes().count_ones() as usize,
+        Instruction::Display { n, .. } => {
+            n as usize * chip8.selected_planes().count_ones() as usize
+        }
+        Instruction::Load(x) => x as usize + 1,
+        Instruction::LoadRange(x, y) => (x as usize).abs_diff(y as usize) + 1,
+        Instruction::LoadAudioPattern => 16,
+        _ => return None,
+    };
+    let start = chip8.index_register() as usize;
+    Some(start..start + len)
+}
+
+/// The part of `range` that lies within memory.
+fn memory_range<'a>(chip8: &'a Chip8, range: &Range<usize>) -> &'a [u8] {
+    let memory = chip8.memory();
+    let end = range.end.min(memory.len());
+    &memory[range.start.min(end)..end]
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+fn signal(err: &Chip8Error) -> u8 {
+    match err {
+        Chip8Error::MemoryOutOfBounds { .. } => SIGSEGV,
+        _ => SIGILL,
+    }
+}
+
+fn ok_or_error(ok: bool) -> String {
+    if ok { "OK" } else { "E01" }.to_string()
+}
+
+/// Parses `<addr>,<len>` in hex, or `None` if it's malformed or runs past `usize::MAX`.
+fn parse_range(args: &str) -> Option<Range<usize>> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    Some(addr..addr.checked_add(len)?)
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,393 @@
+//! The `headless` subcommand: runs a ROM without a window, with keys from a script or a movie, and
+//! writes the final display to an image or checks it against a golden file. It can also record the
+//! display to an animation.
+
+use chip_8_interpreter::audio::{Capture, Tone, DEFAULT_SAMPLE_RATE};
+use chip_8_interpreter::filter::FrameFilter;
+use chip_8_interpreter::movie::{Frame, Movie};
+use chip_8_interpreter::upscale::Scaler;
+use chip_8_interpreter::{image, Chip8, Quirks};
+
+use std::path::Path;
+
+use crate::capture::{self, Picture, Recording};
+use crate::display::DisplayOptions;
+
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u16 = 10;
+
+/// When to stop running.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Limit {
+    Frames(u64),
+    Instructions(u64),
+}
+
+struct Options {
+    rom_path: String,
+    quirks: Quirks,
+    limit: Option<Limit>,
+    instructions_per_frame: u16,
+    /// A key script, see [`KeyScript`].
+    keys: Option<String>,
+    /// A movie to take the keys and instruction counts from instead.
+    replay: Option<String>,
+    /// The image to write the display to, in the format given by its extension.
+    output: Option<String>,
+    /// The golden image to compare the display against.
+    compare: Option<String>,
+    /// The palette, filter and pixel art scaler for PNG images and recordings.
+    display: DisplayOptions,
+    /// How many times larger than the display PNG images and recordings are.
+    capture_scale: usize,
+    /// The GIF or APNG to record the display to.
+    record_video: Option<String>,
+    /// The WAV file to render the sound to.
+    audio_out: Option<String>,
+    /// How the beep sounds in the WAV file.
+    tone: Tone,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut rom_path = None;
+        let mut quirks = Quirks::default();
+        let mut quirk_overrides = Vec::new();
+        let mut limit = None;
+        let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+        let mut keys = None;
+        let mut replay = None;
+        let mut output = None;
+        let mut compare = None;
+        let mut display = DisplayOptions::default();
+        let mut capture_scale = 1;
+        let mut record_video = None;
+        let mut audio_out = None;
+        let mut tone = Tone::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = args.next().ok_or("--quirks needs a preset name")?;
+                    quirks = Quirks::from_name(name).ok_or_else(|| {
+                        format!(
+                            "unknown quirks preset `{}`, expected one of: {}",
+                            name,
+                            Quirks::PRESET_NAMES.join(", ")
+                        )
+                    })?;
+                }
+                "--quirk" => {
+                    let setting = args.next().ok_or("--quirk needs <flag>=<on|off>")?;
+                    quirk_overrides.push(setting);
+                }
+                "--frames" => {
+                    let count = args.next().ok_or("--frames needs a count")?;
+                    limit = Some(Limit::Frames(parse_count(count)?));
+                }
+                "--instructions" => {
+                    let count = args.next().ok_or("--instructions needs a count")?;
+                    limit = Some(Limit::Instructions(parse_count(count)?));
+                }
+                "--ipf" => {
+                    let count = args.next().ok_or("--ipf needs a count")?;
+                    instructions_per_frame = count
+                        .parse()
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| format!("invalid instructions per frame `{}`", count))?;
+                }
+                "--keys" => {
+                    let path = args.next().ok_or("--keys needs a key script path")?;
+                    keys = Some(path.clone());
+                }
+                "--replay" => {
+                    let path = args.next().ok_or("--replay needs a movie path")?;
+                    replay = Some(path.clone());
+                }
+                "-o" => {
+                    let path = args.next().ok_or("-o needs an image path")?;
+                    output = Some(path.clone());
+                }
+                "--compare" => {
+                    let path = args.next().ok_or("--compare needs a golden image path")?;
+                    compare = Some(path.clone());
+                }
+                "--palette" => {
+                    let palette = args
+                        .next()
+                        .ok_or("--palette needs a palette name or colours")?;
+                    display.set("palette", palette)?;
+                }
+                "--filter" => {
+                    let filter = args.next().ok_or("--filter needs a filter name")?;
+                    display.set("filter", filter)?;
+                }
+                "--scaler" => {
+                    let scaler = args.next().ok_or("--scaler needs a scaler name")?;
+                    display.set("scaler", scaler)?;
+                }
+                "--capture-scale" => {
+                    let scale = args.next().ok_or("--capture-scale needs a scale")?;
+                    capture_scale = capture::parse_scale(scale)?;
+                }
+                "--record-video" => {
+                    let path = args
+                        .next()
+                        .ok_or("--record-video needs a GIF or PNG file path")?;
+                    record_video = Some(path.clone());
+                }
+                "--audio-out" => {
+                    let path = args.next().ok_or("--audio-out needs a WAV file path")?;
+                    audio_out = Some(path.clone());
+                }
+                "--beep-frequency" => {
+                    let hz = args.next().ok_or("--beep-frequency needs a frequency")?;
+                    tone.frequency = crate::parse_frequency(hz)?;
+                }
+                "--beep-volume" => {
+                    let percent = args.next().ok_or("--beep-volume needs a percentage")?;
+                    tone.volume = crate::parse_volume(percent)?;
+                }
+                "--beep-waveform" => {
+                    let name = args.next().ok_or("--beep-waveform needs a waveform name")?;
+                    tone.waveform = crate::parse_waveform(name)?;
+                }
+                _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+
+        for setting in quirk_overrides {
+            crate::override_quirk(&mut quirks, setting)?;
+        }
+        if limit.is_none() && replay.is_none() {
+            return Err("headless needs --frames, --instructions or --replay".to_string());
+        }
+        if keys.is_some() && replay.is_some() {
+            return Err("--keys can't be used with --replay".to_string());
+        }
+        if output.is_some() && compare.is_some() {
+            return Err("-o can't be used with --compare".to_string());
+        }
+
+        Ok(Options {
+            rom_path: rom_path.ok_or("missing ROM path")?,
+            quirks,
+            limit,
+            instructions_per_frame,
+            keys,
+            replay,
+            output,
+            compare,
+            display,
+            capture_scale,
+            record_video,
+            audio_out,
+            tone,
+        })
+    }
+}
+
+fn parse_count(count: &str) -> Result<u64, String> {
+    count
+        .parse()
+        .map_err(|_| format!("invalid count `{}`", count))
+}
+
+/// Keys to hold from a given frame on. Each line of a script is a frame number followed by the hex
+/// keys held from that frame until the next line, or `-` for none:
+///
+/// ```text
+/// # Hold 5 for half a second, then 4 and 6 together.
+/// 60 5
+/// 90 -
+/// 120 4 6
+/// ```
+struct KeyScript {
+    /// Frame numbers and key bitmasks in order.
+    changes: Vec<(u64, u16)>,
+}
+
+impl KeyScript {
+    fn parse(script: &str) -> Result<KeyScript, String> {
+        let mut changes: Vec<(u64, u16)> = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let frame = words
+                .next()
+                .and_then(|frame| frame.parse().ok())
+                .ok_or_else(|| format!("line {}: expected a frame number", i + 1))?;
+            if changes.last().is_some_and(|(last, _)| *last >= frame) {
+                return Err(format!(
+                    "line {}: frames must be in increasing order",
+                    i + 1
+                ));
+            }
+            let mut keys = 0u16;
+            for word in words {
+                if word == "-" {
+                    continue;
+                }
+                let key = u8::from_str_radix(word, 16)
+                    .ok()
+                    .filter(|key| *key < 16)
+                    .ok_or_else(|| format!("line {}: invalid key `{}`", i + 1, word))?;
+                keys |= 1 << key;
+            }
+            changes.push((frame, keys));
+        }
+        Ok(KeyScript { changes })
+    }
+
+    fn keys_at(&self, frame: u64) -> u16 {
+        self.changes
+            .iter()
+            .take_while(|(start, _)| *start <= frame)
+            .last()
+            .map_or(0, |(_, keys)| *keys)
+    }
+}
+
+pub fn run(args: &[String]) {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n{}", err, crate::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let fail = |err: String| -> ! {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    };
+    let read = |path: &str| {
+        std::fs::read(path).unwrap_or_else(|err| fail(format!("failed to read {}: {}", path, err)))
+    };
+
+    let rom = read(&options.rom_path);
+    let (mut chip8, movie) = match &options.replay {
+        Some(path) => {
+            let movie = Movie::from_bytes(&read(path))
+                .unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+            let chip8 = movie
+                .start(&rom)
+                .unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+            (chip8, Some(movie))
+        }
+        None => {
+            let mut chip8 = Chip8::with_quirks(options.quirks);
+            chip8
+                .load_rom(&rom)
+                .unwrap_or_else(|err| fail(err.to_string()));
+            (chip8, None)
+        }
+    };
+    let script = match &options.keys {
+        Some(path) => {
+            let script = String::from_utf8_lossy(&read(path)).into_owned();
+            KeyScript::parse(&script).unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
+        }
+        None => KeyScript {
+            changes: Vec::new(),
+        },
+    };
+
+    let mut capture = Capture::new(options.tone, DEFAULT_SAMPLE_RATE);
+    let mut video = options.record_video.as_ref().map(|path| {
+        Recording::start(
+            Path::new(path),
+            options.display.scaler.factor(),
+            options.capture_scale,
+        )
+        .unwrap_or_else(|err| fail(err))
+    });
+    let mut frame_filter = FrameFilter::new(options.display.filter, options.display.rgb_palette());
+    // The last frame through the filter, which counts in frames and so sees every one.
+    let mut filtered = None;
+    let mut executed = 0;
+    for frame_number in 0.. {
+        let mut frame = match &movie {
+            Some(movie) => match movie.frames().get(frame_number as usize) {
+                Some(frame) => *frame,
+                None => break,
+            },
+            None => Frame {
+                keys: script.keys_at(frame_number),
+                steps: options.instructions_per_frame,
+            },
+        };
+        match options.limit {
+            Some(Limit::Frames(frames)) if frame_number >= frames => break,
+            Some(Limit::Instructions(instructions)) => {
+                if executed >= instructions {
+                    break;
+                }
+                frame.steps = (frame.steps as u64).min(instructions - executed) as u16;
+            }
+            _ => {}
+        }
+        if chip8.halted() {
+            break;
+        }
+        if let Err(err) = frame.run_steps(&mut chip8) {
+            fail(err.to_string());
+        }
+        let rgb = frame_filter.apply(chip8.framebuffer());
+        if let Some(video) = &mut video {
+            video.push(&picture(&chip8, rgb, options.display.scaler));
+        }
+        filtered = Some(rgb.to_vec());
+        capture.frame(&chip8);
+        chip8.tick_timers();
+        executed += frame.steps as u64;
+    }
+
+    if let Some(video) = video {
+        video.finish().unwrap_or_else(|err| fail(err));
+    }
+    if let Some(path) = &options.audio_out {
+        std::fs::write(path, capture.wav())
+            .unwrap_or_else(|err| fail(format!("failed to write {}: {}", path, err)));
+    }
+
+    let rgb = filtered.unwrap_or_else(|| frame_filter.apply(chip8.framebuffer()).to_vec());
+    if let Some(path) = &options.compare {
+        if encode(path, &chip8, &rgb, &options) != read(path) {
+            fail(format!("display differs from {}", path));
+        }
+    } else if let Some(path) = &options.output {
+        std::fs::write(path, encode(path, &chip8, &rgb, &options))
+            .unwrap_or_else(|err| fail(format!("failed to write {}: {}", path, err)));
+    } else {
+        print!(
+            "{}",
+            image::ascii(chip8.width(), chip8.height(), chip8.framebuffer())
+        );
+    }
+}
+
+/// The display, already coloured and filtered into `rgb`, run through `scaler`.
+fn picture(chip8: &Chip8, rgb: &[u8], scaler: Scaler) -> Picture {
+    let (width, height) = (chip8.width(), chip8.height());
+    Picture {
+        rgb: scaler.apply(width, height, rgb),
+        width: width * scaler.factor(),
+        height: height * scaler.factor(),
+    }
+}
+
+/// The display in the format `path`'s extension asks for: PNG for `.png`, from the filtered `rgb`
+/// run through the scaler and enlarged by the capture scale, PBM for `.pbm` and ASCII art otherwise.
+fn encode(path: &str, chip8: &Chip8, rgb: &[u8], options: &Options) -> Vec<u8> {
+    let (width, height, pixels) = (chip8.width(), chip8.height(), chip8.framebuffer());
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("png") => picture(chip8, rgb, options.display.scaler).png(options.capture_scale),
+        Some("pbm") => image::pbm(width, height, pixels).into_bytes(),
+        _ => image::ascii(width, height, pixels).into_bytes(),
+    }
+}
@@ -0,0 +1,212 @@
+//! Encoders for writing the display out as an image, without any image library.
+
+/// Encodes 8-bit RGB pixels, row by row, as a PNG.
+///
+/// The image data is compressed the same quick way as APNG frames, which suits the flat colours of
+/// a CHIP-8 screen.
+pub fn png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(
+        rgb.len(),
+        width * height * 3,
+        "pixel data doesn't match the size"
+    );
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolour, default compression and filtering, no interlacing.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let row_len = width * 3 + 1;
+    let mut scanlines = Vec::with_capacity(height * row_len);
+    for row in rgb.chunks(width * 3).take(height) {
+        // No filter.
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_compressed(&scanlines, 3, row_len));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Encodes palette indices as a plain PBM, with every pixel that isn't off in black.
+pub fn pbm(width: usize, height: usize, pixels: &[u8]) -> String {
+    let mut pbm = format!("P1\n{} {}\n", width, height);
+    for row in pixels.chunks(width).take(height) {
+        let row = row
+            .iter()
+            .map(|pixel| if *pixel == 0 { "0" } else { "1" })
+            .collect::<Vec<_>>();
+        pbm.push_str(&row.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+/// Draws palette indices as text, one line per row: `.` for off, `#` for the first XO-CHIP plane,
+/// `+` for the second and `@` for both.
+pub fn ascii(width: usize, height: usize, pixels: &[u8]) -> String {
+    const CHARS: [char; 4] = ['.', '#', '+', '@'];
+    let mut ascii = String::with_capacity((width + 1) * height);
+    for row in pixels.chunks(width).take(height) {
+        ascii.extend(row.iter().map(|pixel| CHARS[(pixel & 0x03) as usize]));
+        ascii.push('\n');
+    }
+    ascii
+}
+
+pub(crate) fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Compresses `data` into a zlib stream of one deflate block with the fixed Huffman codes.
+///
+/// Only repeats of the last `pixel_len` bytes and of the `row_len` bytes before are looked for,
+/// which is where the copies are in an image made of blocks of flat colour. That takes no search
+/// and still shrinks scaled up CHIP-8 screens many times over.
+pub(crate) fn zlib_compressed(data: &[u8], pixel_len: usize, row_len: usize) -> Vec<u8> {
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = 258;
+    const WINDOW: usize = 32768;
+
+    let mut bits = BitWriter::default();
+    // Last block, fixed Huffman codes.
+    bits.write(1, 1);
+    bits.write(1, 2);
+    let mut pos = 0;
+    while pos < data.len() {
+        let best = [pixel_len, row_len]
+            .into_iter()
+            .filter(|distance| (1..=pos.min(WINDOW)).contains(distance))
+            .map(|distance| {
+                let len = data[pos..]
+                    .iter()
+                    .zip(&data[pos - distance..])
+                    .take(MAX_MATCH)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (len, distance)
+            })
+            .max();
+        match best {
+            Some((len, distance)) if len >= MIN_MATCH => {
+                bits.length(len);
+                bits.distance(distance);
+                pos += len;
+            }
+            _ => {
+                bits.literal(data[pos] as u16);
+                pos += 1;
+            }
+        }
+    }
+    bits.literal(256);
+
+    let mut zlib = vec![0x78, 0x01];
+    zlib.extend_from_slice(&bits.finish());
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+/// Packs deflate codes least significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits not yet making up a whole byte, and how many there are.
+    pending: u32,
+    pending_len: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, len: u32) {
+        self.pending |= bits << self.pending_len;
+        self.pending_len += len;
+        while self.pending_len >= 8 {
+            self.bytes.push(self.pending as u8);
+            self.pending >>= 8;
+            self.pending_len -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which deflate stores most significant bit first.
+    fn code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    /// Writes a literal byte, or the end of block marker 256, or a length code.
+    fn literal(&mut self, value: u16) {
+        let value = value as u32;
+        match value {
+            0..=143 => self.code(0x30 + value, 8),
+            144..=255 => self.code(0x190 + value - 144, 9),
+            256..=279 => self.code(value - 256, 7),
+            _ => self.code(0xC0 + value - 280, 8),
+        }
+    }
+
+    fn length(&mut self, len: usize) {
+        const BASES: [u16; 29] = [
+            3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99,
+            115, 131, 163, 195, 227, 258,
+        ];
+        const EXTRA: [u32; 29] = [
+            0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+        ];
+        let i = BASES
+            .iter()
+            .rposition(|base| *base as usize <= len)
+            .unwrap();
+        self.literal(257 + i as u16);
+        self.write((len - BASES[i] as usize) as u32, EXTRA[i]);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        const BASES: [u16; 30] = [
+            1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025,
+            1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+        ];
+        let i = BASES
+            .iter()
+            .rposition(|base| *base as usize <= distance)
+            .unwrap();
+        let extra = (i as u32 / 2).saturating_sub(1);
+        self.code(i as u32, 5);
+        self.write((distance - BASES[i] as usize) as u32, extra);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_len > 0 {
+            self.bytes.push(self.pending as u8);
+        }
+        self.bytes
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xFFFF_FFFF, |crc, b| {
+        (0..8).fold(crc ^ *b as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    });
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
@@ -0,0 +1,437 @@
+use std::fmt;
+
+use crate::Chip8Error;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum VariableRegister {
+    V0,
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+    V7,
+    V8,
+    V9,
+    VA,
+    VB,
+    VC,
+    VD,
+    VE,
+    VF,
+}
+
+impl TryFrom<u8> for VariableRegister {
+    type Error = Chip8Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let reg = match value {
+            0x0 => Self::V0,
+            0x1 => Self::V1,
+            0x2 => Self::V2,
+            0x3 => Self::V3,
+            0x4 => Self::V4,
+            0x5 => Self::V5,
+            0x6 => Self::V6,
+            0x7 => Self::V7,
+            0x8 => Self::V8,
+            0x9 => Self::V9,
+            0xA => Self::VA,
+            0xB => Self::VB,
+            0xC => Self::VC,
+            0xD => Self::VD,
+            0xE => Self::VE,
+            0xF => Self::VF,
+            _ => return Err(Chip8Error::InvalidRegister(value)),
+        };
+        Ok(reg)
+    }
+}
+
+impl fmt::Display for VariableRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{:X}", *self as u8)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Instruction {
+    /// `0NNN`, a call to a machine code subroutine of the host CPU.
+    MachineCall(u16),
+    ClearScreen,
+    Jump(u16),
+    JumpWithOffset(u16),
+    Call(u16),
+    Pop,
+    SetRegImm(VariableRegister, u8),
+    AddRegImm(VariableRegister, u8),
+    SetIdxRegImm(u16),
+    Display {
+        x: VariableRegister,
+        y: VariableRegister,
+        n: u8,
+    },
+    SkipIfEqImm(VariableRegister, u8),
+    SkipIfNeqImm(VariableRegister, u8),
+    SkipIfEqReg(VariableRegister, VariableRegister),
+    SkipIfNeqReg(VariableRegister, VariableRegister),
+    Set(VariableRegister, VariableRegister),
+    BinOr(VariableRegister, VariableRegister),
+    BinAnd(VariableRegister, VariableRegister),
+    Xor(VariableRegister, VariableRegister),
+    Add(VariableRegister, VariableRegister),
+    SubtractLR(VariableRegister, VariableRegister),
+    SubtractRL(VariableRegister, VariableRegister),
+    ShiftLeft(VariableRegister, VariableRegister),
+    ShiftRight(VariableRegister, VariableRegister),
+    Random(VariableRegister, u8),
+    SkipIfKeyPressed(VariableRegister),
+    SkipIfKeyNotPressed(VariableRegister),
+    GetDelayTimer(VariableRegister),
+    SetDelayTimer(VariableRegister),
+    SetSoundTimer(VariableRegister),
+    GetKey(VariableRegister),
+    Font(VariableRegister),
+    BinDecConversion(VariableRegister),
+    Store(u8),
+    Load(u8),
+    AddToIndex(VariableRegister),
+    // SUPER-CHIP 1.1
+    ScrollDown(u8),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
+    BigFont(VariableRegister),
+    SaveFlags(u8),
+    LoadFlags(u8),
+    // XO-CHIP
+    ScrollUp(u8),
+    SaveRange(VariableRegister, VariableRegister),
+    LoadRange(VariableRegister, VariableRegister),
+    /// `F000 NNNN`, with the address in the word following the instruction.
+    SetIdxRegLong,
+    SelectPlanes(u8),
+    LoadAudioPattern,
+    SetPitch(VariableRegister),
+}
+
+impl Instruction {
+    /// The instruction word that [`decode`] turns into this instruction. For
+    /// [`SetIdxRegLong`](Instruction::SetIdxRegLong) this is only the first of its two words.
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: VariableRegister, y: VariableRegister, n: u16| {
+            op << 12 | (x as u16) << 8 | (y as u16) << 4 | n
+        };
+        let xnn = |op: u16, x: u16, nn: u16| op << 12 | x << 8 | nn;
+        match *self {
+            Instruction::MachineCall(addr) => addr & 0xFFF,
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Jump(addr) => 0x1000 | addr & 0xFFF,
+            Instruction::JumpWithOffset(addr) => 0xB000 | addr & 0xFFF,
+            Instruction::Call(addr) => 0x2000 | addr & 0xFFF,
+            Instruction::Pop => 0x00EE,
+            Instruction::SetRegImm(x, imm) => xnn(0x6, x as u16, imm as u16),
+            Instruction::AddRegImm(x, imm) => xnn(0x7, x as u16, imm as u16),
+            Instruction::SetIdxRegImm(addr) => 0xA000 | addr & 0xFFF,
+            Instruction::Display { x, y, n } => xy(0xD, x, y, n as u16 & 0x0F),
+            Instruction::SkipIfEqImm(x, imm) => xnn(0x3, x as u16, imm as u16),
+            Instruction::SkipIfNeqImm(x, imm) => xnn(0x4, x as u16, imm as u16),
+            Instruction::SkipIfEqReg(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::SkipIfNeqReg(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::Set(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::BinOr(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::BinAnd(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::Add(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::SubtractLR(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SubtractRL(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            Instruction::Random(x, imm) => xnn(0xC, x as u16, imm as u16),
+            Instruction::SkipIfKeyPressed(x) => xnn(0xE, x as u16, 0x9E),
+            Instruction::SkipIfKeyNotPressed(x) => xnn(0xE, x as u16, 0xA1),
+            Instruction::GetDelayTimer(x) => xnn(0xF, x as u16, 0x07),
+            Instruction::GetKey(x) => xnn(0xF, x as u16, 0x0A),
+            Instruction::SetDelayTimer(x) => xnn(0xF, x as u16, 0x15),
+            Instruction::SetSoundTimer(x) => xnn(0xF, x as u16, 0x18),
+            Instruction::AddToIndex(x) => xnn(0xF, x as u16, 0x1E),
+            Instruction::Font(x) => xnn(0xF, x as u16, 0x29),
+            Instruction::BinDecConversion(x) => xnn(0xF, x as u16, 0x33),
+            Instruction::Store(x) => xnn(0xF, x as u16 & 0x0F, 0x55),
+            Instruction::Load(x) => xnn(0xF, x as u16 & 0x0F, 0x65),
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16 & 0x0F,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::BigFont(x) => xnn(0xF, x as u16, 0x30),
+            Instruction::SaveFlags(x) => xnn(0xF, x as u16 & 0x0F, 0x75),
+            Instruction::LoadFlags(x) => xnn(0xF, x as u16 & 0x0F, 0x85),
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16 & 0x0F,
+            Instruction::SaveRange(x, y) => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5, x, y, 0x3),
+            Instruction::SetIdxRegLong => 0xF000,
+            Instruction::SelectPlanes(n) => xnn(0xF, n as u16 & 0x0F, 0x01),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::SetPitch(x) => xnn(0xF, x as u16, 0x3A),
+        }
+    }
+}
+
+/// Mnemonics following Cowgod's CHIP-8 technical reference, extended for SUPER-CHIP and
+/// XO-CHIP.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::MachineCall(addr) => write!(f, "SYS {:#05X}", addr),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Jump(addr) => write!(f, "JP {:#05X}", addr),
+            Instruction::JumpWithOffset(addr) => write!(f, "JP V0, {:#05X}", addr),
+            Instruction::Call(addr) => write!(f, "CALL {:#05X}", addr),
+            Instruction::Pop => write!(f, "RET"),
+            Instruction::SetRegImm(x, imm) => write!(f, "LD {}, {:#04X}", x, imm),
+            Instruction::AddRegImm(x, imm) => write!(f, "ADD {}, {:#04X}", x, imm),
+            Instruction::SetIdxRegImm(addr) => write!(f, "LD I, {:#05X}", addr),
+            Instruction::Display { x, y, n } => write!(f, "DRW {}, {}, {}", x, y, n),
+            Instruction::SkipIfEqImm(x, imm) => write!(f, "SE {}, {:#04X}", x, imm),
+            Instruction::SkipIfNeqImm(x, imm) => write!(f, "SNE {}, {:#04X}", x, imm),
+            Instruction::SkipIfEqReg(x, y) => write!(f, "SE {}, {}", x, y),
+            Instruction::SkipIfNeqReg(x, y) => write!(f, "SNE {}, {}", x, y),
+            Instruction::Set(x, y) => write!(f, "LD {}, {}", x, y),
+            Instruction::BinOr(x, y) => write!(f, "OR {}, {}", x, y),
+            Instruction::BinAnd(x, y) => write!(f, "AND {}, {}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR {}, {}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD {}, {}", x, y),
+            Instruction::SubtractLR(x, y) => write!(f, "SUB {}, {}", x, y),
+            Instruction::SubtractRL(x, y) => write!(f, "SUBN {}, {}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL {}, {}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR {}, {}", x, y),
+            Instruction::Random(x, imm) => write!(f, "RND {}, {:#04X}", x, imm),
+            Instruction::SkipIfKeyPressed(x) => write!(f, "SKP {}", x),
+            Instruction::SkipIfKeyNotPressed(x) => write!(f, "SKNP {}", x),
+            Instruction::GetDelayTimer(x) => write!(f, "LD {}, DT", x),
+            Instruction::SetDelayTimer(x) => write!(f, "LD DT, {}", x),
+            Instruction::SetSoundTimer(x) => write!(f, "LD ST, {}", x),
+            Instruction::GetKey(x) => write!(f, "LD {}, K", x),
+            Instruction::Font(x) => write!(f, "LD F, {}", x),
+            Instruction::BinDecConversion(x) => write!(f, "LD B, {}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::AddToIndex(x) => write!(f, "ADD I, {}", x),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::BigFont(x) => write!(f, "LD HF, {}", x),
+            Instruction::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::SaveRange(x, y) => write!(f, "SAVE {}-{}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD {}-{}", x, y),
+            Instruction::SetIdxRegLong => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SetPitch(x) => write!(f, "PITCH {}", x),
+        }
+    }
+}
+
+/// Decodes the instruction word `ins` that was fetched from `addr`.
+pub fn decode(addr: u16, ins: u16) -> Result<Instruction, Chip8Error> {
+    let unknown = Chip8Error::UnknownOpcode { addr, word: ins };
+    let first_nibble = ins >> 12 & 0x0F;
+    let ins = match first_nibble {
+        0x00 => {
+            let second_nibble = ins >> 8 & 0x0F;
+            match second_nibble {
+                0x00 => {
+                    let second_byte = ins & 0xFF;
+                    match second_byte {
+                        0xC0..=0xCF => Instruction::ScrollDown((ins & 0x0F) as u8),
+                        0xD0..=0xDF => Instruction::ScrollUp((ins & 0x0F) as u8),
+                        0xE0 => Instruction::ClearScreen,
+                        0xEE => Instruction::Pop,
+                        0xFB => Instruction::ScrollRight,
+                        0xFC => Instruction::ScrollLeft,
+                        0xFD => Instruction::Exit,
+                        0xFE => Instruction::LowRes,
+                        0xFF => Instruction::HighRes,
+                        _ => Instruction::MachineCall(ins & 0xFFF),
+                    }
+                }
+                _ => Instruction::MachineCall(ins & 0xFFF),
+            }
+        }
+        0x01 => {
+            let imm = ins & 0xFFF;
+            Instruction::Jump(imm)
+        }
+        0x02 => {
+            let imm = ins & 0xFFF;
+            Instruction::Call(imm)
+        }
+        0x03 => {
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            let imm = (ins & 0xFF) as u8;
+            Instruction::SkipIfEqImm(reg, imm)
+        }
+        0x04 => {
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            let imm = (ins & 0xFF) as u8;
+            Instruction::SkipIfNeqImm(reg, imm)
+        }
+        0x05 => {
+            let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+            match ins & 0x0F {
+                0x0 => Instruction::SkipIfEqReg(x_reg, y_reg),
+                0x2 => Instruction::SaveRange(x_reg, y_reg),
+                0x3 => Instruction::LoadRange(x_reg, y_reg),
+                _ => return Err(unknown),
+            }
+        }
+        0x06 => {
+            let imm = (ins & 0xFF) as u8;
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            Instruction::SetRegImm(reg, imm)
+        }
+        0x07 => {
+            let imm = (ins & 0xFF) as u8;
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            Instruction::AddRegImm(reg, imm)
+        }
+        0x08 => {
+            // Logical and arithmetic instructions
+            match ins & 0x0F {
+                0x00 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::Set(x_reg, y_reg)
+                }
+                0x01 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::BinOr(x_reg, y_reg)
+                }
+                0x02 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::BinAnd(x_reg, y_reg)
+                }
+                0x03 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::Xor(x_reg, y_reg)
+                }
+                0x04 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::Add(x_reg, y_reg)
+                }
+                0x05 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::SubtractLR(x_reg, y_reg)
+                }
+                0x06 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::ShiftRight(x_reg, y_reg)
+                }
+                0x07 => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::SubtractRL(x_reg, y_reg)
+                }
+                0x0E => {
+                    let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+                    let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+                    Instruction::ShiftLeft(x_reg, y_reg)
+                }
+                _ => return Err(unknown),
+            }
+        }
+        0x09 => {
+            let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+            if ins & 0x0F == 0x0 {
+                Instruction::SkipIfNeqReg(x_reg, y_reg)
+            } else {
+                return Err(unknown);
+            }
+        }
+        0x0A => {
+            let imm = ins & 0xFFF;
+            Instruction::SetIdxRegImm(imm)
+        }
+        0x0B => {
+            let imm = ins & 0xFFF;
+            Instruction::JumpWithOffset(imm)
+        }
+        0x0C => {
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            let imm = (ins & 0xFF) as u8;
+            Instruction::Random(reg, imm)
+        }
+        0x0D => {
+            let x_reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            let y_reg = VariableRegister::try_from((ins >> 4 & 0x0F) as u8)?;
+            let imm = (ins & 0x0F) as u8;
+            Instruction::Display {
+                x: x_reg,
+                y: y_reg,
+                n: imm,
+            }
+        }
+        0x0E => {
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            match ins & 0xFF {
+                0x9E => Instruction::SkipIfKeyPressed(reg),
+                0xA1 => Instruction::SkipIfKeyNotPressed(reg),
+                _ => return Err(unknown),
+            }
+        }
+        0x0F => {
+            let reg = VariableRegister::try_from((ins >> 8 & 0x0F) as u8)?;
+            match ins & 0xFF {
+                0x00 if ins == 0xF000 => Instruction::SetIdxRegLong,
+                0x01 => Instruction::SelectPlanes((ins >> 8 & 0x0F) as u8),
+                0x02 if ins == 0xF002 => Instruction::LoadAudioPattern,
+                0x07 => Instruction::GetDelayTimer(reg),
+                0x0A => Instruction::GetKey(reg),
+                0x15 => Instruction::SetDelayTimer(reg),
+                0x18 => Instruction::SetSoundTimer(reg),
+                0x1E => Instruction::AddToIndex(reg),
+                0x29 => Instruction::Font(reg),
+                0x30 => Instruction::BigFont(reg),
+                0x33 => Instruction::BinDecConversion(reg),
+                0x3A => Instruction::SetPitch(reg),
+                0x55 => {
+                    let imm = (ins >> 8 & 0x0F) as u8;
+                    Instruction::Store(imm)
+                }
+                0x65 => {
+                    let imm = (ins >> 8 & 0x0F) as u8;
+                    Instruction::Load(imm)
+                }
+                0x75 => {
+                    let imm = (ins >> 8 & 0x0F) as u8;
+                    Instruction::SaveFlags(imm)
+                }
+                0x85 => {
+                    let imm = (ins >> 8 & 0x0F) as u8;
+                    Instruction::LoadFlags(imm)
+                }
+                _ => return Err(unknown),
+            }
+        }
+        _ => return Err(unknown),
+    };
+    Ok(ins)
+}
@@ -0,0 +1,173 @@
+//! Which host keys stand for the sixteen hex keys.
+//!
+//! The presets put the keypad on the left of the keyboard in the COSMAC VIP arrangement:
+//!
+//! ```text
+//! 1 2 3 C
+//! 4 5 6 D
+//! 7 8 9 E
+//! A 0 B F
+//! ```
+
+use chip_8_interpreter::Keypad;
+use raylib::prelude::*;
+
+use KeyboardKey::*;
+
+/// Host keys for the hex keys `0x0` through `0xF`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [KeyboardKey; 16],
+}
+
+impl Keymap {
+    pub const QWERTY: Keymap = Keymap::from_rows([
+        [KEY_ONE, KEY_TWO, KEY_THREE, KEY_FOUR],
+        [KEY_Q, KEY_W, KEY_E, KEY_R],
+        [KEY_A, KEY_S, KEY_D, KEY_F],
+        [KEY_Z, KEY_X, KEY_C, KEY_V],
+    ]);
+
+    pub const AZERTY: Keymap = Keymap::from_rows([
+        [KEY_ONE, KEY_TWO, KEY_THREE, KEY_FOUR],
+        [KEY_A, KEY_Z, KEY_E, KEY_R],
+        [KEY_Q, KEY_S, KEY_D, KEY_F],
+        [KEY_W, KEY_X, KEY_C, KEY_V],
+    ]);
+
+    pub const DVORAK: Keymap = Keymap::from_rows([
+        [KEY_ONE, KEY_TWO, KEY_THREE, KEY_FOUR],
+        [KEY_APOSTROPHE, KEY_COMMA, KEY_PERIOD, KEY_P],
+        [KEY_A, KEY_O, KEY_E, KEY_U],
+        [KEY_SEMICOLON, KEY_Q, KEY_J, KEY_K],
+    ]);
+
+    /// Names accepted by [`Keymap::from_name`].
+    pub const PRESET_NAMES: [&'static str; 3] = ["qwerty", "azerty", "dvorak"];
+
+    /// A keymap from four rows of four host keys, laid out like the VIP's keypad.
+    const fn from_rows(rows: [[KeyboardKey; 4]; 4]) -> Keymap {
+        // The hex key at each position of the VIP's keypad, row by row.
+        const LAYOUT: [usize; 16] = [
+            0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+        ];
+        let mut keys = [rows[0][0]; 16];
+        let mut i = 0;
+        while i < 16 {
+            keys[LAYOUT[i]] = rows[i / 4][i % 4];
+            i += 1;
+        }
+        Keymap { keys }
+    }
+
+    pub fn from_name(name: &str) -> Option<Keymap> {
+        match name.to_ascii_lowercase().as_str() {
+            "qwerty" => Some(Self::QWERTY),
+            "azerty" => Some(Self::AZERTY),
+            "dvorak" => Some(Self::DVORAK),
+            _ => None,
+        }
+    }
+
+    /// Reads a keymap file, where each line maps a hex key to a host key, such as `5 = W`. Keys
+    /// that aren't mentioned keep their QWERTY mapping, and `#` starts a comment.
+    pub fn parse(config: &str) -> Result<Keymap, String> {
+        let mut keymap = Self::QWERTY;
+        for (i, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (hex_key, host_key) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `<hex key> = <host key>`", i + 1))?;
+            let hex_key = u8::from_str_radix(hex_key.trim(), 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(|| format!("line {}: invalid hex key `{}`", i + 1, hex_key.trim()))?;
+            let host_key = key_from_name(host_key.trim())
+                .ok_or_else(|| format!("line {}: unknown key `{}`", i + 1, host_key.trim()))?;
+            keymap.keys[hex_key as usize] = host_key;
+        }
+        Ok(keymap)
+    }
+
+    /// The hex keys whose host keys are held down.
+    pub fn held(&self, rl: &RaylibHandle) -> Keypad {
+        self.keypad_where(|key| rl.is_key_down(key))
+    }
+
+    /// The hex keys whose host keys went down since the last frame, which may have been released
+    /// again already.
+    pub fn pressed(&self, rl: &RaylibHandle) -> Keypad {
+        self.keypad_where(|key| rl.is_key_pressed(key))
+    }
+
+    fn keypad_where(&self, mut is_down: impl FnMut(KeyboardKey) -> bool) -> Keypad {
+        let mut keypad = Keypad::default();
+        for (hex_key, host_key) in self.keys.iter().enumerate() {
+            keypad.set_held(hex_key as u8, is_down(*host_key));
+        }
+        keypad
+    }
+}
+
+/// Looks up a host key by the character on it or its name, such as `W`, `7`, `comma`, `space` or
+/// `kp5` for the numeric keypad.
+fn key_from_name(name: &str) -> Option<KeyboardKey> {
+    const LETTERS: [KeyboardKey; 26] = [
+        KEY_A, KEY_B, KEY_C, KEY_D, KEY_E, KEY_F, KEY_G, KEY_H, KEY_I, KEY_J, KEY_K, KEY_L, KEY_M,
+        KEY_N, KEY_O, KEY_P, KEY_Q, KEY_R, KEY_S, KEY_T, KEY_U, KEY_V, KEY_W, KEY_X, KEY_Y, KEY_Z,
+    ];
+    const DIGITS: [KeyboardKey; 10] = [
+        KEY_ZERO, KEY_ONE, KEY_TWO, KEY_THREE, KEY_FOUR, KEY_FIVE, KEY_SIX, KEY_SEVEN, KEY_EIGHT,
+        KEY_NINE,
+    ];
+    const KEYPAD_DIGITS: [KeyboardKey; 10] = [
+        KEY_KP_0, KEY_KP_1, KEY_KP_2, KEY_KP_3, KEY_KP_4, KEY_KP_5, KEY_KP_6, KEY_KP_7, KEY_KP_8,
+        KEY_KP_9,
+    ];
+
+    let name = name.to_ascii_lowercase();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return match c {
+            'a'..='z' => Some(LETTERS[(c as u8 - b'a') as usize]),
+            '0'..='9' => Some(DIGITS[(c as u8 - b'0') as usize]),
+            '\'' => Some(KEY_APOSTROPHE),
+            ',' => Some(KEY_COMMA),
+            '-' => Some(KEY_MINUS),
+            '.' => Some(KEY_PERIOD),
+            '/' => Some(KEY_SLASH),
+            ';' => Some(KEY_SEMICOLON),
+            '=' => Some(KEY_EQUAL),
+            '[' => Some(KEY_LEFT_BRACKET),
+            '\\' => Some(KEY_BACKSLASH),
+            ']' => Some(KEY_RIGHT_BRACKET),
+            _ => None,
+        };
+    }
+    if let Some(digit) = name
+        .strip_prefix("kp")
+        .and_then(|d| d.parse::<usize>().ok())
+    {
+        return KEYPAD_DIGITS.get(digit).copied();
+    }
+    match name.as_str() {
+        "apostrophe" => Some(KEY_APOSTROPHE),
+        "comma" => Some(KEY_COMMA),
+        "minus" => Some(KEY_MINUS),
+        "period" => Some(KEY_PERIOD),
+        "slash" => Some(KEY_SLASH),
+        "semicolon" => Some(KEY_SEMICOLON),
+        "equal" => Some(KEY_EQUAL),
+        "space" => Some(KEY_SPACE),
+        "enter" => Some(KEY_ENTER),
+        "tab" => Some(KEY_TAB),
+        "up" => Some(KEY_UP),
+        "down" => Some(KEY_DOWN),
+        "left" => Some(KEY_LEFT),
+        "right" => Some(KEY_RIGHT),
+        _ => None,
+    }
+}
@@ -0,0 +1,39 @@
+//! The hex keypad: sixteen keys, `0x0` to `0xF`.
+
+/// Which of the sixteen hex keys are held down.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Keypad {
+    /// Bit `n` is set while key `n` is held.
+    held: u16,
+}
+
+impl Keypad {
+    /// A keypad with the keys in the bitmask `held` held down.
+    pub fn with_held(held: u16) -> Self {
+        Self { held }
+    }
+
+    /// The held keys as a bitmask, with bit `n` for key `n`.
+    pub fn held(&self) -> u16 {
+        self.held
+    }
+
+    /// Whether `key` is held. Only the low nibble of `key` is used, like the VIP's keypad latch.
+    pub fn is_held(&self, key: u8) -> bool {
+        self.held & (1 << (key & 0x0F)) != 0
+    }
+
+    pub fn set_held(&mut self, key: u8, held: bool) {
+        let bit = 1 << (key & 0x0F);
+        if held {
+            self.held |= bit;
+        } else {
+            self.held &= !bit;
+        }
+    }
+
+    /// The lowest held key, if any.
+    pub fn first_held(&self) -> Option<u8> {
+        (self.held != 0).then(|| self.held.trailing_zeros() as u8)
+    }
+}
@@ -0,0 +1,36 @@
+//! A headless CHIP-8 machine that can be embedded without a window.
+//!
+//! The raylib frontend in `main.rs` is just one client of [`Chip8`].
+
+pub mod animation;
+pub mod asm;
+pub mod audio;
+mod chip8;
+pub mod disasm;
+mod error;
+pub mod filter;
+pub mod gdb;
+pub mod image;
+mod instruction;
+mod keypad;
+pub mod movie;
+mod quirks;
+pub mod rewind;
+pub mod state;
+pub mod upscale;
+pub mod vip;
+
+pub use chip8::Chip8;
+pub use error::Chip8Error;
+pub use instruction::{decode, Instruction, VariableRegister};
+pub use keypad::Keypad;
+pub use quirks::Quirks;
+
+/// The address programs are loaded at.
+pub const PROGRAM_START: u16 = 0x200;
+pub const GRID_WIDTH: usize = 64;
+pub const GRID_HEIGHT: usize = 32;
+/// Display width in the SUPER-CHIP high resolution mode.
+pub const HIRES_WIDTH: usize = 128;
+/// Display height in the SUPER-CHIP high resolution mode.
+pub const HIRES_HEIGHT: usize = 64;
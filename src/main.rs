@@ -1,736 +1,1038 @@
-use raylib::input::key_from_i32;
+use chip_8_interpreter::animation;
+use chip_8_interpreter::audio::{Capture, Sound, Tone, Waveform, DEFAULT_SAMPLE_RATE};
+use chip_8_interpreter::filter::FrameFilter;
+use chip_8_interpreter::gdb::GdbStub;
+use chip_8_interpreter::movie::{Frame, Movie};
+use chip_8_interpreter::rewind::Rewind;
+use chip_8_interpreter::state::SaveState;
+use chip_8_interpreter::vip::{self, CosmacVip};
+use chip_8_interpreter::{asm, disasm};
+use chip_8_interpreter::{Chip8, Chip8Error, Keypad, Quirks, GRID_HEIGHT, GRID_WIDTH};
 use raylib::prelude::*;
 
-use std::collections::HashMap;
+mod capture;
+mod debugger;
+mod display;
+mod gamepad;
+mod headless;
+mod keymap;
+mod overlay;
+mod renderer;
+mod speaker;
+
+use capture::{Picture, Recording, CAPTURE_KEY};
+use debugger::Debugger;
+use display::DisplayOptions;
+use gamepad::{GamepadProfile, Remapper};
+use keymap::Keymap;
+use overlay::KeypadOverlay;
+use renderer::Renderer;
+use speaker::Speaker;
+
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-const RECT_LEN: usize = 20;
-const GRID_WIDTH: usize = 64;
-const GRID_HEIGHT: usize = 32;
+/// How many window pixels wide and high a display pixel is in a new window outside of debug mode.
+const DISPLAY_SCALE: usize = 20;
 const TIME_STEP_MS: f64 = 100.0;
+const TIMER_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum VariableRegister {
-    V0,
-    V1,
-    V2,
-    V3,
-    V4,
-    V5,
-    V6,
-    V7,
-    V8,
-    V9,
-    VA,
-    VB,
-    VC,
-    VD,
-    VE,
-    VF,
-}
+/// Keys for the save state slots. Pressed alone they load the slot, with shift they save to it.
+const SLOT_KEYS: [KeyboardKey; 9] = [
+    KeyboardKey::KEY_F1,
+    KeyboardKey::KEY_F2,
+    KeyboardKey::KEY_F3,
+    KeyboardKey::KEY_F4,
+    KeyboardKey::KEY_F5,
+    KeyboardKey::KEY_F6,
+    KeyboardKey::KEY_F7,
+    KeyboardKey::KEY_F8,
+    KeyboardKey::KEY_F9,
+];
 
-impl From<u8> for VariableRegister {
-    fn from(value: u8) -> Self {
-        match value {
-            0x0 => Self::V0,
-            0x1 => Self::V1,
-            0x2 => Self::V2,
-            0x3 => Self::V3,
-            0x4 => Self::V4,
-            0x5 => Self::V5,
-            0x6 => Self::V6,
-            0x7 => Self::V7,
-            0x8 => Self::V8,
-            0x9 => Self::V9,
-            0xA => Self::VA,
-            0xB => Self::VB,
-            0xC => Self::VC,
-            0xD => Self::VD,
-            0xE => Self::VE,
-            0xF => Self::VF,
-            _ => panic!("invalid register"),
-        }
-    }
-}
+const MUTE_KEY: KeyboardKey = KeyboardKey::KEY_M;
+const FULLSCREEN_KEY: KeyboardKey = KeyboardKey::KEY_F11;
+/// Held to play the game backwards.
+const REWIND_KEY: KeyboardKey = KeyboardKey::KEY_BACKSPACE;
+const DEFAULT_REWIND_SECONDS: usize = 10;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Instruction {
-    ClearScreen,
-    Jump(u16),
-    JumpWithOffset(u16),
-    Call(u16),
-    Pop,
-    SetRegImm(VariableRegister, u8),
-    AddRegImm(VariableRegister, u8),
-    SetIdxRegImm(u16),
-    Display {
-        x: VariableRegister,
-        y: VariableRegister,
-        n: u8,
-    },
-    SkipIfEqImm(VariableRegister, u8),
-    SkipIfNeqImm(VariableRegister, u8),
-    SkipIfEqReg(VariableRegister, VariableRegister),
-    SkipIfNeqReg(VariableRegister, VariableRegister),
-    Set(VariableRegister, VariableRegister),
-    BinOr(VariableRegister, VariableRegister),
-    BinAnd(VariableRegister, VariableRegister),
-    Xor(VariableRegister, VariableRegister),
-    Add(VariableRegister, VariableRegister),
-    SubtractLR(VariableRegister, VariableRegister),
-    SubtractRL(VariableRegister, VariableRegister),
-    ShiftLeft(VariableRegister, VariableRegister),
-    ShiftRight(VariableRegister, VariableRegister),
-    Random(VariableRegister, u8),
-    SkipIfKeyPressed(VariableRegister),
-    SkipIfKeyNotPressed(VariableRegister),
-    GetDelayTimer(VariableRegister),
-    SetDelayTimer(VariableRegister),
-    SetSoundTimer(VariableRegister),
-    GetKey(VariableRegister),
-    Font(VariableRegister),
-    BinDecConversion(VariableRegister),
-    Store(u8),
-    Load(u8),
-    AddToIndex(VariableRegister),
-}
+const USAGE: &str = "usage: chip-8-interpreter [--quirks <preset>] [--quirk <flag>=<on|off>] \
+                     [--debug] [--gdb <port>] \
+                     [--load-state <file>] [--rewind <seconds>] [--record <movie> | --replay <movie>] \
+                     [--beep-frequency <hz>] [--beep-volume <percent>] [--beep-waveform <name>] \
+                     [--no-audio] [--audio-out <wav>] [--keymap <preset or file>] [--keypad] \
+                     [--palette <name or colours>] [--filter <name>] [--scaler <name>] [--border <colour>] [--pixel-grid] [--fullscreen] \
+                     [--display-config <file>] [--screenshot <png>] [--record-video <gif or png>] \
+                     [--capture-scale <n>] \
+                     [--vip <interpreter> [--vip-monitor <monitor>]] <rom>
+       chip-8-interpreter disasm [--octo] [--quirks <preset>] <rom>
+       chip-8-interpreter asm <source> [-o <rom>]
+       chip-8-interpreter headless [--quirks <preset>] [--quirk <flag>=<on|off>] \
+                     [--frames <n> | --instructions <n>] \
+                     [--ipf <n>] [--keys <script> | --replay <movie>] \
+                     [-o <image> | --compare <golden>] [--palette <name or colours>] \
+                     [--filter <name>] [--scaler <name>] [--audio-out <wav>] \
+                     [--beep-frequency <hz>] [--beep-volume <percent>] [--beep-waveform <name>] \
+                     [--record-video <gif or png>] [--capture-scale <n>] <rom>";
 
-pub fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
-    dbg!(&args);
-    let rom = std::fs::read(&args[1]).expect("failed to read ROM at given path");
-    run(&rom);
+struct Options {
+    rom_path: String,
+    quirks: Quirks,
+    /// Show the debugger panels and start paused.
+    debug: bool,
+    /// Wait for a GDB remote serial protocol connection on this port.
+    gdb_port: Option<u16>,
+    /// Start from this save state instead of the beginning of the ROM.
+    load_state: Option<String>,
+    /// How far back the rewind buffer goes.
+    rewind_seconds: usize,
+    /// Record the session's input to this movie file.
+    record: Option<String>,
+    /// Replay the session in this movie file instead of reading the keyboard.
+    replay: Option<String>,
+    tone: Tone,
+    /// Whether to open the audio device at all.
+    audio: bool,
+    /// Render the sound to this WAV file.
+    audio_out: Option<String>,
+    keymap: Keymap,
+    /// Show the clickable keypad beside the display.
+    keypad: bool,
+    display: DisplayOptions,
+    /// Save a screenshot here when the window is closed.
+    screenshot: Option<String>,
+    /// Record the display to this GIF or APNG from the start.
+    record_video: Option<String>,
+    /// How many times larger than the display screenshots and recordings are.
+    capture_scale: usize,
+    /// Run on an emulated COSMAC VIP with this CHIP-8 interpreter image instead.
+    vip_interpreter: Option<String>,
+    vip_monitor: Option<String>,
 }
 
-fn run(rom: &[u8]) {
-    let mut memory = vec![0u8; 4096];
-
-    let fonts = [
-        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-        0x20, 0x60, 0x20, 0x20, 0x70, // 1
-        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-    ];
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut rom_path = None;
+        let mut quirks = Quirks::default();
+        // Applied over the preset, whichever order they were given in.
+        let mut quirk_overrides = Vec::new();
+        let mut debug = false;
+        let mut gdb_port = None;
+        let mut load_state = None;
+        let mut rewind_seconds = DEFAULT_REWIND_SECONDS;
+        let mut record = None;
+        let mut replay = None;
+        let mut tone = Tone::default();
+        let mut audio = true;
+        let mut audio_out = None;
+        let mut keymap = Keymap::QWERTY;
+        let mut keypad = false;
+        let mut display_config = None;
+        let mut screenshot = None;
+        let mut record_video = None;
+        let mut capture_scale = capture::DEFAULT_SCALE;
+        // Applied over the config file, whichever order they were given in.
+        let mut display_settings = Vec::new();
+        let mut vip_interpreter = None;
+        let mut vip_monitor = None;
 
-    memory[0x0..fonts.len()].copy_from_slice(&fonts);
-    memory[0x200..(0x200 + rom.len())].copy_from_slice(rom);
-
-    let mut index_register = 0u16;
-
-    let mut variable_registers = HashMap::with_capacity(16);
-    variable_registers.insert(VariableRegister::V0, 0u8);
-    variable_registers.insert(VariableRegister::V1, 0);
-    variable_registers.insert(VariableRegister::V2, 0);
-    variable_registers.insert(VariableRegister::V3, 0);
-    variable_registers.insert(VariableRegister::V4, 0);
-    variable_registers.insert(VariableRegister::V5, 0);
-    variable_registers.insert(VariableRegister::V6, 0);
-    variable_registers.insert(VariableRegister::V7, 0);
-    variable_registers.insert(VariableRegister::V8, 0);
-    variable_registers.insert(VariableRegister::V9, 0);
-    variable_registers.insert(VariableRegister::VA, 0);
-    variable_registers.insert(VariableRegister::VB, 0);
-    variable_registers.insert(VariableRegister::VC, 0);
-    variable_registers.insert(VariableRegister::VD, 0);
-    variable_registers.insert(VariableRegister::VE, 0);
-    variable_registers.insert(VariableRegister::VF, 0);
-
-    let mut program_counter = 0x200u16;
-
-    let mut display = [false; GRID_WIDTH * GRID_HEIGHT];
-    let mut stack: Vec<u16> = Vec::new();
-
-    let (mut rl, thread) = raylib::init()
-        .size(
-            (GRID_WIDTH * RECT_LEN) as i32,
-            (GRID_HEIGHT * RECT_LEN) as i32,
-        )
-        .title("CHIP-8 Interpreter")
-        .build();
-
-    let mut key_downs = HashMap::with_capacity(16);
-    key_downs.insert(KeyboardKey::KEY_ONE, false);
-    key_downs.insert(KeyboardKey::KEY_TWO, false);
-    key_downs.insert(KeyboardKey::KEY_THREE, false);
-    key_downs.insert(KeyboardKey::KEY_FOUR, false);
-    key_downs.insert(KeyboardKey::KEY_Q, false);
-    key_downs.insert(KeyboardKey::KEY_W, false);
-    key_downs.insert(KeyboardKey::KEY_E, false);
-    key_downs.insert(KeyboardKey::KEY_R, false);
-    key_downs.insert(KeyboardKey::KEY_A, false);
-    key_downs.insert(KeyboardKey::KEY_S, false);
-    key_downs.insert(KeyboardKey::KEY_D, false);
-    key_downs.insert(KeyboardKey::KEY_F, false);
-    key_downs.insert(KeyboardKey::KEY_Z, false);
-    key_downs.insert(KeyboardKey::KEY_X, false);
-    key_downs.insert(KeyboardKey::KEY_C, false);
-    key_downs.insert(KeyboardKey::KEY_V, false);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = args.next().ok_or("--quirks needs a preset name")?;
+                    quirks = Quirks::from_name(name).ok_or_else(|| {
+                        format!(
+                            "unknown quirks preset `{}`, expected one of: {}",
+                            name,
+                            Quirks::PRESET_NAMES.join(", ")
+                        )
+                    })?;
+                }
+                "--quirk" => {
+                    let setting = args.next().ok_or("--quirk needs <flag>=<on|off>")?;
+                    quirk_overrides.push(setting);
+                }
+                "--debug" => debug = true,
+                "--gdb" => {
+                    let port = args.next().ok_or("--gdb needs a port")?;
+                    let port = port
+                        .parse()
+                        .map_err(|_| format!("invalid port `{}`", port))?;
+                    gdb_port = Some(port);
+                }
+                "--load-state" => {
+                    let path = args.next().ok_or("--load-state needs a save state path")?;
+                    load_state = Some(path.clone());
+                }
+                "--rewind" => {
+                    let seconds = args.next().ok_or("--rewind needs a number of seconds")?;
+                    rewind_seconds = seconds
+                        .parse()
+                        .map_err(|_| format!("invalid number of seconds `{}`", seconds))?;
+                }
+                "--record" => {
+                    let path = args.next().ok_or("--record needs a movie path")?;
+                    record = Some(path.clone());
+                }
+                "--replay" => {
+                    let path = args.next().ok_or("--replay needs a movie path")?;
+                    replay = Some(path.clone());
+                }
+                "--beep-frequency" => {
+                    let hz = args.next().ok_or("--beep-frequency needs a frequency")?;
+                    tone.frequency = parse_frequency(hz)?;
+                }
+                "--beep-volume" => {
+                    let percent = args.next().ok_or("--beep-volume needs a percentage")?;
+                    tone.volume = parse_volume(percent)?;
+                }
+                "--beep-waveform" => {
+                    let name = args.next().ok_or("--beep-waveform needs a waveform name")?;
+                    tone.waveform = parse_waveform(name)?;
+                }
+                "--no-audio" => audio = false,
+                "--audio-out" => {
+                    let path = args.next().ok_or("--audio-out needs a WAV file path")?;
+                    audio_out = Some(path.clone());
+                }
+                "--keymap" => {
+                    let name = args
+                        .next()
+                        .ok_or("--keymap needs a preset name or a file")?;
+                    keymap = match Keymap::from_name(name) {
+                        Some(keymap) => keymap,
+                        None => {
+                            let config = std::fs::read_to_string(name).map_err(|err| {
+                                format!(
+                                    "`{}` is neither a keymap preset ({}) nor a readable file: {}",
+                                    name,
+                                    Keymap::PRESET_NAMES.join(", "),
+                                    err
+                                )
+                            })?;
+                            Keymap::parse(&config).map_err(|err| format!("{}: {}", name, err))?
+                        }
+                    };
+                }
+                "--keypad" => keypad = true,
+                "--palette" => {
+                    let palette = args
+                        .next()
+                        .ok_or("--palette needs a palette name or colours")?;
+                    display_settings.push(("palette", palette.as_str()));
+                }
+                "--filter" => {
+                    let filter = args.next().ok_or("--filter needs a filter name")?;
+                    display_settings.push(("filter", filter.as_str()));
+                }
+                "--scaler" => {
+                    let scaler = args.next().ok_or("--scaler needs a scaler name")?;
+                    display_settings.push(("scaler", scaler.as_str()));
+                }
+                "--border" => {
+                    let color = args.next().ok_or("--border needs a colour")?;
+                    display_settings.push(("border", color.as_str()));
+                }
+                "--pixel-grid" => display_settings.push(("pixel-grid", "true")),
+                "--fullscreen" => display_settings.push(("fullscreen", "true")),
+                "--screenshot" => {
+                    let path = args.next().ok_or("--screenshot needs a PNG file path")?;
+                    screenshot = Some(path.clone());
+                }
+                "--record-video" => {
+                    let path = args
+                        .next()
+                        .ok_or("--record-video needs a GIF or PNG file path")?;
+                    if animation::Format::from_path(path).is_none() {
+                        return Err(format!("`{}` must end in .gif or .png", path));
+                    }
+                    record_video = Some(path.clone());
+                }
+                "--capture-scale" => {
+                    let scale = args.next().ok_or("--capture-scale needs a scale")?;
+                    capture_scale = capture::parse_scale(scale)?;
+                }
+                "--display-config" => {
+                    let path = args
+                        .next()
+                        .ok_or("--display-config needs a config file path")?;
+                    display_config = Some(path);
+                }
+                "--vip" => {
+                    let path = args.next().ok_or("--vip needs an interpreter image path")?;
+                    vip_interpreter = Some(path.clone());
+                }
+                "--vip-monitor" => {
+                    let path = args
+                        .next()
+                        .ok_or("--vip-monitor needs a monitor ROM path")?;
+                    vip_monitor = Some(path.clone());
+                }
+                _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
 
-    let mut prev_time = Instant::now();
-    let mut delay_timer = Duration::from_millis(0);
-    let mut sound_timer = Duration::from_millis(0);
+        for setting in quirk_overrides {
+            override_quirk(&mut quirks, setting)?;
+        }
+        if vip_monitor.is_some() && vip_interpreter.is_none() {
+            return Err("--vip-monitor needs --vip".to_string());
+        }
+        if (debug || gdb_port.is_some()) && vip_interpreter.is_some() {
+            return Err("--debug and --gdb can't be used with --vip".to_string());
+        }
+        let mut display = DisplayOptions::default();
+        if let Some(path) = display_config {
+            let config = std::fs::read_to_string(path)
+                .map_err(|err| format!("failed to read {}: {}", path, err))?;
+            display
+                .parse_config(&config)
+                .map_err(|err| format!("{}: {}", path, err))?;
+        }
+        for (setting, value) in display_settings {
+            display.set(setting, value)?;
+        }
 
-    while !rl.window_should_close() {
-        for (key, is_pressed) in &mut key_downs {
-            *is_pressed = rl.is_key_pressed(*key);
+        if keypad && vip_interpreter.is_some() {
+            return Err("--keypad can't be used with --vip".to_string());
+        }
+        if (screenshot.is_some() || record_video.is_some()) && vip_interpreter.is_some() {
+            return Err("--screenshot and --record-video can't be used with --vip".to_string());
+        }
+        if load_state.is_some() && vip_interpreter.is_some() {
+            return Err("--load-state can't be used with --vip".to_string());
+        }
+        if record.is_some() && replay.is_some() {
+            return Err("--record can't be used with --replay".to_string());
+        }
+        if (record.is_some() || replay.is_some())
+            && (debug || gdb_port.is_some() || load_state.is_some() || vip_interpreter.is_some())
+        {
+            return Err(
+                "--record and --replay can't be used with --debug, --gdb, --load-state or --vip"
+                    .to_string(),
+            );
+        }
+        if debug && gdb_port.is_some() {
+            return Err("--debug can't be used with --gdb".to_string());
         }
 
-        let current_time = Instant::now();
-        let delta = current_time - prev_time;
+        Ok(Options {
+            rom_path: rom_path.ok_or("missing ROM path")?,
+            quirks,
+            debug,
+            gdb_port,
+            load_state,
+            rewind_seconds,
+            record,
+            replay,
+            tone,
+            audio,
+            audio_out,
+            keymap,
+            keypad,
+            display,
+            screenshot,
+            record_video,
+            capture_scale,
+            vip_interpreter,
+            vip_monitor,
+        })
+    }
+}
 
-        delay_timer = delay_timer.saturating_sub(delta);
-        sound_timer = sound_timer.saturating_sub(delta);
-
-        if delta > std::time::Duration::from_millis(TIME_STEP_MS as u64) {
-            let ins = fetch(&mut memory, &mut program_counter);
-            let ins = decode(ins);
-            println!("{:X?}", ins);
-            execute(
-                &mut memory,
-                &mut display,
-                &mut program_counter,
-                &mut stack,
-                &mut variable_registers,
-                &mut index_register,
-                &mut key_downs,
-                &mut delay_timer,
-                &mut sound_timer,
-                ins,
+pub fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("disasm") {
+        disasm(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("asm") {
+        assemble(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("headless") {
+        headless::run(&args[2..]);
+        return;
+    }
+    let options = match Options::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let rom = std::fs::read(&options.rom_path).expect("failed to read ROM at given path");
+    match &options.vip_interpreter {
+        Some(interpreter_path) => {
+            let interpreter =
+                std::fs::read(interpreter_path).expect("failed to read interpreter image");
+            let monitor = options
+                .vip_monitor
+                .as_ref()
+                .map(|path| std::fs::read(path).expect("failed to read monitor ROM"));
+            run_vip(
+                &rom,
+                &interpreter,
+                monitor.as_deref(),
+                &options.keymap,
+                &options.display,
             );
-
-            prev_time = current_time;
         }
-
-        let mut d = rl.begin_drawing(&thread);
-        d.clear_background(Color::BLACK);
-        draw_grid(&display, &mut d);
+        None => run(&rom, &options),
     }
 }
 
-fn draw_grid(display: &[bool; GRID_WIDTH * GRID_HEIGHT], d: &mut RaylibDrawHandle) {
-    let mut gx = 0;
-    let mut gy = 0;
-    for y in 0..GRID_HEIGHT {
-        for x in 0..GRID_WIDTH {
-            match display[(y * GRID_WIDTH + x) as usize] {
-                true => d.draw_rectangle(
-                    (gx * RECT_LEN) as i32,
-                    (gy * RECT_LEN) as i32,
-                    RECT_LEN as i32,
-                    RECT_LEN as i32,
-                    Color::WHITE,
-                ),
-                false => d.draw_rectangle(
-                    (gx * RECT_LEN) as i32,
-                    (gy * RECT_LEN) as i32,
-                    RECT_LEN as i32,
-                    RECT_LEN as i32,
-                    Color::BLACK,
-                ),
+/// Prints the disassembly of a ROM, as a listing or with `--octo` as Octo source.
+fn disasm(args: &[String]) {
+    let usage = |message: String| -> ! {
+        eprintln!("error: {}\n{}", message, USAGE);
+        std::process::exit(2);
+    };
+    let mut octo = false;
+    let mut quirks = Quirks::default();
+    let mut rom_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--octo" => octo = true,
+            "--quirks" => {
+                let Some(name) = args.next() else {
+                    usage("--quirks needs a preset name".to_string());
+                };
+                quirks = Quirks::from_name(name).unwrap_or_else(|| {
+                    usage(format!(
+                        "unknown quirks preset `{}`, expected one of: {}",
+                        name,
+                        Quirks::PRESET_NAMES.join(", ")
+                    ))
+                });
             }
-            gx += 1;
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage("expected `disasm [--octo] [--quirks <preset>] <rom>`".to_string()),
         }
-        gx = 0;
-        gy += 1;
     }
+    let Some(rom_path) = rom_path else {
+        usage("expected `disasm [--octo] [--quirks <preset>] <rom>`".to_string());
+    };
+    let rom = std::fs::read(rom_path).expect("failed to read ROM at given path");
+    if octo {
+        print!("{}", disasm::octo(&rom));
+    } else {
+        print!("{}", disasm::listing(&rom, &quirks));
+    }
+}
+
+/// Assembles Octo source into a ROM, and writes the debug info next to it with a `.sym`
+/// extension.
+fn assemble(args: &[String]) {
+    let (source_path, rom_path) = match args {
+        [source_path] => (source_path, Path::new(source_path).with_extension("ch8")),
+        [source_path, flag, rom_path] if flag == "-o" => (source_path, PathBuf::from(rom_path)),
+        _ => {
+            eprintln!("error: expected `asm <source> [-o <rom>]`\n{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let source = std::fs::read_to_string(source_path).expect("failed to read source file");
+    let assembly = match asm::assemble(&source) {
+        Ok(assembly) => assembly,
+        Err(err) => {
+            eprintln!("error: {}: {}", source_path, err);
+            std::process::exit(1);
+        }
+    };
+    std::fs::write(&rom_path, assembly.rom()).expect("failed to write ROM");
+    std::fs::write(rom_path.with_extension("sym"), assembly.debug_info())
+        .expect("failed to write debug info");
 }
 
-fn fetch(memory: &mut [u8], program_counter: &mut u16) -> u16 {
-    let ins = u16::from_be_bytes([
-        memory[*program_counter as usize],
-        memory[(*program_counter + 1) as usize],
-    ]);
-    *program_counter += 2;
-    ins
+/// The size of a new window outside of debug mode.
+const WINDOW_WIDTH: i32 = (GRID_WIDTH * DISPLAY_SCALE) as i32;
+const WINDOW_HEIGHT: i32 = (GRID_HEIGHT * DISPLAY_SCALE) as i32;
+
+/// Opens the window, which can be resized unless its layout is fixed, as the debugger's is.
+fn init_window(
+    width: i32,
+    height: i32,
+    resizable: bool,
+    display: &DisplayOptions,
+) -> (RaylibHandle, RaylibThread) {
+    let mut builder = raylib::init();
+    builder.size(width, height).title("CHIP-8 Interpreter");
+    if resizable {
+        builder.resizable();
+    }
+    let (mut rl, thread) = builder.build();
+    if display.fullscreen {
+        rl.toggle_fullscreen();
+    }
+    (rl, thread)
 }
 
-fn decode(ins: u16) -> Instruction {
-    let first_nibble = ins >> 12 & 0x0F;
-    match first_nibble {
-        0x00 => {
-            let second_nibble = ins >> 8 & 0x0F;
-            match second_nibble {
-                0x00 => {
-                    let second_byte = ins & 0xFF;
-                    match second_byte {
-                        0xE0 => Instruction::ClearScreen,
-                        0xEE => Instruction::Pop,
-                        _ => unimplemented!("unknown instruction"),
-                    }
-                }
-                _ => unimplemented!("unknown instruction"),
-            }
-        }
-        0x01 => {
-            let imm = ins & 0xFFF;
-            Instruction::Jump(imm)
-        }
-        0x02 => {
-            let imm = ins & 0xFFF;
-            Instruction::Call(imm)
-        }
-        0x03 => {
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            let imm = (ins & 0xFF) as u8;
-            Instruction::SkipIfEqImm(reg, imm)
-        }
-        0x04 => {
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            let imm = (ins & 0xFF) as u8;
-            Instruction::SkipIfNeqImm(reg, imm)
-        }
-        0x05 => {
-            let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-            if ins & 0x0F == 0x0 {
-                Instruction::SkipIfEqReg(x_reg, y_reg)
-            } else {
-                unimplemented!("unknown instruction")
-            }
+/// Where a `display_width` by `display_height` display goes in the window, letterboxed at a whole
+/// multiple of its size and leaving `keypad_width` free on the right.
+fn display_bounds(
+    rl: &RaylibHandle,
+    keypad_width: i32,
+    display_width: usize,
+    display_height: usize,
+) -> Rectangle {
+    let area = Rectangle::new(
+        0.0,
+        0.0,
+        (rl.get_screen_width() - keypad_width).max(1) as f32,
+        rl.get_screen_height() as f32,
+    );
+    display::letterbox(area, display_width, display_height)
+}
+
+fn run(rom: &[u8], options: &Options) {
+    let mut chip8 = Chip8::with_quirks(options.quirks);
+    if let Err(err) = chip8.load_rom(rom) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+    if let Some(path) = &options.load_state {
+        chip8 = load_state(rom, Path::new(path)).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        });
+    }
+
+    let replay = options.replay.as_ref().map(|path| {
+        let movie = read_movie(Path::new(path)).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        });
+        chip8 = movie.start(rom).unwrap_or_else(|err| {
+            eprintln!("error: {}: {}", path, err);
+            std::process::exit(1);
+        });
+        movie
+    });
+    let mut replay_frame = 0;
+    let mut recording = options.record.as_ref().map(|_| Movie::new(&chip8, rom));
+    // Going back in time or loading a state would make the movie impossible to replay.
+    let movie_active = replay.is_some() || recording.is_some();
+
+    let gdb_error = |err: std::io::Error| -> ! {
+        eprintln!("error: gdb connection: {}", err);
+        std::process::exit(1);
+    };
+    let mut gdb = options.gdb_port.map(|port| {
+        println!("waiting for a debugger on 127.0.0.1:{}", port);
+        GdbStub::listen(port).unwrap_or_else(|err| gdb_error(err))
+    });
+
+    let mut debugger = options.debug.then(|| Debugger::new(&chip8));
+    let (width, height) = match debugger {
+        Some(_) => (debugger::WINDOW_WIDTH, debugger::WINDOW_HEIGHT),
+        None => (WINDOW_WIDTH, WINDOW_HEIGHT),
+    };
+    let mut overlay = options.keypad.then(|| KeypadOverlay::new(width, 0));
+    let keypad_width = overlay.as_ref().map_or(0, |_| overlay::WIDTH);
+    let (mut rl, thread) = init_window(
+        width + keypad_width,
+        height,
+        debugger.is_none(),
+        &options.display,
+    );
+    let mut capture = options
+        .audio_out
+        .as_ref()
+        .map(|_| Capture::new(options.tone, DEFAULT_SAMPLE_RATE));
+    let mut speaker = if options.audio {
+        Speaker::open(&thread, options.tone)
+    } else {
+        Speaker::silent(options.tone)
+    };
+
+    let mut prev_time = Instant::now();
+    let mut prev_timer_tick = Instant::now();
+    let mut error: Option<Chip8Error> = None;
+    let mut rewind = Rewind::new(if movie_active {
+        0
+    } else {
+        options.rewind_seconds
+    });
+    // Keys are latched once per frame so that movies can record them. A frame sees the keys held
+    // at its start, plus any tapped and released since the last one so that quick taps count.
+    let mut frame = Frame { keys: 0, steps: 0 };
+    let mut pressed = 0u16;
+
+    let profile_path = GamepadProfile::path(&options.rom_path);
+    let mut profile = GamepadProfile::load(&profile_path).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    });
+    let mut remapper = Remapper::default();
+    let mut renderer = Renderer::new(
+        options
+            .display
+            .pixel_grid
+            .then_some(options.display.palette[0]),
+    );
+    let mut frame_filter = FrameFilter::new(options.display.filter, options.display.rgb_palette());
+    let mut picture = Picture::default();
+    let scaler = options.display.scaler;
+    let mut video = options.record_video.as_ref().map(|path| {
+        Recording::start(Path::new(path), scaler.factor(), options.capture_scale).unwrap_or_else(
+            |err| {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            },
+        )
+    });
+
+    while !rl.window_should_close() {
+        if rl.is_key_pressed(FULLSCREEN_KEY) {
+            rl.toggle_fullscreen();
         }
-        0x06 => {
-            let imm = (ins & 0xFF) as u8;
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            Instruction::SetRegImm(reg, imm)
-        }
-        0x07 => {
-            let imm = (ins & 0xFF) as u8;
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            Instruction::AddRegImm(reg, imm)
-        }
-        0x08 => {
-            // Logical and arithmetic instructions
-            match ins & 0x0F {
-                0x00 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::Set(x_reg, y_reg)
-                }
-                0x01 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::BinOr(x_reg, y_reg)
-                }
-                0x02 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::BinAnd(x_reg, y_reg)
-                }
-                0x03 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::Xor(x_reg, y_reg)
-                }
-                0x04 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::Add(x_reg, y_reg)
-                }
-                0x05 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::SubtractLR(x_reg, y_reg)
-                }
-                0x06 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::ShiftRight(x_reg, y_reg)
-                }
-                0x07 => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::SubtractRL(x_reg, y_reg)
-                }
-                0x0E => {
-                    let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-                    let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-                    Instruction::ShiftLeft(x_reg, y_reg)
-                }
-                _ => unimplemented!("unknown instruction"),
-            }
+        let display_bounds = match debugger {
+            Some(_) => debugger::DISPLAY_BOUNDS,
+            None => display_bounds(&rl, keypad_width, chip8.width(), chip8.height()),
+        };
+        if let Some(overlay) = &mut overlay {
+            overlay.move_to(rl.get_screen_width() - overlay::WIDTH, 0);
         }
-        0x09 => {
-            let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            let y_reg = VariableRegister::from((ins >> 4 & 0x0F) as u8);
-            if ins & 0x0F == 0x0 {
-                Instruction::SkipIfNeqReg(x_reg, y_reg)
-            } else {
-                unimplemented!("unknown instruction")
+
+        if remapper.update(&rl, &options.keymap, &mut profile) {
+            match profile.save(&profile_path) {
+                Ok(()) => println!("saved gamepad profile to {}", profile_path.display()),
+                Err(err) => eprintln!("error: {}", err),
             }
         }
-        0x0A => {
-            let imm = ins & 0xFFF;
-            Instruction::SetIdxRegImm(imm)
-        }
-        0x0B => {
-            let imm = ins & 0xFFF;
-            Instruction::JumpWithOffset(imm)
-        }
-        0x0C => {
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            let imm = (ins & 0xFF) as u8;
-            Instruction::Random(reg, imm)
-        }
-        0x0D => {
-            let x_reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            let y_reg = VariableRegister::from((ins >> 12 & 0x0F) as u8);
-            let imm = (ins & 0x0F) as u8;
-            Instruction::Display {
-                x: x_reg,
-                y: y_reg,
-                n: imm,
+        // Keys pressed on the remapping screen are for the profile, not the game.
+        if !remapper.is_open() {
+            pressed |= options.keymap.pressed(&rl).held();
+            if let Some(overlay) = &overlay {
+                pressed |= overlay.pressed(&rl).held();
             }
         }
-        0x0E => {
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            match ins & 0xFF {
-                0x9E => Instruction::SkipIfKeyPressed(reg),
-                0xA1 => Instruction::SkipIfKeyNotPressed(reg),
-                _ => unimplemented!("unknown instruction"),
+
+        for (slot, key) in SLOT_KEYS.iter().enumerate() {
+            if movie_active || !rl.is_key_pressed(*key) {
+                continue;
             }
-        }
-        0x0F => {
-            let reg = VariableRegister::from((ins >> 8 & 0x0F) as u8);
-            match ins & 0xFF {
-                0x07 => Instruction::GetDelayTimer(reg),
-                0x0A => Instruction::GetKey(reg),
-                0x15 => Instruction::SetDelayTimer(reg),
-                0x18 => Instruction::SetSoundTimer(reg),
-                0x1E => Instruction::AddToIndex(reg),
-                0x29 => Instruction::Font(reg),
-                0x33 => Instruction::BinDecConversion(reg),
-                0x55 => {
-                    let imm = (ins >> 8 & 0x0F) as u8;
-                    Instruction::Store(imm)
-                }
-                0x65 => {
-                    let imm = (ins >> 8 & 0x0F) as u8;
-                    Instruction::Load(imm)
-                }
-                _ => {
-                    println!("unknown instruction: {:X}", ins);
-                    unimplemented!("unknown instruction")
+            let path = slot_path(&options.rom_path, slot + 1);
+            if shift_down(&rl) {
+                let state = SaveState::capture(&chip8, rom);
+                match std::fs::write(&path, state.to_bytes()) {
+                    Ok(()) => println!("saved state to {}", path.display()),
+                    Err(err) => eprintln!("error: failed to write {}: {}", path.display(), err),
+                }
+            } else {
+                match load_state(rom, &path) {
+                    Ok(loaded) => {
+                        chip8 = loaded;
+                        error = None;
+                    }
+                    Err(err) => eprintln!("error: {}", err),
                 }
             }
         }
-        _ => unimplemented!("unknown instruction"),
-    }
-}
 
-fn execute(
-    memory: &mut [u8],
-    display: &mut [bool],
-    program_counter: &mut u16,
-    stack: &mut Vec<u16>,
-    variable_registers: &mut HashMap<VariableRegister, u8>,
-    index_register: &mut u16,
-    key_downs: &mut HashMap<KeyboardKey, bool>,
-    delay_timer: &mut Duration,
-    sound_timer: &mut Duration,
-    ins: Instruction,
-) {
-    match ins {
-        Instruction::ClearScreen => {
-            for pixel in display {
-                *pixel = false;
+        if let Some(debugger) = debugger.as_mut().filter(|_| !remapper.is_open()) {
+            debugger.handle_keys(&rl, &chip8);
+        }
+        if let Some(gdb) = &mut gdb {
+            gdb.poll(&mut chip8).unwrap_or_else(|err| gdb_error(err));
+            if gdb.killed() {
+                break;
             }
         }
-        Instruction::Jump(loc) => {
-            *program_counter = loc;
+        let paused = debugger.as_ref().is_some_and(Debugger::paused)
+            || gdb.as_ref().is_some_and(GdbStub::stopped)
+            || remapper.is_open();
+
+        let rewinding = !movie_active && rl.is_key_down(REWIND_KEY);
+        if rl.is_key_pressed(MUTE_KEY) {
+            speaker.toggle_mute();
         }
-        Instruction::JumpWithOffset(offset) => {
-            // This follows the COSMAC VIP interpreter to jump to address `NNN` plus value in
-            // register V0.
-            let addr = *variable_registers.get(&VariableRegister::V0).unwrap() as u16;
-            let addr = addr + offset;
-            *program_counter = addr;
+        if rl.is_key_pressed(CAPTURE_KEY) && !picture.rgb.is_empty() {
+            if !shift_down(&rl) {
+                let path = capture::next_path(&options.rom_path, "shot", "png");
+                match std::fs::write(&path, picture.png(options.capture_scale)) {
+                    Ok(()) => println!("saved screenshot to {}", path.display()),
+                    Err(err) => eprintln!("error: failed to write {}: {}", path.display(), err),
+                }
+            } else if let Some(video) = video.take() {
+                finish_video(video);
+            } else {
+                let path = capture::next_path(&options.rom_path, "video", "gif");
+                match Recording::start(&path, scaler.factor(), options.capture_scale) {
+                    Ok(recording) => {
+                        println!("recording to {}", path.display());
+                        video = Some(recording);
+                    }
+                    Err(err) => eprintln!("error: {}", err),
+                }
+            }
         }
-        Instruction::Pop => {
-            let Some(loc) = stack.pop() else {
-                panic!("invalid pop: missing return address from stack");
+
+        let current_time = Instant::now();
+        let delta = current_time - prev_time;
+
+        while current_time - prev_timer_tick >= TIMER_PERIOD {
+            prev_timer_tick += TIMER_PERIOD;
+            // The filters count in frames, so the display is only taken at the end of each one.
+            let (width, height) = (chip8.width(), chip8.height());
+            picture = Picture {
+                rgb: scaler.apply(width, height, frame_filter.apply(chip8.framebuffer())),
+                width: width * scaler.factor(),
+                height: height * scaler.factor(),
             };
-            *program_counter = loc;
-        }
-        Instruction::Call(loc) => {
-            stack.push(*program_counter);
-            *program_counter = loc;
-        }
-        Instruction::SetRegImm(reg, imm) => {
-            variable_registers.entry(reg).and_modify(|v| *v = imm);
-        }
-        Instruction::AddRegImm(reg, imm) => {
-            variable_registers
-                .entry(reg)
-                .and_modify(|v| *v = (*v).wrapping_add(imm));
-        }
-        Instruction::SetIdxRegImm(imm) => {
-            *index_register = imm;
-        }
-        Instruction::Display { x, y, n } => {
-            let x = (variable_registers.get(&x).unwrap() & (GRID_WIDTH - 1) as u8) as u64;
-            let y = (variable_registers.get(&y).unwrap() & (GRID_HEIGHT - 1) as u8) as u64;
-            variable_registers
-                .entry(VariableRegister::VF)
-                .and_modify(|v| *v = 0);
-            println!(
-                "Display({:X?}, {:X?}): {:X?}",
-                x,
-                y,
-                &memory[(*index_register as usize)..((*index_register + n as u16) as usize)]
+            renderer.update(
+                &mut rl,
+                &thread,
+                &picture.rgb,
+                picture.width,
+                picture.height,
             );
-            for j in 0u64..n as u64 {
-                if y + j >= GRID_HEIGHT as u64 {
-                    break;
-                }
-
-                let sprite_byte = memory[(*index_register + j as u16) as usize];
-                for i in 0u64..8 {
-                    if x + i >= GRID_WIDTH as u64 {
-                        break;
+            if let Some(video) = &mut video {
+                video.push(&picture);
+            }
+            if let Some(movie) = &replay {
+                if let Some(frame) = movie.frames().get(replay_frame) {
+                    if let Err(err) = frame.run_steps(&mut chip8) {
+                        eprintln!("error: {}", err);
+                        error = Some(err);
+                    }
+                    if let Some(capture) = &mut capture {
+                        capture.frame(&chip8);
+                    }
+                    chip8.tick_timers();
+                    replay_frame += 1;
+                    if replay_frame == movie.frames().len() {
+                        finish_replay(movie, &chip8);
                     }
+                }
+                continue;
+            }
 
-                    let sprite_pixel = (sprite_byte >> (7 - i)) & 0x1;
-                    if sprite_pixel == 1 && display[((y + j) * 64 + (x + i)) as usize] {
-                        display[((y + j) * 64 + (x + i)) as usize] = false;
-                        variable_registers
-                            .entry(VariableRegister::VF)
-                            .and_modify(|v| *v = 1);
-                    } else if sprite_pixel == 1 && !display[((y + j) * 64 + (x + i)) as usize] {
-                        display[((y + j) * 64 + (x + i)) as usize] = true;
+            if rewinding {
+                if let Some(frame) = rewind.pop() {
+                    chip8 = frame;
+                    error = None;
+                    if let Some(debugger) = &mut debugger {
+                        debugger.stepped(&chip8);
                     }
                 }
+            } else if !paused {
+                if let Some(capture) = &mut capture {
+                    capture.frame(&chip8);
+                }
+                chip8.tick_timers();
+                rewind.record(&chip8);
+                if let Some(movie) = &mut recording {
+                    movie.push(frame, &chip8);
+                }
+            } else if recording.is_some() {
+                // Keep the frame the pause interrupted, so the instructions it already ran are
+                // recorded along with the keys they saw once the game carries on.
+                continue;
             }
-        }
-        Instruction::SkipIfEqImm(reg, imm) => {
-            if *variable_registers.get(&reg).unwrap() == imm {
-                *program_counter += 2;
+            frame = Frame {
+                keys: options.keymap.held(&rl).held()
+                    | profile.held(&rl).held()
+                    | overlay
+                        .as_ref()
+                        .map_or(0, |overlay| overlay.held(&rl).held())
+                    | std::mem::take(&mut pressed),
+                steps: 0,
+            };
+            chip8.set_keypad(Keypad::with_held(frame.keys));
+            if let Some(overlay) = &mut overlay {
+                overlay.frame(chip8.take_polled_keys());
             }
         }
-        Instruction::SkipIfNeqImm(reg, imm) => {
-            if *variable_registers.get(&reg).unwrap() != imm {
-                *program_counter += 2;
+
+        let due = delta > std::time::Duration::from_millis(TIME_STEP_MS as u64);
+        let step = match (&mut debugger, &mut gdb) {
+            (Some(debugger), _) => debugger.should_step(&chip8, due),
+            (_, Some(gdb)) => gdb
+                .should_step(&chip8, due)
+                .unwrap_or_else(|err| gdb_error(err)),
+            _ => due,
+        };
+        // Under gdb an error only stops the machine until the debugger resumes it.
+        let step = step && replay.is_none() && !rewinding && !remapper.is_open();
+        if (error.is_none() || gdb.is_some()) && !chip8.halted() && step {
+            let result = match &mut gdb {
+                Some(gdb) => gdb.step(&mut chip8).unwrap_or_else(|err| gdb_error(err)),
+                None => chip8.step(),
+            };
+            frame.steps += 1;
+            match result {
+                Ok(_) => error = None,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    error = Some(err);
+                }
             }
-        }
-        Instruction::SkipIfEqReg(x_reg, y_reg) => {
-            if variable_registers.get(&x_reg).unwrap() == variable_registers.get(&y_reg).unwrap() {
-                *program_counter += 2;
+            if let Some(debugger) = &mut debugger {
+                if error.is_some() {
+                    debugger.pause(chip8.program_counter());
+                }
+                debugger.stepped(&chip8);
             }
+
+            prev_time = current_time;
         }
-        Instruction::SkipIfNeqReg(x_reg, y_reg) => {
-            if variable_registers.get(&x_reg).unwrap() != variable_registers.get(&y_reg).unwrap() {
-                *program_counter += 2;
-            }
+
+        speaker.update(&if paused || rewinding {
+            Sound::SILENCE
+        } else {
+            Sound::of(&chip8)
+        });
+
+        let mut d = rl.begin_drawing(&thread);
+        d.clear_background(match debugger {
+            Some(_) => Color::BLACK,
+            None => options.display.border,
+        });
+        renderer.draw(display_bounds, &mut d);
+        if let Some(debugger) = &debugger {
+            debugger.draw(&chip8, &mut d);
         }
-        Instruction::Set(x_reg, y_reg) => {
-            let val = *variable_registers.get(&y_reg).unwrap();
-            variable_registers.entry(x_reg).and_modify(|v| *v = val);
-        }
-        Instruction::BinOr(x_reg, y_reg) => {
-            let x_val = *variable_registers.get(&x_reg).unwrap();
-            let y_val = *variable_registers.get(&y_reg).unwrap();
-            variable_registers
-                .entry(x_reg)
-                .and_modify(|v| *v = x_val | y_val);
-        }
-        Instruction::BinAnd(x_reg, y_reg) => {
-            let x_val = *variable_registers.get(&x_reg).unwrap();
-            let y_val = *variable_registers.get(&y_reg).unwrap();
-            variable_registers
-                .entry(x_reg)
-                .and_modify(|v| *v = x_val & y_val);
-        }
-        Instruction::Xor(x_reg, y_reg) => {
-            let x_val = *variable_registers.get(&x_reg).unwrap();
-            let y_val = *variable_registers.get(&y_reg).unwrap();
-            variable_registers
-                .entry(x_reg)
-                .and_modify(|v| *v = x_val ^ y_val);
-        }
-        Instruction::Add(x_reg, y_reg) => {
-            let x_val = *variable_registers.get(&x_reg).unwrap();
-            let y_val = *variable_registers.get(&y_reg).unwrap();
-            match x_val.checked_add(y_val) {
-                None => {
-                    variable_registers
-                        .entry(VariableRegister::VF)
-                        .and_modify(|v| *v = 1);
-                    variable_registers
-                        .entry(x_reg)
-                        .and_modify(|v| *v = x_val.wrapping_add(y_val));
-                }
-                Some(val) => {
-                    variable_registers
-                        .entry(VariableRegister::VF)
-                        .and_modify(|v| *v = 0);
-                    variable_registers.entry(x_reg).and_modify(|v| *v = val);
-                }
-            }
+        if let Some(overlay) = &overlay {
+            overlay.draw(chip8.keypad(), &mut d);
         }
-        Instruction::SubtractLR(x_reg, y_reg) => {
-            let v1 = *variable_registers.get(&x_reg).unwrap();
-            let v2 = *variable_registers.get(&y_reg).unwrap();
-            if v1 > v2 {
-                variable_registers
-                    .entry(VariableRegister::VF)
-                    .and_modify(|v| *v = 1);
-                variable_registers
-                    .entry(x_reg)
-                    .and_modify(|v| *v = v1 - v2 & 0xFF);
-            } else {
-                variable_registers
-                    .entry(VariableRegister::VF)
-                    .and_modify(|v| *v = 0);
-                variable_registers
-                    .entry(x_reg)
-                    .and_modify(|v| *v = v1.wrapping_sub(v2) & 0xFF);
-            }
+        if let Some(err) = &error {
+            draw_error(err, &chip8, &mut d);
         }
-        Instruction::SubtractRL(x_reg, y_reg) => {
-            let v1 = *variable_registers.get(&y_reg).unwrap();
-            let v2 = *variable_registers.get(&x_reg).unwrap();
-            if v1 > v2 {
-                variable_registers
-                    .entry(VariableRegister::VF)
-                    .and_modify(|v| *v = 1);
-                variable_registers
-                    .entry(x_reg)
-                    .and_modify(|v| *v = v1 - v2 & 0xFF);
-            } else {
-                variable_registers
-                    .entry(VariableRegister::VF)
-                    .and_modify(|v| *v = 0);
-                variable_registers
-                    .entry(x_reg)
-                    .and_modify(|v| *v = v1.wrapping_sub(v2) & 0xFF);
-            }
+        if remapper.is_open() {
+            remapper.draw(&profile, display_bounds, &mut d);
         }
-        Instruction::ShiftLeft(x_reg, _y_reg) => {
-            // We follow CHIP-48 and SUPER-CHIP so that shifts happen to VX in place and ignores Y
-            // completely.
-            let mut val = *variable_registers.get(&x_reg).unwrap();
-            let vf = val & 0x80;
-            val <<= 1;
-            variable_registers
-                .entry(VariableRegister::VF)
-                .and_modify(|v| *v = vf);
-            variable_registers.entry(x_reg).and_modify(|v| *v = val);
-        }
-        Instruction::ShiftRight(x_reg, _y_reg) => {
-            // We follow CHIP-48 and SUPER-CHIP so that shifts happen to VX in place and ignores Y
-            // completely.
-            let mut val = *variable_registers.get(&x_reg).unwrap();
-            let vf = val & 0x01;
-            val >>= 1;
-            variable_registers
-                .entry(VariableRegister::VF)
-                .and_modify(|v| *v = vf);
-            variable_registers.entry(x_reg).and_modify(|v| *v = val);
-        }
-        Instruction::Random(reg, imm) => {
-            use rand::Rng;
-            let r: u8 = rand::thread_rng().gen();
-            let r = r & imm;
-            variable_registers.entry(reg).and_modify(|v| *v = r);
-        }
-        Instruction::SkipIfKeyPressed(reg) => {
-            let key = *variable_registers.get(&reg).unwrap();
-            let key = key_from_i32(key as i32).unwrap();
-            if *key_downs.get(&key).unwrap() {
-                *program_counter += 2;
+    }
+
+    if let (Some(movie), Some(path)) = (&recording, &options.record) {
+        match std::fs::write(path, movie.to_bytes()) {
+            Ok(()) => println!("recorded {} frames to {}", movie.frames().len(), path),
+            Err(err) => {
+                eprintln!("error: failed to write {}: {}", path, err);
+                std::process::exit(1);
             }
         }
-        Instruction::SkipIfKeyNotPressed(reg) => {
-            let key = *variable_registers.get(&reg).unwrap();
-            let key = key_from_i32(key as i32).unwrap();
-            if !*key_downs.get(&key).unwrap() {
-                *program_counter += 2;
-            }
+    }
+    if let Some(path) = &options.screenshot {
+        if let Err(err) = std::fs::write(path, picture.png(options.capture_scale)) {
+            eprintln!("error: failed to write {}: {}", path, err);
+            std::process::exit(1);
         }
-        Instruction::GetDelayTimer(reg) => {
-            let delay_timer = delay_timer.as_millis() as u8;
-            variable_registers
-                .entry(reg)
-                .and_modify(|v| *v = delay_timer);
+    }
+    if let Some(video) = video {
+        finish_video(video);
+    }
+    if let (Some(capture), Some(path)) = (&capture, &options.audio_out) {
+        if let Err(err) = std::fs::write(path, capture.wav()) {
+            eprintln!("error: failed to write {}: {}", path, err);
+            std::process::exit(1);
         }
-        Instruction::SetDelayTimer(reg) => {
-            let val = *variable_registers.get(&reg).unwrap();
-            *delay_timer = Duration::from_millis(val as u64);
+    }
+    if let Some(movie) = &replay {
+        if replay_frame < movie.frames().len() {
+            println!(
+                "replay stopped after {} of {} frames",
+                replay_frame,
+                movie.frames().len()
+            );
         }
-        Instruction::SetSoundTimer(reg) => {
-            let val = *variable_registers.get(&reg).unwrap();
-            *sound_timer = Duration::from_millis(val as u64);
+    }
+}
+
+/// Applies a `<flag>=<on|off>` override given with `--quirk`.
+fn override_quirk(quirks: &mut Quirks, setting: &str) -> Result<(), String> {
+    let invalid = || {
+        format!(
+            "invalid quirk `{}`, expected <flag>=<on|off> with a flag out of: {}",
+            setting,
+            Quirks::FLAG_NAMES.join(", ")
+        )
+    };
+    let (name, value) = setting.split_once('=').ok_or_else(invalid)?;
+    let flag = quirks.flag_mut(name).ok_or_else(invalid)?;
+    *flag = match value {
+        "on" => true,
+        "off" => false,
+        _ => return Err(invalid()),
+    };
+    Ok(())
+}
+
+/// The beep frequency in Hz given with `--beep-frequency`.
+fn parse_frequency(hz: &str) -> Result<f32, String> {
+    hz.parse()
+        .ok()
+        .filter(|hz| *hz > 0.0)
+        .ok_or_else(|| format!("invalid frequency `{}`", hz))
+}
+
+/// The volume given in percent with `--beep-volume`, from 0 to 1.
+fn parse_volume(percent: &str) -> Result<f32, String> {
+    let volume: f32 = percent
+        .parse()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .ok_or_else(|| format!("invalid volume `{}`", percent))?;
+    Ok(volume / 100.0)
+}
+
+fn parse_waveform(name: &str) -> Result<Waveform, String> {
+    Waveform::from_name(name).ok_or_else(|| {
+        format!(
+            "unknown waveform `{}`, expected one of: {}",
+            name,
+            Waveform::NAMES.join(", ")
+        )
+    })
+}
+
+fn read_movie(path: &Path) -> Result<Movie, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    Movie::from_bytes(&bytes).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Reports whether the replay ended up where the recording did.
+fn finish_replay(movie: &Movie, chip8: &Chip8) {
+    if movie.matches_end(chip8) {
+        println!(
+            "replayed {} frames, the display matches the recording",
+            movie.frames().len()
+        );
+    } else {
+        eprintln!("error: the replay's display differs from the recording");
+    }
+}
+
+fn shift_down(rl: &RaylibHandle) -> bool {
+    rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT)
+}
+
+/// Writes a recording and reports where it went.
+fn finish_video(video: Recording) {
+    let path = video.path().to_path_buf();
+    match video.finish() {
+        Ok(frames) => println!("recorded {} frames to {}", frames, path.display()),
+        Err(err) => eprintln!("error: {}", err),
+    }
+}
+
+/// The save state file for `slot` next to the ROM, such as `game.slot1.c8s` for `game.ch8`.
+fn slot_path(rom_path: &str, slot: usize) -> PathBuf {
+    Path::new(rom_path).with_extension(format!("slot{}.c8s", slot))
+}
+
+/// Reads the save state at `path` and restores it, provided it was taken with `rom`.
+fn load_state(rom: &[u8], path: &Path) -> Result<Chip8, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    SaveState::from_bytes(&bytes)
+        .and_then(|state| state.restore(rom))
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Runs `rom` through the original interpreter on an emulated COSMAC VIP, in real time.
+fn run_vip(
+    rom: &[u8],
+    interpreter: &[u8],
+    monitor: Option<&[u8]>,
+    keymap: &Keymap,
+    display: &DisplayOptions,
+) {
+    let mut vip = match CosmacVip::new(interpreter, monitor).and_then(|mut vip| {
+        vip.load_rom(rom)?;
+        Ok(vip)
+    }) {
+        Ok(vip) => vip,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
         }
-        Instruction::GetKey(reg) => {
-            for (key, is_pressed) in key_downs {
-                if *is_pressed {
-                    let key = *key as u32 as u8;
-                    variable_registers.entry(reg).and_modify(|v| *v = key);
-                    return;
-                }
-            }
+    };
 
-            *program_counter -= 2;
-        }
-        Instruction::Font(reg) => {
-            let addr = *variable_registers.get(&reg).unwrap();
-            *index_register = addr as u16;
-        }
-        Instruction::BinDecConversion(reg) => {
-            let val = *variable_registers.get(&reg).unwrap();
-            let d3 = val % 10;
-            let d2 = val / 10 % 10;
-            let d1 = val / 100;
-
-            let addr = *index_register as usize;
-            memory[addr] = d1;
-            memory[addr + 1] = d2;
-            memory[addr + 2] = d3;
-        }
-        Instruction::Store(x) => {
-            let variable_registers = variable_registers.iter().collect::<Vec<_>>();
-            let addr = *index_register as usize;
-            for i in 0..usize::min(x as usize, variable_registers.len()) {
-                let (_, val) = variable_registers[i];
-                memory[addr + i] = *val;
-            }
+    let (mut rl, thread) = init_window(WINDOW_WIDTH, WINDOW_HEIGHT, true, display);
+
+    let frame_period = Duration::from_secs_f64(
+        vip::CYCLES_PER_FRAME as f64 / vip::MACHINE_CYCLES_PER_SECOND as f64,
+    );
+    let mut prev_frame = Instant::now();
+    let mut renderer = Renderer::new(display.pixel_grid.then_some(display.palette[0]));
+    let mut frame_filter = FrameFilter::new(display.filter, display.rgb_palette());
+
+    while !rl.window_should_close() {
+        let held = keymap.held(&rl);
+        for key in 0..16 {
+            vip.set_key(key, held.is_held(key));
         }
-        Instruction::Load(x) => {
-            let mut variable_registers = variable_registers
-                .iter()
-                .map(|(k, v)| (*k, *v))
-                .collect::<Vec<_>>();
-            let addr = *index_register as usize;
-            for i in 0..usize::min(x as usize, variable_registers.len()) {
-                variable_registers[i].1 = memory[addr + i];
-            }
+
+        let current_time = Instant::now();
+        while current_time - prev_frame >= frame_period {
+            vip.run_frame();
+            prev_frame += frame_period;
+            let (width, height) = (vip::VIP_WIDTH, vip::VIP_HEIGHT);
+            renderer.update(
+                &mut rl,
+                &thread,
+                &display
+                    .scaler
+                    .apply(width, height, frame_filter.apply(&vip.framebuffer())),
+                width * display.scaler.factor(),
+                height * display.scaler.factor(),
+            );
         }
-        Instruction::AddToIndex(reg) => {
-            // We use AMIGA interpreter's behavior of setting VF to 1 if I overflows from 0x0FFF to
-            // above 0x1000.
-            let offset = *variable_registers.get(&reg).unwrap();
-            if *index_register + offset as u16 > 0x0FFF {
-                variable_registers
-                    .entry(VariableRegister::VF)
-                    .and_modify(|v| *v = 1);
-                *index_register += offset as u16;
-            } else {
-                variable_registers
-                    .entry(VariableRegister::VF)
-                    .and_modify(|v| *v = 0);
-                *index_register += offset as u16;
-            }
+
+        if rl.is_key_pressed(FULLSCREEN_KEY) {
+            rl.toggle_fullscreen();
         }
+        // The interpreter draws each of its 32 rows on four lines, so keep the CHIP-8 shape.
+        let bounds = display_bounds(&rl, 0, GRID_WIDTH, GRID_HEIGHT);
+        let mut d = rl.begin_drawing(&thread);
+        d.clear_background(display.border);
+        renderer.draw(bounds, &mut d);
+    }
+
+    println!(
+        "ran {} machine cycles in {} frames ({:.3} s at {} Hz)",
+        vip.cycles(),
+        vip.frames(),
+        vip.cycles() as f64 / vip::MACHINE_CYCLES_PER_SECOND as f64,
+        vip::CLOCK_HZ
+    );
+}
+
+/// Overlays the error that halted the machine along with the machine state at that point.
+fn draw_error(err: &Chip8Error, chip8: &Chip8, d: &mut RaylibDrawHandle) {
+    const FONT_SIZE: i32 = 20;
+    const LINE_HEIGHT: i32 = FONT_SIZE + 4;
+
+    let mut lines = vec![
+        format!("error: {}", err),
+        format!(
+            "PC={:03X} I={:03X} DT={:02X} ST={:02X}",
+            chip8.program_counter(),
+            chip8.index_register(),
+            chip8.delay_timer(),
+            chip8.sound_timer()
+        ),
+    ];
+    for (row, regs) in chip8.registers().chunks(8).enumerate() {
+        let regs = regs
+            .iter()
+            .enumerate()
+            .map(|(i, val)| format!("V{:X}={:02X}", row * 8 + i, val))
+            .collect::<Vec<_>>();
+        lines.push(regs.join(" "));
+    }
+    lines.push(format!("stack: {:03X?}", chip8.stack()));
+
+    let height = LINE_HEIGHT * lines.len() as i32 + 8;
+    d.draw_rectangle(0, 0, d.get_screen_width(), height, Color::new(0, 0, 0, 200));
+    for (i, line) in lines.iter().enumerate() {
+        d.draw_text(line, 4, 4 + LINE_HEIGHT * i as i32, FONT_SIZE, Color::RED);
     }
 }
@@ -0,0 +1,195 @@
+//! Input movies: the keys held in every frame of a session and the random seed, from which the
+//! session can be replayed exactly.
+//!
+//! A movie starts with the magic `C8MV`, a little endian `u16` format version, the [`rom_hash`]
+//! of the ROM, the quirks and the seed. Then comes the number of frames as a `u32` and, for each
+//! frame, the held keys as a bitmask and the number of instructions executed, both `u16`. It ends
+//! with a hash of the final framebuffer that replays are checked against.
+
+use std::fmt;
+
+use crate::state::{self, rom_hash, Reader, StateError, Writer};
+use crate::{Chip8, Chip8Error, Keypad, Quirks};
+
+/// The version written by [`Movie::to_bytes`]. Bumped whenever the layout changes.
+pub const VERSION: u16 = 2;
+const MAGIC: &[u8; 4] = b"C8MV";
+
+/// Errors raised while reading or starting to replay a movie.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data doesn't start with the movie magic.
+    NotAMovie,
+    /// The movie was written by a different version of the format.
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of the movie.
+    Truncated,
+    /// A field holds a value that can't be replayed.
+    Invalid(&'static str),
+    /// The movie was recorded with a different ROM.
+    RomMismatch,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported movie version {}, expected {}",
+                version, VERSION
+            ),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Invalid(field) => write!(f, "movie has an invalid {}", field),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(err: StateError) -> Self {
+        match err {
+            StateError::NotASaveState => MovieError::NotAMovie,
+            StateError::UnsupportedVersion(version) => MovieError::UnsupportedVersion(version),
+            StateError::Truncated => MovieError::Truncated,
+            StateError::Invalid(field) => MovieError::Invalid(field),
+            StateError::RomMismatch => MovieError::RomMismatch,
+        }
+    }
+}
+
+/// The input for one 60 Hz frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Bit `n` is set while key `n` is held.
+    pub keys: u16,
+    /// How many instructions were executed during the frame.
+    pub steps: u16,
+}
+
+impl Frame {
+    /// Plays the frame on `chip8`: sets the keys, executes the instructions and ticks the timers.
+    /// Instructions stop at the first error, like they do in the frontend.
+    pub fn run(&self, chip8: &mut Chip8) -> Result<(), Chip8Error> {
+        let result = self.run_steps(chip8);
+        chip8.tick_timers();
+        result
+    }
+
+    /// Like [`Frame::run`], but leaves ticking the timers to the caller.
+    pub fn run_steps(&self, chip8: &mut Chip8) -> Result<(), Chip8Error> {
+        chip8.set_keypad(Keypad::with_held(self.keys));
+        (0..self.steps).try_for_each(|_| {
+            if chip8.halted() {
+                return Ok(());
+            }
+            chip8.step().map(drop)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Movie {
+    rom_hash: u64,
+    quirks: Quirks,
+    seed: u64,
+    frames: Vec<Frame>,
+    framebuffer_hash: u64,
+}
+
+impl Movie {
+    /// An empty movie for a session of `chip8`, which has just loaded `rom`.
+    pub fn new(chip8: &Chip8, rom: &[u8]) -> Self {
+        Self {
+            rom_hash: rom_hash(rom),
+            quirks: chip8.quirks(),
+            seed: chip8.seed(),
+            frames: Vec::new(),
+            framebuffer_hash: framebuffer_hash(chip8),
+        }
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Appends a frame, after which the session looks like `chip8`.
+    pub fn push(&mut self, frame: Frame, chip8: &Chip8) {
+        self.frames.push(frame);
+        self.framebuffer_hash = framebuffer_hash(chip8);
+    }
+
+    /// The machine the movie starts from, provided `rom` is the ROM it was recorded with.
+    pub fn start(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        if rom_hash(rom) != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        let mut chip8 = Chip8::with_quirks(self.quirks);
+        chip8
+            .load_rom(rom)
+            .map_err(|_| MovieError::Invalid("ROM size"))?;
+        chip8.reseed(self.seed);
+        Ok(chip8)
+    }
+
+    /// Whether `chip8` shows the same picture as the end of the recording.
+    pub fn matches_end(&self, chip8: &Chip8) -> bool {
+        framebuffer_hash(chip8) == self.framebuffer_hash
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(MAGIC);
+        writer.u16(VERSION);
+        writer.u64(self.rom_hash);
+        state::write_quirks(&mut writer, self.quirks);
+        writer.u64(self.seed);
+        writer.u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            writer.u16(frame.keys);
+            writer.u16(frame.steps);
+        }
+        writer.u64(self.framebuffer_hash);
+        writer.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut reader = Reader::after_magic(bytes, MAGIC.len());
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.u64()?;
+        let quirks = state::read_quirks(&mut reader)?;
+        let seed = reader.u64()?;
+        let frames = (0..reader.u32()?)
+            .map(|_| {
+                Ok(Frame {
+                    keys: reader.u16()?,
+                    steps: reader.u16()?,
+                })
+            })
+            .collect::<Result<_, StateError>>()?;
+        let framebuffer_hash = reader.u64()?;
+        if !reader.at_end() {
+            return Err(MovieError::Invalid("length"));
+        }
+        Ok(Self {
+            rom_hash,
+            quirks,
+            seed,
+            frames,
+            framebuffer_hash,
+        })
+    }
+}
+
+/// The same hash as for ROMs, over the framebuffer.
+fn framebuffer_hash(chip8: &Chip8) -> u64 {
+    rom_hash(chip8.framebuffer())
+}
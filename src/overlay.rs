@@ -0,0 +1,131 @@
+//! The on-screen keypad: a 4x4 COSMAC VIP keypad beside the display that can be clicked or touched,
+//! and that lights up the keys the program reads.
+
+use chip_8_interpreter::Keypad;
+use raylib::prelude::*;
+
+/// The hex keys in the order they're laid out on the VIP, row by row.
+const LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+const KEY_SIZE: i32 = 64;
+const GAP: i32 = 8;
+const FONT_SIZE: i32 = 32;
+/// How many frames a key stays lit after the program last read it.
+const GLOW_FRAMES: u8 = 20;
+
+/// The width the keypad takes up next to the display.
+pub const WIDTH: i32 = 4 * KEY_SIZE + 5 * GAP;
+
+#[derive(Debug, Clone)]
+pub struct KeypadOverlay {
+    /// The top left corner of the keypad.
+    x: i32,
+    y: i32,
+    /// For each hex key, how many more frames it stays lit.
+    glow: [u8; 16],
+}
+
+impl KeypadOverlay {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self {
+            x,
+            y,
+            glow: [0; 16],
+        }
+    }
+
+    /// Moves the top left corner of the keypad to `x`, `y`, such as when the window is resized.
+    pub fn move_to(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fn key_bounds(&self, i: usize) -> Rectangle {
+        let (column, row) = ((i % 4) as i32, (i / 4) as i32);
+        Rectangle::new(
+            (self.x + GAP + column * (KEY_SIZE + GAP)) as f32,
+            (self.y + GAP + row * (KEY_SIZE + GAP)) as f32,
+            KEY_SIZE as f32,
+            KEY_SIZE as f32,
+        )
+    }
+
+    fn key_at(&self, point: Vector2) -> Option<u8> {
+        (0..LAYOUT.len())
+            .find(|i| self.key_bounds(*i).check_collision_point_rec(point))
+            .map(|i| LAYOUT[i])
+    }
+
+    /// The keys under the mouse while its left button is down, or under a finger.
+    pub fn held(&self, rl: &RaylibHandle) -> Keypad {
+        let mut keypad = Keypad::default();
+        let mut points = (0..rl.get_touch_points_count())
+            .map(|i| rl.get_touch_position(i))
+            .collect::<Vec<_>>();
+        if rl.is_mouse_button_down(MouseButton::MOUSE_LEFT_BUTTON) {
+            points.push(rl.get_mouse_position());
+        }
+        for key in points.into_iter().filter_map(|point| self.key_at(point)) {
+            keypad.set_held(key, true);
+        }
+        keypad
+    }
+
+    /// The key clicked since the last frame, so that a click shorter than a frame still counts.
+    pub fn pressed(&self, rl: &RaylibHandle) -> Keypad {
+        let mut keypad = Keypad::default();
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+            if let Some(key) = self.key_at(rl.get_mouse_position()) {
+                keypad.set_held(key, true);
+            }
+        }
+        keypad
+    }
+
+    /// Lights up the keys in the bitmask `polled`, read by the program during the last frame, and
+    /// fades the others.
+    pub fn frame(&mut self, polled: u16) {
+        for (key, glow) in self.glow.iter_mut().enumerate() {
+            *glow = if polled & (1 << key) != 0 {
+                GLOW_FRAMES
+            } else {
+                glow.saturating_sub(1)
+            };
+        }
+    }
+
+    /// Draws the keypad with the keys in `held` pressed down.
+    pub fn draw(&self, held: Keypad, d: &mut RaylibDrawHandle) {
+        for (i, key) in LAYOUT.iter().enumerate() {
+            let bounds = self.key_bounds(i);
+            let glow = self.glow[*key as usize] as f32 / GLOW_FRAMES as f32;
+            let (fill, text) = if held.is_held(*key) {
+                (Color::WHITE, Color::BLACK)
+            } else {
+                let lit = Color::new(0xE0, 0xA0, 0x20, 0xFF);
+                let base = Color::new(0x30, 0x30, 0x30, 0xFF);
+                let mix =
+                    |lit: u8, base: u8| (base as f32 + (lit as f32 - base as f32) * glow) as u8;
+                let fill = Color::new(
+                    mix(lit.r, base.r),
+                    mix(lit.g, base.g),
+                    mix(lit.b, base.b),
+                    0xFF,
+                );
+                (fill, Color::WHITE)
+            };
+            d.draw_rectangle_rec(bounds, fill);
+            d.draw_rectangle_lines_ex(bounds, 2, Color::GRAY);
+            let label = format!("{:X}", key);
+            let label_width = measure_text(&label, FONT_SIZE);
+            d.draw_text(
+                &label,
+                bounds.x as i32 + (KEY_SIZE - label_width) / 2,
+                bounds.y as i32 + (KEY_SIZE - FONT_SIZE) / 2,
+                FONT_SIZE,
+                text,
+            );
+        }
+    }
+}
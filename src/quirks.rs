@@ -0,0 +1,134 @@
+/// Behaviours that differ between CHIP-8 implementations.
+///
+/// Programs were written against whichever interpreter their authors had at hand, so no single
+/// choice runs every ROM correctly. The named presets match the common platforms; individual
+/// flags can be adjusted on top of them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6` and `8XYE` shift VX in place and ignore VY (CHIP-48, SUPER-CHIP). Otherwise VY is
+    /// shifted and the result stored in VX (COSMAC VIP).
+    pub shift_ignores_vy: bool,
+    /// `BXNN` jumps to `XNN` plus VX (CHIP-48, SUPER-CHIP). Otherwise `BNNN` jumps to `NNN` plus
+    /// V0 (COSMAC VIP).
+    pub jump_uses_vx: bool,
+    /// `FX55` and `FX65` leave I pointing just past the last register accessed (COSMAC VIP).
+    pub load_store_increments_i: bool,
+    /// With [`Quirks::load_store_increments_i`], I is left pointing at the last register
+    /// accessed instead, one short of the COSMAC VIP (CHIP-48).
+    pub load_store_increments_i_by_x: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0 (COSMAC VIP).
+    pub logic_resets_vf: bool,
+    /// Sprites drawn past an edge of the screen wrap around to the opposite edge instead of
+    /// being clipped.
+    pub sprite_wrap: bool,
+    /// `DXYN` waits for the next 60 Hz vertical blank before drawing (COSMAC VIP).
+    pub display_wait: bool,
+    /// `FX1E` sets VF to 1 when I goes past `0x0FFF` (Amiga interpreter).
+    pub index_overflow_sets_vf: bool,
+    /// Memory is 64 KiB instead of 4 KiB (XO-CHIP).
+    pub extended_memory: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_ignores_vy: false,
+        jump_uses_vx: false,
+        load_store_increments_i: true,
+        load_store_increments_i_by_x: false,
+        logic_resets_vf: true,
+        sprite_wrap: false,
+        display_wait: true,
+        index_overflow_sets_vf: false,
+        extended_memory: false,
+    };
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub const CHIP_48: Quirks = Quirks {
+        shift_ignores_vy: true,
+        jump_uses_vx: true,
+        load_store_increments_i: true,
+        load_store_increments_i_by_x: true,
+        logic_resets_vf: false,
+        sprite_wrap: false,
+        display_wait: false,
+        index_overflow_sets_vf: false,
+        extended_memory: false,
+    };
+
+    /// SUPER-CHIP 1.1, which fixed CHIP-48's `FX55` and `FX65` to leave I alone.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_ignores_vy: true,
+        jump_uses_vx: true,
+        load_store_increments_i: false,
+        load_store_increments_i_by_x: false,
+        logic_resets_vf: false,
+        sprite_wrap: false,
+        display_wait: false,
+        index_overflow_sets_vf: false,
+        extended_memory: false,
+    };
+
+    /// XO-CHIP as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_ignores_vy: false,
+        jump_uses_vx: false,
+        load_store_increments_i: true,
+        load_store_increments_i_by_x: false,
+        logic_resets_vf: false,
+        sprite_wrap: true,
+        display_wait: false,
+        index_overflow_sets_vf: false,
+        extended_memory: true,
+    };
+
+    /// Names accepted by [`Quirks::from_name`].
+    pub const PRESET_NAMES: [&'static str; 4] = ["cosmac-vip", "chip-48", "super-chip", "xo-chip"];
+
+    /// Names accepted by [`Quirks::flag_mut`], one for each field.
+    pub const FLAG_NAMES: [&'static str; 9] = [
+        "shift-ignores-vy",
+        "jump-uses-vx",
+        "load-store-increments-i",
+        "load-store-increments-i-by-x",
+        "logic-resets-vf",
+        "sprite-wrap",
+        "display-wait",
+        "index-overflow-sets-vf",
+        "extended-memory",
+    ];
+
+    /// Looks up a preset by name, e.g. `"cosmac-vip"` or `"super-chip"`.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "cosmac-vip" | "vip" | "chip-8" => Some(Self::COSMAC_VIP),
+            "chip-48" => Some(Self::CHIP_48),
+            "super-chip" | "schip" => Some(Self::SUPER_CHIP),
+            "xo-chip" => Some(Self::XO_CHIP),
+            _ => None,
+        }
+    }
+
+    /// The flag with one of [`Quirks::FLAG_NAMES`], such as `"sprite-wrap"`, for overriding a
+    /// single quirk of a preset.
+    pub fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift-ignores-vy" => Some(&mut self.shift_ignores_vy),
+            "jump-uses-vx" => Some(&mut self.jump_uses_vx),
+            "load-store-increments-i" => Some(&mut self.load_store_increments_i),
+            "load-store-increments-i-by-x" => Some(&mut self.load_store_increments_i_by_x),
+            "logic-resets-vf" => Some(&mut self.logic_resets_vf),
+            "sprite-wrap" => Some(&mut self.sprite_wrap),
+            "display-wait" => Some(&mut self.display_wait),
+            "index-overflow-sets-vf" => Some(&mut self.index_overflow_sets_vf),
+            "extended-memory" => Some(&mut self.extended_memory),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::COSMAC_VIP
+    }
+}
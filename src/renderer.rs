@@ -0,0 +1,95 @@
+//! Draws the display as a single texture. Frames are uploaded only when they differ from the last
+//! one, then drawn scaled up with nearest-neighbour filtering.
+
+use raylib::prelude::*;
+
+#[derive(Debug)]
+pub struct Renderer {
+    /// The colour of the lines between the pixels, if they're drawn.
+    grid: Option<Color>,
+    /// The texture and the display size it was created for, which changes with the resolution.
+    texture: Option<(Texture2D, usize, usize)>,
+    /// The RGB pixels the texture was last uploaded from.
+    uploaded: Vec<u8>,
+    /// RGBA pixels for the texture, kept to avoid allocating every upload.
+    pixels: Vec<u8>,
+}
+
+impl Renderer {
+    pub fn new(grid: Option<Color>) -> Self {
+        Self {
+            grid,
+            texture: None,
+            uploaded: Vec::new(),
+            pixels: Vec::new(),
+        }
+    }
+
+    /// Uploads a `width` by `height` frame of 8-bit RGB pixels unless it's the one already in the
+    /// texture.
+    pub fn update(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        rgb: &[u8],
+        width: usize,
+        height: usize,
+    ) {
+        let resized = !matches!(self.texture, Some((_, w, h)) if (w, h) == (width, height));
+        if !resized && self.uploaded == rgb {
+            return;
+        }
+        if resized {
+            let image = Image::gen_image_color(width as i32, height as i32, Color::BLACK);
+            let texture = rl
+                .load_texture_from_image(thread, &image)
+                .expect("failed to create the display texture");
+            texture.set_texture_filter(thread, TextureFilter::TEXTURE_FILTER_POINT);
+            self.texture = Some((texture, width, height));
+        }
+
+        self.pixels.clear();
+        self.pixels.extend(
+            rgb.chunks(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF]),
+        );
+        if let Some((texture, _, _)) = &mut self.texture {
+            texture.update_texture(&self.pixels);
+        }
+        self.uploaded.clear();
+        self.uploaded.extend_from_slice(rgb);
+    }
+    /// Draws the last uploaded display scaled to fill `bounds`.
+    pub fn draw(&self, bounds: Rectangle, d: &mut RaylibDrawHandle) {
+        let Some((texture, width, height)) = &self.texture else {
+            return;
+        };
+        d.draw_texture_pro(
+            texture,
+            Rectangle::new(0.0, 0.0, *width as f32, *height as f32),
+            bounds,
+            Vector2::zero(),
+            0.0,
+            Color::WHITE,
+        );
+
+        // The lines take a window pixel off every display pixel, so leave them out when the
+        // pixels are too small to spare it.
+        let (pixel_width, pixel_height) =
+            (bounds.width / *width as f32, bounds.height / *height as f32);
+        let Some(color) = self.grid else {
+            return;
+        };
+        if pixel_width < 4.0 || pixel_height < 4.0 {
+            return;
+        }
+        for x in 1..*width {
+            let x = bounds.x + x as f32 * pixel_width;
+            d.draw_rectangle_rec(Rectangle::new(x, bounds.y, 1.0, bounds.height), color);
+        }
+        for y in 1..*height {
+            let y = bounds.y + y as f32 * pixel_height;
+            d.draw_rectangle_rec(Rectangle::new(bounds.x, y, bounds.width, 1.0), color);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! A ring buffer of the last few seconds of frames for playing a game backwards.
+//!
+//! Everything but memory is cloned for every frame. Memory is up to 64 KiB with XO-CHIP and rarely
+//! changes much between frames, so only the bytes that changed are kept.
+
+use std::collections::VecDeque;
+
+use crate::Chip8;
+
+/// The rate frames are recorded at, matching the timers.
+pub const FRAME_RATE: usize = 60;
+
+#[derive(Debug, Clone)]
+struct Frame {
+    /// The machine with its memory taken out.
+    chip8: Chip8,
+    /// The address and previous value of every byte that changed since the frame before, to turn
+    /// this frame's memory back into that frame's.
+    undo: Vec<(usize, u8)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    /// Memory as of the newest frame.
+    memory: Vec<u8>,
+}
+
+impl Rewind {
+    /// A buffer holding up to `seconds` worth of frames.
+    pub fn new(seconds: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            capacity: seconds * FRAME_RATE,
+            memory: Vec::new(),
+        }
+    }
+
+    /// Records `chip8` as the newest frame, dropping the oldest one once the buffer is full.
+    pub fn record(&mut self, chip8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+
+        let mut chip8 = chip8.clone();
+        let memory = chip8.take_memory();
+        if memory.len() != self.memory.len() {
+            // The first frame, or a machine with a different memory size that the older frames
+            // can't be turned into.
+            self.frames.clear();
+        }
+        let undo = memory
+            .iter()
+            .zip(&self.memory)
+            .enumerate()
+            .filter(|(_, (new, old))| new != old)
+            .map(|(addr, (_, old))| (addr, *old))
+            .collect();
+        self.memory = memory;
+        self.frames.push_back(Frame { chip8, undo });
+    }
+
+    /// Removes the newest frame and returns the machine as it was then, or `None` once the buffer
+    /// has run out.
+    pub fn pop(&mut self) -> Option<Chip8> {
+        let Frame { mut chip8, undo } = self.frames.pop_back()?;
+        chip8.set_memory(self.memory.clone());
+        for (addr, old) in undo {
+            self.memory[addr] = old;
+        }
+        Some(chip8)
+    }
+}
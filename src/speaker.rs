@@ -0,0 +1,72 @@
+//! Plays the beeper through raylib's audio device, or nowhere when there isn't one.
+
+use chip_8_interpreter::audio::{Beeper, Sound, Tone, DEFAULT_SAMPLE_RATE};
+use raylib::prelude::*;
+
+/// raylib's default audio stream buffer size. Every update fills a whole buffer.
+const SAMPLES_PER_UPDATE: usize = 4096;
+
+struct Output {
+    // Declared before `audio` so that the stream is closed before the device.
+    stream: AudioStream,
+    audio: RaylibAudio,
+}
+
+pub struct Speaker {
+    /// `None` when there is no audio device, in which case nothing is played.
+    output: Option<Output>,
+    beeper: Beeper,
+    muted: bool,
+    samples: Vec<f32>,
+}
+
+impl Speaker {
+    /// Opens the audio device, falling back to a silent speaker if there isn't one.
+    pub fn open(thread: &RaylibThread, tone: Tone) -> Self {
+        let mut audio = RaylibAudio::init_audio_device();
+        if !audio.is_audio_device_ready() {
+            eprintln!("warning: no audio device, sound is disabled");
+            return Self::silent(tone);
+        }
+        // 8-bit samples, since `update_audio_stream` passes raylib the length in bytes where it
+        // expects a number of samples.
+        let mut stream = AudioStream::init_audio_stream(thread, DEFAULT_SAMPLE_RATE, 8, 1);
+        audio.play_audio_stream(&mut stream);
+        Self {
+            output: Some(Output { stream, audio }),
+            ..Self::silent(tone)
+        }
+    }
+
+    /// A speaker that never plays anything.
+    pub fn silent(tone: Tone) -> Self {
+        Self {
+            output: None,
+            beeper: Beeper::new(tone, DEFAULT_SAMPLE_RATE),
+            muted: false,
+            samples: vec![0.0; SAMPLES_PER_UPDATE],
+        }
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /// Queues more of `sound` once the device has played what it had.
+    pub fn update(&mut self, sound: &Sound) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        if !output.audio.is_audio_stream_processed(&output.stream) {
+            return;
+        }
+        let sound = if self.muted { &Sound::SILENCE } else { sound };
+        self.beeper.fill(sound, &mut self.samples);
+        let samples = self
+            .samples
+            .iter()
+            .map(|sample| (128.0 + sample * 127.0) as u8)
+            .collect::<Vec<_>>();
+        output.stream.update_audio_stream(&samples);
+    }
+}
@@ -0,0 +1,224 @@
+//! Save states: snapshots of a [`Chip8`] that can be written to disk and restored later.
+//!
+//! A save state starts with the magic `C8ST`, a little endian `u16` format version and the
+//! [`rom_hash`] of the ROM it was taken with, followed by the machine state. Byte strings are
+//! prefixed with their length as a little endian `u32`.
+
+use std::fmt;
+
+use crate::{Chip8, Quirks};
+
+/// The version written by [`SaveState::to_bytes`]. Bumped whenever the layout changes.
+pub const VERSION: u16 = 4;
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Errors raised while reading or restoring a save state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the save state magic.
+    NotASaveState,
+    /// The save state was written by a different version of the format.
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of the machine state.
+    Truncated,
+    /// A field holds a value the machine can't be in.
+    Invalid(&'static str),
+    /// The save state was taken with a different ROM.
+    RomMismatch,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::NotASaveState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported save state version {}, expected {}",
+                version, VERSION
+            ),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+            StateError::RomMismatch => write!(f, "save state was taken with a different ROM"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// A snapshot of a machine along with the ROM it was running.
+#[derive(Debug, Clone)]
+pub struct SaveState {
+    rom_hash: u64,
+    chip8: Chip8,
+}
+
+impl SaveState {
+    /// Takes a snapshot of `chip8`, which is running `rom`.
+    pub fn capture(chip8: &Chip8, rom: &[u8]) -> Self {
+        Self {
+            rom_hash: rom_hash(rom),
+            chip8: chip8.clone(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(MAGIC);
+        writer.u16(VERSION);
+        writer.u64(self.rom_hash);
+        self.chip8.write_state(&mut writer);
+        writer.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(StateError::NotASaveState);
+        }
+        let mut reader = Reader::after_magic(bytes, MAGIC.len());
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.u64()?;
+        let chip8 = Chip8::read_state(&mut reader)?;
+        if !reader.at_end() {
+            return Err(StateError::Invalid("length"));
+        }
+        Ok(Self { rom_hash, chip8 })
+    }
+
+    /// The machine in the snapshot, provided `rom` is the ROM it was taken with.
+    pub fn restore(&self, rom: &[u8]) -> Result<Chip8, StateError> {
+        if rom_hash(rom) != self.rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+        Ok(self.chip8.clone())
+    }
+}
+
+/// The 64-bit FNV-1a hash of `rom`, which identifies the ROM a save state belongs to.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+pub(crate) fn write_quirks(w: &mut Writer, quirks: Quirks) {
+    for flag in [
+        quirks.shift_ignores_vy,
+        quirks.jump_uses_vx,
+        quirks.load_store_increments_i,
+        quirks.load_store_increments_i_by_x,
+        quirks.logic_resets_vf,
+        quirks.sprite_wrap,
+        quirks.display_wait,
+        quirks.index_overflow_sets_vf,
+        quirks.extended_memory,
+    ] {
+        w.bool(flag);
+    }
+}
+
+pub(crate) fn read_quirks(r: &mut Reader) -> Result<Quirks, StateError> {
+    Ok(Quirks {
+        shift_ignores_vy: r.bool()?,
+        jump_uses_vx: r.bool()?,
+        load_store_increments_i: r.bool()?,
+        load_store_increments_i_by_x: r.bool()?,
+        logic_resets_vf: r.bool()?,
+        sprite_wrap: r.bool()?,
+        display_wait: r.bool()?,
+        index_overflow_sets_vf: r.bool()?,
+        extended_memory: r.bool()?,
+    })
+}
+
+pub(crate) struct Writer(pub(crate) Vec<u8>);
+
+impl Writer {
+    pub(crate) fn u8(&mut self, val: u8) {
+        self.0.push(val);
+    }
+
+    pub(crate) fn u16(&mut self, val: u16) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, val: u32) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, val: u64) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, val: bool) {
+        self.u8(val as u8);
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// A reader for `bytes` positioned after the `len` byte magic.
+    pub(crate) fn after_magic(bytes: &'a [u8], len: usize) -> Self {
+        Self { bytes, pos: len }
+    }
+
+    pub(crate) fn at_end(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], StateError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or(StateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self
+            .take(N)?
+            .try_into()
+            .expect("slice has the requested length"))
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&[u8], StateError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
@@ -0,0 +1,219 @@
+//! Pixel art scalers, which enlarge an image while keeping edges sharp and smoothing out the
+//! staircases along diagonals. They're pure functions over a row by row image, so they work on
+//! palette indices and colours alike, and give the same result in the window and in exported images.
+//!
+//! Pixels beyond the edges of the image count as copies of the nearest edge pixel.
+
+/// A scaler that can be picked by name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Scaler {
+    /// No scaling: the renderer enlarges pixels into blocks.
+    #[default]
+    None,
+    Scale2x,
+    Scale3x,
+    Hq2x,
+}
+
+impl Scaler {
+    /// Names accepted by [`Scaler::from_name`].
+    pub const NAMES: [&'static str; 4] = ["none", "scale2x", "scale3x", "hq2x"];
+
+    /// Looks up a scaler by name, e.g. `"scale2x"`. `"epx"` is Scale2x under its original name.
+    pub fn from_name(name: &str) -> Option<Scaler> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(Scaler::None),
+            "scale2x" | "epx" => Some(Scaler::Scale2x),
+            "scale3x" => Some(Scaler::Scale3x),
+            "hq2x" => Some(Scaler::Hq2x),
+            _ => None,
+        }
+    }
+
+    /// How many times wider and higher the scaled image is.
+    pub fn factor(self) -> usize {
+        match self {
+            Scaler::None => 1,
+            Scaler::Scale2x | Scaler::Hq2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+
+    /// Scales a `width` by `height` image of 8-bit RGB pixels.
+    pub fn apply(self, width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+        let pixels = rgb
+            .chunks(3)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect::<Vec<_>>();
+        let scaled = match self {
+            Scaler::None => pixels,
+            Scaler::Scale2x => scale2x(width, height, &pixels),
+            Scaler::Scale3x => scale3x(width, height, &pixels),
+            Scaler::Hq2x => hq2x(width, height, &pixels),
+        };
+        scaled.concat()
+    }
+}
+
+/// The pixel at `x`, `y` offset by `dx`, `dy`, clamped to the image.
+fn neighbour<T: Copy>(
+    width: usize,
+    height: usize,
+    pixels: &[T],
+    (x, y): (usize, usize),
+    (dx, dy): (isize, isize),
+) -> T {
+    let x = x.saturating_add_signed(dx).min(width - 1);
+    let y = y.saturating_add_signed(dy).min(height - 1);
+    pixels[y * width + x]
+}
+
+/// Calls `scale` with the 3x3 neighbourhood of every pixel, row by row with the pixel in the
+/// middle, and puts the `factor` by `factor` block it returns in its place.
+fn scale_blocks<T: Copy, const N: usize>(
+    width: usize,
+    height: usize,
+    pixels: &[T],
+    factor: usize,
+    scale: impl Fn([T; 9]) -> [T; N],
+) -> Vec<T> {
+    assert_eq!(
+        pixels.len(),
+        width * height,
+        "pixel data doesn't match the size"
+    );
+    assert_eq!(N, factor * factor, "blocks don't match the factor");
+    let mut scaled = Vec::with_capacity(pixels.len() * N);
+    if pixels.is_empty() {
+        return scaled;
+    }
+    // Filled in a row of blocks at a time.
+    let mut rows = vec![Vec::with_capacity(width * factor); factor];
+    for y in 0..height {
+        for x in 0..width {
+            let mut around = [pixels[y * width + x]; 9];
+            for (i, pixel) in around.iter_mut().enumerate() {
+                let offset = (i as isize % 3 - 1, i as isize / 3 - 1);
+                *pixel = neighbour(width, height, pixels, (x, y), offset);
+            }
+            let block = scale(around);
+            for (row, block_row) in rows.iter_mut().zip(block.chunks(factor)) {
+                row.extend_from_slice(block_row);
+            }
+        }
+        for row in &mut rows {
+            scaled.append(row);
+        }
+    }
+    scaled
+}
+
+/// Scale2x, also known as EPX: doubles the image, filling each corner of a pixel's block with the
+/// neighbours on that side when they match and cut the corner off.
+pub fn scale2x<T: Copy + PartialEq>(width: usize, height: usize, pixels: &[T]) -> Vec<T> {
+    scale_blocks(width, height, pixels, 2, |[_, b, _, d, e, f, _, h, _]| {
+        [
+            if d == b && d != h && b != f { d } else { e },
+            if b == f && b != d && f != h { f } else { e },
+            if d == h && d != b && h != f { d } else { e },
+            if h == f && h != d && f != b { f } else { e },
+        ]
+    })
+}
+
+/// Scale3x: the same idea as [`scale2x`] at three times the size, where the edge pixels of a block
+/// also follow a diagonal that continues past it.
+pub fn scale3x<T: Copy + PartialEq>(width: usize, height: usize, pixels: &[T]) -> Vec<T> {
+    scale_blocks(width, height, pixels, 3, |[a, b, c, d, e, f, g, h, i]| {
+        let top_left = d == b && d != h && b != f;
+        let top_right = b == f && b != d && f != h;
+        let bottom_left = d == h && d != b && h != f;
+        let bottom_right = h == f && h != d && f != b;
+        [
+            if top_left { d } else { e },
+            if (top_left && e != c) || (top_right && e != a) {
+                b
+            } else {
+                e
+            },
+            if top_right { f } else { e },
+            if (top_left && e != g) || (bottom_left && e != a) {
+                d
+            } else {
+                e
+            },
+            e,
+            if (top_right && e != i) || (bottom_right && e != c) {
+                f
+            } else {
+                e
+            },
+            if bottom_left { d } else { e },
+            if (bottom_left && e != i) || (bottom_right && e != g) {
+                h
+            } else {
+                e
+            },
+            if bottom_right { f } else { e },
+        ]
+    })
+}
+
+/// An HQ2x style scaler: where an edge cuts the corner of a pixel's block, the corner is blended
+/// between the pixel and the edge instead of taken over by the edge, which smooths diagonals.
+///
+/// HQ2x proper tells colours apart by thresholds in YUV and picks blends from a table of all 256
+/// neighbourhoods. CHIP-8 displays only have a few flat colours, so this compares colours exactly
+/// and keeps to the cases that come up with them: a corner is blended 3:1 towards the edge where the
+/// edge is solid beyond it and 1:1 where it's a thin line.
+pub fn hq2x(width: usize, height: usize, pixels: &[[u8; 3]]) -> Vec<[u8; 3]> {
+    let corner = |e: [u8; 3], side: [u8; 3], vertical: [u8; 3], diagonal: [u8; 3]| {
+        if side != vertical || side == e {
+            return e;
+        }
+        let edge_weight = if diagonal == side { 3 } else { 2 };
+        blend(e, side, edge_weight)
+    };
+    scale_blocks(width, height, pixels, 2, |[a, b, c, d, e, f, g, h, i]| {
+        [
+            corner(e, d, b, a),
+            corner(e, f, b, c),
+            corner(e, d, h, g),
+            corner(e, f, h, i),
+        ]
+    })
+}
+
+/// `pixel` and `edge` mixed with `edge_weight` parts of `edge` to four in total.
+fn blend(pixel: [u8; 3], edge: [u8; 3], edge_weight: u16) -> [u8; 3] {
+    let mut blended = [0; 3];
+    for ((blended, pixel), edge) in blended.iter_mut().zip(pixel).zip(edge) {
+        *blended = ((pixel as u16 * (4 - edge_weight) + edge as u16 * edge_weight) / 4) as u8;
+    }
+    blended
+}
+
+/// Resizes a `width` by `height` image of 8-bit RGB pixels to `new_width` by `new_height`, taking
+/// the nearest pixel. Enlarging by a whole number turns each pixel into a block.
+pub fn nearest(
+    width: usize,
+    height: usize,
+    rgb: &[u8],
+    new_width: usize,
+    new_height: usize,
+) -> Vec<u8> {
+    assert_eq!(
+        rgb.len(),
+        width * height * 3,
+        "pixel data doesn't match the size"
+    );
+    let mut resized = Vec::with_capacity(new_width * new_height * 3);
+    for y in 0..new_height {
+        let row = y * height / new_height * width;
+        for x in 0..new_width {
+            let i = (row + x * width / new_width) * 3;
+            resized.extend_from_slice(&rgb[i..i + 3]);
+        }
+    }
+    resized
+}
@@ -0,0 +1,309 @@
+/// Everything the CPU is connected to.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+    /// `INP N` for `N` in `1..=7`, returning the byte placed on the data bus.
+    fn input(&mut self, port: u8) -> u8;
+    /// `OUT N` for `N` in `1..=7`, with the byte read from memory.
+    fn output(&mut self, port: u8, val: u8);
+    /// Whether external flag `EF1` through `EF4` is asserted.
+    fn flag(&self, n: u8) -> bool;
+}
+
+/// An RCA CDP1802 COSMAC microprocessor.
+///
+/// Timing is counted in machine cycles of eight clock pulses each. Most instructions take two
+/// machine cycles, long branches and skips take three, and DMA and interrupt cycles take one.
+#[derive(Debug, Clone)]
+pub struct Cdp1802 {
+    /// Scratchpad registers `R0` through `RF`.
+    pub r: [u16; 16],
+    /// Selects the program counter.
+    pub p: u8,
+    /// Selects the data pointer.
+    pub x: u8,
+    /// Accumulator.
+    pub d: u8,
+    /// Data flag, the carry and not-borrow of the ALU.
+    pub df: bool,
+    /// Holds the old `X` and `P` on interrupts and `MARK`.
+    pub t: u8,
+    /// Interrupt enable.
+    pub ie: bool,
+    /// Output flip-flop.
+    pub q: bool,
+    /// Set by `IDL` until the next DMA or interrupt request.
+    pub idle: bool,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1802 {
+    /// A CPU in its reset state: `X`, `P`, `Q` and `R0` cleared and interrupts enabled.
+    pub fn new() -> Self {
+        Self {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+
+    /// Performs one DMA-out cycle, returning the byte at `R0` and advancing `R0`.
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let val = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        val
+    }
+
+    /// Takes an interrupt if interrupts are enabled, returning whether it did. Takes one machine
+    /// cycle when taken.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = self.x << 4 | self.p;
+        self.x = 2;
+        self.p = 1;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    /// Executes one instruction and returns the number of machine cycles it took.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+
+        let op = self.fetch(bus);
+        let (i, n) = (op >> 4, op & 0x0F);
+        let n_reg = n as usize;
+        match i {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[n_reg]),
+            0x1 => self.r[n_reg] = self.r[n_reg].wrapping_add(1),
+            0x2 => self.r[n_reg] = self.r[n_reg].wrapping_sub(1),
+            0x3 => {
+                // The branch stays in the page of the target byte, which is the next page when
+                // the opcode is the last byte of a page.
+                let page = self.r[self.p as usize] & 0xFF00;
+                let taken = self.condition(n, bus);
+                let target = self.fetch(bus);
+                if taken {
+                    self.r[self.p as usize] = page | target as u16;
+                }
+            }
+            0x4 => {
+                self.d = bus.read(self.r[n_reg]);
+                self.r[n_reg] = self.r[n_reg].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n_reg], self.d),
+            0x6 => match n {
+                0x0 => self.inc_x(),
+                0x1..=0x7 => {
+                    let val = bus.read(self.rx());
+                    bus.output(n, val);
+                    self.inc_x();
+                }
+                // `68` is unused on the 1802.
+                0x8 => {}
+                _ => {
+                    let val = bus.input(n - 8);
+                    bus.write(self.rx(), val);
+                    self.d = val;
+                }
+            },
+            0x7 => self.execute_7(n, bus),
+            0x8 => self.d = self.r[n_reg] as u8,
+            0x9 => self.d = (self.r[n_reg] >> 8) as u8,
+            0xA => self.r[n_reg] = (self.r[n_reg] & 0xFF00) | self.d as u16,
+            0xB => self.r[n_reg] = (self.r[n_reg] & 0x00FF) | (self.d as u16) << 8,
+            0xC => {
+                self.execute_long(n, bus);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.execute_f(n, bus),
+        }
+        2
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = &mut self.r[self.p as usize];
+        let val = bus.read(*pc);
+        *pc = pc.wrapping_add(1);
+        val
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    fn inc_x(&mut self) {
+        let rx = &mut self.r[self.x as usize];
+        *rx = rx.wrapping_add(1);
+    }
+
+    /// The condition tested by short branch `3N`. Conditions `8` to `F` are the inverse of `0` to
+    /// `7`.
+    fn condition(&self, n: u8, bus: &impl Bus) -> bool {
+        let cond = match n & 0x7 {
+            0x0 => true,
+            0x1 => self.q,
+            0x2 => self.d == 0,
+            0x3 => self.df,
+            ef => bus.flag(ef - 3),
+        };
+        cond != (n & 0x8 != 0)
+    }
+
+    fn add(&mut self, a: u8, b: u8, carry: bool) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// `a - b`, with `DF` set when there is no borrow.
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) {
+        let diff = a as i16 - b as i16 - borrow as i16;
+        self.d = diff as u8;
+        self.df = diff >= 0;
+    }
+
+    fn execute_7(&mut self, n: u8, bus: &mut impl Bus) {
+        match n {
+            0x0 | 0x1 => {
+                let val = bus.read(self.rx());
+                self.inc_x();
+                self.x = val >> 4;
+                self.p = val & 0x0F;
+                self.ie = n == 0x0;
+            }
+            0x2 => {
+                self.d = bus.read(self.rx());
+                self.inc_x();
+            }
+            0x3 => {
+                bus.write(self.rx(), self.d);
+                let rx = &mut self.r[self.x as usize];
+                *rx = rx.wrapping_sub(1);
+            }
+            0x4 => {
+                let val = bus.read(self.rx());
+                self.add(val, self.d, self.df);
+            }
+            0x5 => {
+                let val = bus.read(self.rx());
+                self.subtract(val, self.d, !self.df);
+            }
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 0x01 != 0;
+                self.d = self.d >> 1 | (carry as u8) << 7;
+            }
+            0x7 => {
+                let val = bus.read(self.rx());
+                self.subtract(self.d, val, !self.df);
+            }
+            0x8 => bus.write(self.rx(), self.t),
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let val = self.fetch(bus);
+                self.add(val, self.d, self.df);
+            }
+            0xD => {
+                let val = self.fetch(bus);
+                self.subtract(val, self.d, !self.df);
+            }
+            0xE => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | carry as u8;
+            }
+            _ => {
+                let val = self.fetch(bus);
+                self.subtract(self.d, val, !self.df);
+            }
+        }
+    }
+
+    fn execute_long(&mut self, n: u8, bus: &mut impl Bus) {
+        // `C4` is `NOP`, `C5` to `C7` and `CC` to `CF` are long skips, the rest long branches.
+        let (cond, is_skip) = match n {
+            0x0 => (true, false),
+            0x1 => (self.q, false),
+            0x2 => (self.d == 0, false),
+            0x3 => (self.df, false),
+            0x4 => (false, true),
+            0x5 => (!self.q, true),
+            0x6 => (self.d != 0, true),
+            0x7 => (!self.df, true),
+            0x8 => (true, true),
+            0x9 => (!self.q, false),
+            0xA => (self.d != 0, false),
+            0xB => (!self.df, false),
+            0xC => (self.ie, true),
+            0xD => (self.q, true),
+            0xE => (self.d == 0, true),
+            _ => (self.df, true),
+        };
+
+        let pc = self.p as usize;
+        if is_skip {
+            if cond {
+                self.r[pc] = self.r[pc].wrapping_add(2);
+            }
+        } else {
+            let hi = self.fetch(bus);
+            let lo = self.fetch(bus);
+            if cond {
+                self.r[pc] = u16::from_be_bytes([hi, lo]);
+            }
+        }
+    }
+
+    fn execute_f(&mut self, n: u8, bus: &mut impl Bus) {
+        // `F8` to `FF` are the immediate forms of `F0` to `F7`, reading the operand from `R(P)`.
+        let val = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => bus.read(self.rx()),
+            _ => self.fetch(bus),
+        };
+        match n & 0x7 {
+            0x0 => self.d = val,
+            0x1 => self.d |= val,
+            0x2 => self.d &= val,
+            0x3 => self.d ^= val,
+            0x4 => self.add(val, self.d, false),
+            0x5 => self.subtract(val, self.d, false),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 0x01 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.subtract(self.d, val, false),
+        }
+    }
+}
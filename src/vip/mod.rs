@@ -0,0 +1,244 @@
+//! A low-level COSMAC VIP that runs the original CHIP-8 interpreter on an emulated RCA 1802.
+//!
+//! Unlike [`Chip8`](crate::Chip8), which executes CHIP-8 instructions directly, this runs the
+//! 512-byte interpreter image RCA shipped with the VIP. That makes it slow but authoritative:
+//! whatever the interpreter does with an ambiguous instruction is by definition what the VIP did,
+//! `0NNN` machine code subroutines work, and every instruction takes as many machine cycles as it
+//! did on the real hardware.
+//!
+//! The interpreter and monitor ROM images are not bundled and have to be supplied by the user.
+
+mod cpu;
+mod video;
+
+pub use cpu::{Bus, Cdp1802};
+pub use video::{Cdp1861, CYCLES_PER_FRAME, DISPLAY_LINES};
+
+use crate::Chip8Error;
+
+/// The VIP runs the 1802 at 1.76064 MHz, with eight clock pulses per machine cycle.
+pub const CLOCK_HZ: u32 = 1_760_640;
+pub const MACHINE_CYCLES_PER_SECOND: u32 = CLOCK_HZ / 8;
+pub const RAM_SIZE: usize = 4096;
+/// Width of [`CosmacVip::framebuffer`].
+pub const VIP_WIDTH: usize = 64;
+/// Height of [`CosmacVip::framebuffer`]. The CHIP-8 interpreter repeats every one of its 32 rows
+/// on four lines.
+pub const VIP_HEIGHT: usize = DISPLAY_LINES;
+
+const INTERPRETER_SIZE: usize = 0x200;
+const MONITOR_SIZE: usize = 0x200;
+
+/// Memory, keypad and video as seen from the CPU.
+#[derive(Debug, Clone)]
+struct VipBus {
+    ram: Vec<u8>,
+    monitor: Option<Vec<u8>>,
+    /// After a reset the monitor ROM also appears at `0x0000` until an address with A15 set is
+    /// accessed.
+    monitor_at_zero: bool,
+    keys: [bool; 16],
+    /// The key selected by `OUT 2`, whose state is reported on `EF3`.
+    latched_key: u8,
+    video: Cdp1861,
+}
+
+impl Bus for VipBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr & 0x8000 != 0 {
+            self.monitor_at_zero = false;
+        }
+        if addr & 0x8000 != 0 || self.monitor_at_zero {
+            let offset = addr as usize % MONITOR_SIZE;
+            return self.monitor.as_ref().map_or(0, |monitor| monitor[offset]);
+        }
+        self.ram[addr as usize % self.ram.len()]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if addr & 0x8000 != 0 {
+            // The monitor is read-only.
+            self.monitor_at_zero = false;
+            return;
+        }
+        if !self.monitor_at_zero {
+            let len = self.ram.len();
+            self.ram[addr as usize % len] = val;
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.video.set_enabled(true);
+        }
+        0
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        match port {
+            1 => self.video.set_enabled(false),
+            2 => self.latched_key = val & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn flag(&self, n: u8) -> bool {
+        match n {
+            1 => self.video.ef1(),
+            3 => self.keys[self.latched_key as usize],
+            _ => false,
+        }
+    }
+}
+
+/// A COSMAC VIP with 4 KiB of RAM, the CHIP-8 interpreter at `0x0000` and the program at `0x0200`.
+#[derive(Debug, Clone)]
+pub struct CosmacVip {
+    cpu: Cdp1802,
+    bus: VipBus,
+    cycles: u64,
+    frames: u64,
+}
+
+impl CosmacVip {
+    /// Creates a VIP with the `interpreter` image in RAM.
+    ///
+    /// With a `monitor` ROM image the machine starts in the monitor like a real VIP, which then
+    /// jumps to the interpreter. Without one the state the monitor hands over is set up directly,
+    /// but interpreter routines that call into the monitor, such as `FX0A`, won't work.
+    pub fn new(interpreter: &[u8], monitor: Option<&[u8]>) -> Result<Self, Chip8Error> {
+        if interpreter.len() > INTERPRETER_SIZE {
+            return Err(Chip8Error::RomTooLarge {
+                len: interpreter.len(),
+                max: INTERPRETER_SIZE,
+            });
+        }
+        if let Some(monitor) = monitor {
+            if monitor.len() > MONITOR_SIZE {
+                return Err(Chip8Error::RomTooLarge {
+                    len: monitor.len(),
+                    max: MONITOR_SIZE,
+                });
+            }
+        }
+
+        let mut ram = vec![0u8; RAM_SIZE];
+        ram[..interpreter.len()].copy_from_slice(interpreter);
+        let monitor = monitor.map(|monitor| {
+            let mut rom = vec![0u8; MONITOR_SIZE];
+            rom[..monitor.len()].copy_from_slice(monitor);
+            rom
+        });
+
+        let mut cpu = Cdp1802::new();
+        let monitor_at_zero = monitor.is_some();
+        if !monitor_at_zero {
+            // The monitor leaves the page of the top of RAM in R1.1 for the interpreter, which
+            // keeps its variables and display buffer there.
+            cpu.r[1] = (RAM_SIZE as u16 - 0x100) & 0xFF00;
+        }
+
+        Ok(Self {
+            cpu,
+            bus: VipBus {
+                ram,
+                monitor,
+                monitor_at_zero,
+                keys: [false; 16],
+                latched_key: 0,
+                video: Cdp1861::new(),
+            },
+            cycles: 0,
+            frames: 0,
+        })
+    }
+
+    /// Copies `rom` into RAM at `0x200`, right after the interpreter.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let start = INTERPRETER_SIZE;
+        // The interpreter keeps its variables and display buffer in the top 352 bytes of RAM.
+        let max = RAM_SIZE - start - 0x160;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                len: rom.len(),
+                max,
+            });
+        }
+        self.bus.ram[start..(start + rom.len())].copy_from_slice(rom);
+        Ok(())
+    }
+
+    /// Runs for at least `cycles` machine cycles. Instructions are never split, so this may
+    /// overshoot by up to two cycles.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let target = self.cycles + cycles;
+        while self.cycles < target {
+            let taken = if self.bus.video.dma_pending() {
+                let val = self.cpu.dma_out(&mut self.bus);
+                self.bus.video.dma(val);
+                1
+            } else if self.bus.video.interrupt_pending() && self.cpu.interrupt() {
+                self.bus.video.acknowledge_interrupt();
+                1
+            } else {
+                self.cpu.step(&mut self.bus)
+            };
+
+            for _ in 0..taken {
+                if self.bus.video.tick() {
+                    self.frames += 1;
+                }
+            }
+            self.cycles += taken as u64;
+        }
+    }
+
+    /// Runs until the video chip has finished the current frame, which takes
+    /// [`CYCLES_PER_FRAME`] machine cycles, about 1/60 of a second.
+    pub fn run_frame(&mut self) {
+        let frames = self.frames;
+        while self.frames == frames {
+            self.run_cycles(1);
+        }
+    }
+
+    /// Machine cycles executed since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Video frames completed since power on.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// The display as [`VIP_WIDTH`] by [`VIP_HEIGHT`] pixels in row-major order, 1 for lit
+    /// pixels and 0 otherwise, matching [`Chip8::framebuffer`](crate::Chip8::framebuffer).
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.bus
+            .video
+            .frame()
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 0x1))
+            .collect()
+    }
+
+    /// Sets whether hex key `key` (`0x0` through `0xF`) is held down.
+    pub fn set_key(&mut self, key: u8, is_pressed: bool) {
+        self.bus.keys[(key & 0x0F) as usize] = is_pressed;
+    }
+
+    /// Whether the beeper is sounding, which the VIP drives from the 1802's Q output.
+    pub fn sound_on(&self) -> bool {
+        self.cpu.q
+    }
+
+    pub fn cpu(&self) -> &Cdp1802 {
+        &self.cpu
+    }
+
+    /// RAM, where the CHIP-8 interpreter keeps V0 through VF in the page at the top.
+    pub fn memory(&self) -> &[u8] {
+        &self.bus.ram
+    }
+}
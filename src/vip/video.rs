@@ -0,0 +1,122 @@
+pub const CYCLES_PER_LINE: u32 = 14;
+pub const LINES_PER_FRAME: u32 = 262;
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES_PER_FRAME;
+/// Bytes fetched by DMA for every displayed line, one bit per pixel.
+pub const BYTES_PER_LINE: usize = 8;
+pub const DISPLAY_LINES: usize = 128;
+
+const INTERRUPT_LINE: u32 = 62;
+const DISPLAY_START_LINE: u32 = 64;
+const DISPLAY_END_LINE: u32 = DISPLAY_START_LINE + DISPLAY_LINES as u32;
+/// The machine cycle within a line at which the DMA requests start, which puts the first DMA 29
+/// cycles after the interrupt as the VIP documentation describes.
+const DMA_START_CYCLE: u32 = 1;
+
+/// An RCA CDP1861 "Pixie" video display controller.
+///
+/// The 1861 steals cycles from the CPU through DMA to fetch every displayed line, and tells the
+/// CPU where the beam is through an interrupt before the first line and the `EF1` flag around the
+/// start and end of the display.
+#[derive(Debug, Clone)]
+pub struct Cdp1861 {
+    enabled: bool,
+    /// Machine cycle within the current frame.
+    frame_cycle: u32,
+    interrupt_pending: bool,
+    dma_pending: usize,
+    dma_index: usize,
+    frame: [u8; BYTES_PER_LINE * DISPLAY_LINES],
+}
+
+impl Default for Cdp1861 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1861 {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            frame_cycle: 0,
+            interrupt_pending: false,
+            dma_pending: 0,
+            dma_index: 0,
+            frame: [0; BYTES_PER_LINE * DISPLAY_LINES],
+        }
+    }
+
+    /// Turned on by `INP 1` and off by `OUT 1`. While off no interrupts or DMA are requested and
+    /// the screen is blank.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.interrupt_pending = false;
+            self.dma_pending = 0;
+            self.frame = [0; BYTES_PER_LINE * DISPLAY_LINES];
+        }
+    }
+
+    fn line(&self) -> u32 {
+        self.frame_cycle / CYCLES_PER_LINE
+    }
+
+    /// `EF1` is asserted for the four lines before the display and the last four display lines.
+    pub fn ef1(&self) -> bool {
+        let line = self.line();
+        (DISPLAY_START_LINE - 4..DISPLAY_START_LINE).contains(&line)
+            || (DISPLAY_END_LINE - 4..DISPLAY_END_LINE).contains(&line)
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.interrupt_pending
+    }
+
+    pub fn acknowledge_interrupt(&mut self) {
+        self.interrupt_pending = false;
+    }
+
+    pub fn dma_pending(&self) -> bool {
+        self.dma_pending > 0
+    }
+
+    /// Stores a byte fetched by a DMA cycle.
+    pub fn dma(&mut self, val: u8) {
+        if let Some(byte) = self.frame.get_mut(self.dma_index) {
+            *byte = val;
+        }
+        self.dma_index += 1;
+        self.dma_pending -= 1;
+    }
+
+    /// Advances the beam by one machine cycle. Returns whether a frame was completed.
+    pub fn tick(&mut self) -> bool {
+        self.frame_cycle += 1;
+        if self.frame_cycle == CYCLES_PER_FRAME {
+            self.frame_cycle = 0;
+        }
+
+        let (line, cycle) = (self.line(), self.frame_cycle % CYCLES_PER_LINE);
+        if cycle == 0 && line == INTERRUPT_LINE && self.enabled {
+            self.interrupt_pending = true;
+        }
+        if cycle == 0 && line == DISPLAY_START_LINE {
+            // The interrupt request is only held until the display starts.
+            self.interrupt_pending = false;
+            self.dma_index = 0;
+        }
+        if cycle == DMA_START_CYCLE
+            && (DISPLAY_START_LINE..DISPLAY_END_LINE).contains(&line)
+            && self.enabled
+        {
+            self.dma_pending += BYTES_PER_LINE;
+        }
+
+        self.frame_cycle == 0
+    }
+
+    /// The last displayed frame, 64 pixels wide and [`DISPLAY_LINES`] high, one bit per pixel.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+}
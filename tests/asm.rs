@@ -0,0 +1,174 @@
+//! Checks the Octo assembler against programs assembled by hand, and against the disassembler.
+
+use chip_8_interpreter::asm::{assemble, AsmError};
+use chip_8_interpreter::{disasm, Quirks};
+
+use std::path::Path;
+
+fn rom(source: &str) -> Vec<u8> {
+    assemble(source).unwrap().rom().to_vec()
+}
+
+fn error(source: &str) -> AsmError {
+    assemble(source).unwrap_err()
+}
+
+#[test]
+fn labels_are_resolved_forwards_and_backwards() {
+    let assembly = assemble(
+        ": main
+            jump later
+        : back
+            return
+        : later
+            back
+            i := data
+        : data
+            0xFF",
+    )
+    .unwrap();
+    assert_eq!(
+        assembly.rom(),
+        [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02, 0xA2, 0x08, 0xFF]
+    );
+    assert_eq!(assembly.labels()["main"], 0x200);
+    assert_eq!(assembly.labels()["back"], 0x202);
+    assert_eq!(assembly.labels()["data"], 0x208);
+    assert_eq!(assembly.line_at(0x204), Some(6));
+}
+
+#[test]
+fn long_addresses_reach_past_0xfff() {
+    let rom = rom("i := long data
+        :org 0x1000
+        : data
+            0x12");
+    assert_eq!(&rom[..4], [0xF0, 0x00, 0x10, 0x00]);
+    assert_eq!(rom.len(), 0x1000 - 0x200 + 1);
+}
+
+/// Operators have no precedence and are evaluated from right to left, like in Octo.
+#[test]
+fn calc_evaluates_right_to_left() {
+    let rom = rom(":calc four { 2 * 1 + 1 }
+        :calc seven { ( 2 * 3 ) + 1 }
+        :calc mask { 0xF0 | 1 << 2 }
+        v0 := four
+        v1 := seven
+        v2 := mask
+        :byte { HERE - 0x200 }");
+    assert_eq!(rom, [0x60, 0x04, 0x61, 0x07, 0x62, 0xF4, 0x06]);
+}
+
+#[test]
+fn macros_substitute_their_arguments() {
+    let rom = rom(":macro set-both reg value { reg := value v1 := value }
+        :macro twice reg { set-both reg 1 set-both reg 2 }
+        twice v2");
+    assert_eq!(rom, [0x62, 0x01, 0x61, 0x01, 0x62, 0x02, 0x61, 0x02]);
+}
+
+#[test]
+fn macros_that_invoke_themselves_are_rejected() {
+    let err = error(
+        ":macro forever { forever }
+        forever",
+    );
+    assert_eq!(err.line, 2);
+    assert!(err.message.contains("invoke itself"), "{}", err);
+
+    let err = error(
+        ":macro ping { pong }
+        :macro pong { ping }
+        ping",
+    );
+    assert!(err.message.contains("invoke itself"), "{}", err);
+}
+
+#[test]
+fn unpack_splits_an_address_between_v0_and_v1() {
+    assert_eq!(
+        rom(":unpack 0xA data
+            : data"),
+        [0x60, 0xA2, 0x61, 0x04]
+    );
+    assert_eq!(rom(":unpack 1 0x345"), [0x60, 0x13, 0x61, 0x45]);
+}
+
+/// `:unpack` only has a nibble for the high part of the address, so a label past `0xFFF` mustn't
+/// spill into the nibble given to it.
+#[test]
+fn unpack_rejects_labels_out_of_reach() {
+    let err = error(
+        ":unpack 0 far
+        :org 0x1000
+        : far",
+    );
+    assert_eq!(err.line, 1);
+    assert!(err.message.contains("out of reach"), "{}", err);
+
+    let err = error(
+        "jump far
+        :org 0x1000
+        : far",
+    );
+    assert_eq!(err.line, 1);
+    assert!(err.message.contains("out of reach"), "{}", err);
+
+    let err = error(
+        ":org 0x1000
+        loop
+            v0 += 1
+        again",
+    );
+    assert_eq!(err.line, 4);
+    assert!(err.message.contains("out of reach"), "{}", err);
+}
+
+#[test]
+fn errors_name_the_line() {
+    let cases = [
+        ("v0 := 1\nnowhere", 2, "undefined name `nowhere`"),
+        ("if v0 == 1 begin\nv1 := 2", 2, "`begin` without `end`"),
+        ("loop\nv0 += 1", 2, "`loop` without `again`"),
+        ("again", 1, "`again` without `loop`"),
+        ("v0 := 256", 1, "256 does not fit into a byte"),
+        ("v0 <> v1", 1, "unknown operator `<> v1`"),
+        ("jump 0x1000", 1, "0x1000 is out of range"),
+        (": main\n: main", 2, "`main` is already defined"),
+        (":calc x { 1 + }", 1, "incomplete `:calc` expression"),
+        (":macro m {\nv0 := 1", 1, "`{` without `}`"),
+        ("v0 := 1\n:calc x { 1 << 64 }", 2, "cannot shift by 64"),
+        (":calc x { 1 >> -1 }", 1, "cannot shift by -1"),
+    ];
+    for (source, line, message) in cases {
+        let err = error(source);
+        assert_eq!(err.line, line, "{:?}: {}", source, err);
+        assert!(err.message.contains(message), "{:?}: {}", source, err);
+    }
+}
+
+/// Disassembling the bundled ROMs as Octo source and assembling them again gives back the same
+/// bytes.
+#[test]
+fn disassembly_assembles_back_to_the_rom() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let original = std::fs::read(&path).unwrap();
+        let source = disasm::octo(&original);
+        let assembly =
+            assemble(&source).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        assert_eq!(assembly.rom(), original, "{}", path.display());
+    }
+}
+
+/// `BXNN` adds VX rather than V0 on CHIP-48 and SUPER-CHIP, and the listing says so.
+#[test]
+fn listing_shows_the_register_jump0_adds() {
+    let rom = rom("jump0 0x345");
+    let vip = disasm::listing(&rom, &Quirks::COSMAC_VIP);
+    assert!(vip.contains("B345       JP V0, 0x345"), "{}", vip);
+    let schip = disasm::listing(&rom, &Quirks::SUPER_CHIP);
+    assert!(schip.contains("B345       JP V3, 0x345"), "{}", schip);
+}
@@ -0,0 +1,100 @@
+//! Checks the WAV files sound is rendered to, sample by sample.
+
+use chip_8_interpreter::audio::{Capture, Tone, Waveform};
+use chip_8_interpreter::movie::Frame;
+use chip_8_interpreter::{asm, Chip8, Quirks};
+
+/// Runs `source` for `frames` frames the way `headless --audio-out` does, and returns the WAV.
+fn render(source: &str, tone: Tone, sample_rate: u32, frames: usize) -> Vec<u8> {
+    let assembly = asm::assemble(source).unwrap();
+    let mut chip8 = Chip8::with_quirks(Quirks::XO_CHIP);
+    chip8.load_rom(assembly.rom()).unwrap();
+    let mut capture = Capture::new(tone, sample_rate);
+    let frame = Frame { keys: 0, steps: 20 };
+    for _ in 0..frames {
+        frame.run_steps(&mut chip8).unwrap();
+        capture.frame(&chip8);
+        chip8.tick_timers();
+    }
+    capture.wav()
+}
+
+/// Checks the header of a mono 16-bit WAV file at `sample_rate` and returns its samples.
+fn samples(wav: &[u8], sample_rate: u32) -> Vec<i16> {
+    let u32_at = |offset: usize| u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap());
+    let u16_at = |offset: usize| u16::from_le_bytes([wav[offset], wav[offset + 1]]);
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(u32_at(4) as usize, wav.len() - 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(16), 16);
+    assert_eq!((u16_at(20), u16_at(22)), (1, 1), "mono PCM");
+    assert_eq!(u32_at(24), sample_rate);
+    assert_eq!(u32_at(28), sample_rate * 2);
+    assert_eq!((u16_at(32), u16_at(34)), (2, 16));
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(40) as usize, wav.len() - 44);
+    wav[44..]
+        .chunks(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect()
+}
+
+/// Half of full scale, as the samples come out at a volume of 50%.
+const HALF: i16 = (0.5 * i16::MAX as f32) as i16;
+
+/// The beep plays for as many frames as the sound timer was set to, in the tone asked for.
+#[test]
+fn sound_timer_plays_the_beep() {
+    let tone = Tone {
+        frequency: 1500.0,
+        volume: 0.5,
+        waveform: Waveform::Square,
+    };
+    // 100 samples a frame, with a period of four samples.
+    let wav = render("v0 := 3 buzzer := v0 loop again", tone, 6000, 5);
+    let samples = samples(&wav, 6000);
+    assert_eq!(samples.len(), 500);
+
+    let expected = (0..500)
+        .map(|i| match i {
+            300.. => 0,
+            _ if i % 4 < 2 => HALF,
+            _ => -HALF,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(samples, expected);
+}
+
+/// An XO-CHIP pattern replaces the beep, one bit after another at the pitch's rate, whatever the
+/// tone is.
+#[test]
+fn audio_pattern_plays_bit_by_bit() {
+    const PATTERN: [u8; 16] = [
+        0xF0, 0x0F, 0xAA, 0x55, 0xFF, 0x00, 0xCC, 0x33, 0x81, 0x42, 0x24, 0x18, 0x01, 0x80, 0x7E,
+        0xE7,
+    ];
+    let bytes = PATTERN.map(|b| format!("{:#04x}", b)).join(" ");
+    let source = format!("i := tune audio v0 := 2 buzzer := v0 loop again : tune {bytes}");
+    let tone = Tone {
+        volume: 0.5,
+        waveform: Waveform::Sine,
+        ..Tone::default()
+    };
+    // The default pitch plays 4000 bits a second, so each bit lasts two samples. Frames are
+    // 133 or 134 samples long.
+    let wav = render(&source, tone, 8000, 4);
+    let samples = samples(&wav, 8000);
+    assert_eq!(samples.len(), 533);
+
+    let expected = (0..533)
+        .map(|i| {
+            let bit = i / 2 % 128;
+            match i {
+                266.. => 0,
+                _ if PATTERN[bit / 8] & (0x80 >> (bit % 8)) != 0 => HALF,
+                _ => -HALF,
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(samples, expected);
+}
@@ -0,0 +1,72 @@
+//! Checks the flicker filters frame by frame.
+
+use chip_8_interpreter::filter::{Filter, FrameFilter};
+
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 255, 0]];
+
+/// The red channel of each pixel.
+fn red(rgb: &[u8]) -> Vec<u8> {
+    rgb.chunks(3).map(|pixel| pixel[0]).collect()
+}
+
+#[test]
+fn no_filter_shows_each_frame_as_it_is() {
+    let mut filter = FrameFilter::new(Filter::None, PALETTE);
+    assert_eq!(
+        filter.apply(&[0, 1, 2, 3]),
+        [0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 255, 0]
+    );
+    assert_eq!(filter.apply(&[0, 0, 0, 0]), [0; 12]);
+}
+
+#[test]
+fn persistence_fades_over_the_given_frames() {
+    let mut filter = FrameFilter::new(Filter::Persistence { frames: 4 }, PALETTE);
+    assert_eq!(red(filter.apply(&[1, 0])), [255, 0]);
+    let fade = (0..4)
+        .map(|_| red(filter.apply(&[0, 0]))[0])
+        .collect::<Vec<_>>();
+    assert_eq!(fade, [191, 127, 63, 0]);
+    assert_eq!(red(filter.apply(&[0, 0])), [0, 0]);
+}
+
+/// A pixel that fades out keeps the colour it was lit with, and lighting it again restarts the
+/// fade.
+#[test]
+fn persistence_keeps_the_colour_and_restarts() {
+    let mut filter = FrameFilter::new(Filter::Persistence { frames: 2 }, PALETTE);
+    filter.apply(&[3]);
+    assert_eq!(filter.apply(&[0]), [0, 127, 0]);
+    filter.apply(&[1]);
+    assert_eq!(filter.apply(&[0]), [127, 127, 127]);
+    assert_eq!(filter.apply(&[0]), [0, 0, 0]);
+}
+
+#[test]
+fn blend_ors_the_previous_frame() {
+    let mut filter = FrameFilter::new(Filter::Blend, PALETTE);
+    assert_eq!(red(filter.apply(&[1, 0, 0, 2])), [255, 0, 0, 255]);
+    // Planes 1 and 2 combine into index 3, which is green.
+    assert_eq!(
+        filter.apply(&[0, 0, 2, 1]),
+        [255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 0]
+    );
+    assert_eq!(red(filter.apply(&[0, 0, 0, 0])), [0, 0, 255, 255]);
+}
+
+#[test]
+fn filter_names() {
+    assert_eq!(Filter::from_name("blend"), Some(Filter::Blend));
+    assert_eq!(
+        Filter::from_name("persistence:10"),
+        Some(Filter::Persistence { frames: 10 })
+    );
+    assert_eq!(
+        Filter::from_name("persistence"),
+        Some(Filter::Persistence {
+            frames: Filter::DEFAULT_PERSISTENCE_FRAMES
+        })
+    );
+    assert_eq!(Filter::from_name("persistence:0"), None);
+    assert_eq!(Filter::from_name("blend:2"), None);
+}
@@ -0,0 +1,250 @@
+//! Checks the gdb stub against packets written by hand, over a byte buffer instead of a socket.
+
+use chip_8_interpreter::gdb::GdbStub;
+use chip_8_interpreter::Chip8;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::rc::Rc;
+
+/// Both directions of a connection, shared between the test and the stub.
+#[derive(Default)]
+struct Pipe {
+    to_stub: VecDeque<u8>,
+    from_stub: Vec<u8>,
+}
+
+/// The stub's end of a [`Pipe`], which behaves like a non-blocking socket.
+#[derive(Clone, Default)]
+struct Stream(Rc<RefCell<Pipe>>);
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.0.borrow_mut();
+        if pipe.to_stub.is_empty() {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        pipe.to_stub.read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().from_stub.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Session {
+    stream: Stream,
+    stub: GdbStub<Stream>,
+    chip8: Chip8,
+}
+
+impl Session {
+    fn new(rom: &[u8]) -> Self {
+        let stream = Stream::default();
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(rom).unwrap();
+        Self {
+            stub: GdbStub::new(stream.clone()),
+            stream,
+            chip8,
+        }
+    }
+
+    /// Sends raw bytes and returns everything the stub sent back.
+    fn send_raw(&mut self, bytes: &[u8]) -> String {
+        self.stream.0.borrow_mut().to_stub.extend(bytes);
+        self.stub.poll(&mut self.chip8).unwrap();
+        self.output()
+    }
+
+    fn output(&mut self) -> String {
+        let bytes = std::mem::take(&mut self.stream.0.borrow_mut().from_stub);
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Sends `packet` and returns the body of the reply, checking the acknowledgement and the
+    /// reply's checksum. Commands that resume the program have no reply until it stops, which
+    /// is returned as an empty string.
+    fn command(&mut self, packet: &str) -> String {
+        let output = self.send_raw(framed(packet).as_bytes());
+        match output.strip_prefix('+') {
+            Some("") => String::new(),
+            Some(reply) => unframe(reply),
+            None => panic!("{:?} wasn't acknowledged: {:?}", packet, output),
+        }
+    }
+
+    /// Runs the machine like the client's loop does until the stub reports a stop, and returns
+    /// the stop reply.
+    fn run(&mut self) -> String {
+        for _ in 0..1000 {
+            self.stub.poll(&mut self.chip8).unwrap();
+            if self.stub.should_step(&self.chip8, true).unwrap() {
+                let _ = self.stub.step(&mut self.chip8).unwrap();
+            }
+            let output = self.output();
+            if !output.is_empty() {
+                return unframe(&output);
+            }
+        }
+        panic!("the stub never stopped");
+    }
+}
+
+fn checksum(packet: &str) -> u8 {
+    packet.bytes().fold(0, |sum, b| sum.wrapping_add(b))
+}
+
+fn framed(packet: &str) -> String {
+    format!("${}#{:02x}", packet, checksum(packet))
+}
+
+/// The body of the single packet in `reply`.
+fn unframe(reply: &str) -> String {
+    let body = reply
+        .strip_prefix('$')
+        .and_then(|reply| reply.get(..reply.len() - 3))
+        .unwrap_or_else(|| panic!("not a packet: {:?}", reply));
+    assert_eq!(reply, framed(body), "bad checksum");
+    body.to_string()
+}
+
+#[test]
+fn packets_are_acknowledged_and_answered() {
+    let mut session = Session::new(&[]);
+    assert_eq!(session.send_raw(b"+$?#3f"), "+$S05#b8");
+
+    // A packet split over several reads is handled once it's complete.
+    assert_eq!(session.send_raw(b"$?"), "");
+    assert_eq!(session.send_raw(b"#3"), "");
+    assert_eq!(session.send_raw(b"f"), "+$S05#b8");
+
+    // Packets with a bad checksum are rejected and the rest still handled.
+    assert_eq!(session.send_raw(b"$?#00$?#zz$?#3f"), "--+$S05#b8");
+}
+
+/// Rejecting a packet doesn't grow the stack, however many arrive at once.
+#[test]
+fn many_bad_packets_are_rejected() {
+    let mut session = Session::new(&[]);
+    let output = session.send_raw("$?#00".repeat(100_000).as_bytes());
+    assert_eq!(output.len(), 100_000);
+    assert!(output.bytes().all(|b| b == b'-'));
+}
+
+#[test]
+fn registers_are_read_in_target_order() {
+    let mut session = Session::new(&[0x6A, 0x42, 0xA1, 0x23]);
+    for _ in 0..2 {
+        assert_eq!(session.command("s"), "");
+        assert_eq!(session.run(), "S05");
+    }
+    // V0 to VF, then I and PC as little endian 16 bits, then SP, DT and ST.
+    let expected = format!(
+        "{}42{}{}{}{}",
+        "00".repeat(10),
+        "00".repeat(5),
+        "2301",
+        "0402",
+        "000000"
+    );
+    assert_eq!(session.command("g"), expected);
+    assert_eq!(session.command("p11"), "0402");
+    assert_eq!(session.command("pa"), "42");
+    assert_eq!(session.command("p15"), "E01");
+
+    assert_eq!(session.command("Pa=7f"), "OK");
+    assert_eq!(session.chip8.registers()[0xA], 0x7F);
+    assert_eq!(session.command("P10=3412"), "OK");
+    assert_eq!(session.chip8.index_register(), 0x1234);
+    assert_eq!(session.command("P10=12"), "E01");
+}
+
+#[test]
+fn memory_is_read_and_written() {
+    let mut session = Session::new(&[0x12, 0x34, 0x56]);
+    assert_eq!(session.command("m200,3"), "123456");
+    // Reads are cut short at the end of memory.
+    let end = session.chip8.memory().len();
+    assert_eq!(session.command(&format!("m{:x},4", end - 2)), "0000");
+    assert_eq!(session.command(&format!("m{:x},1", end)), "E01");
+
+    assert_eq!(session.command("M201,2:abcd"), "OK");
+    assert_eq!(session.command("m200,3"), "12abcd");
+    assert_eq!(session.command("M201,2:ab"), "E01");
+    assert_eq!(session.command("M201,1:abc"), "E01");
+    assert_eq!(session.command(&format!("M{:x},2:abcd", end - 1)), "E01");
+}
+
+/// Ranges that run past the largest address are rejected rather than wrapping around or
+/// overflowing.
+#[test]
+fn overflowing_ranges_are_rejected() {
+    let mut session = Session::new(&[]);
+    let max = format!("{:x}", usize::MAX);
+    assert_eq!(session.command(&format!("m1,{}", max)), "E01");
+    assert_eq!(session.command(&format!("m{},2", max)), "E01");
+    assert_eq!(session.command(&format!("M{},2:abcd", max)), "E01");
+    assert_eq!(session.command(&format!("Z2,{},2", max)), "E01");
+    assert_eq!(
+        session.command(&format!("qXfer:features:read:target.xml:{},2", max)),
+        "E01"
+    );
+}
+
+#[test]
+fn breakpoints_stop_the_program() {
+    // Three loads, then a jump back to the start.
+    let mut session = Session::new(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x00]);
+    assert_eq!(session.command("Z0,204,2"), "OK");
+    assert_eq!(session.command("c"), "");
+    assert_eq!(session.run(), "S05");
+    assert_eq!(session.chip8.program_counter(), 0x204);
+    assert_eq!(session.chip8.registers()[..3], [1, 2, 0]);
+
+    // Continuing from the breakpoint goes round the loop back to it.
+    assert_eq!(session.command("c"), "");
+    assert_eq!(session.run(), "S05");
+    assert_eq!(session.chip8.program_counter(), 0x204);
+    assert_eq!(session.chip8.registers()[..3], [1, 2, 3]);
+
+    assert_eq!(session.command("z0,204,2"), "OK");
+    assert_eq!(session.command("Z2,300,1"), "OK");
+    session.command("s");
+    assert_eq!(session.run(), "S05");
+    assert_eq!(session.chip8.program_counter(), 0x206);
+}
+
+#[test]
+fn write_watchpoints_report_the_address() {
+    // I := 0x300, V0 := 5, save V0.
+    let mut session = Session::new(&[0xA3, 0x00, 0x60, 0x05, 0xF0, 0x55]);
+    assert_eq!(session.command("Z2,300,1"), "OK");
+    session.command("c");
+    assert_eq!(session.run(), "T05watch:300;");
+    assert_eq!(session.chip8.memory()[0x300], 5);
+}
+
+/// Once the program has exited, continuing or stepping reports the exit straight away instead
+/// of waiting for an instruction that never runs.
+#[test]
+fn resuming_after_the_exit_reports_it_again() {
+    let mut session = Session::new(&[0x00, 0xFD]);
+    session.command("c");
+    assert_eq!(session.run(), "W00");
+    assert!(session.chip8.halted());
+
+    assert_eq!(session.command("c"), "W00");
+    assert_eq!(session.command("s"), "W00");
+    assert_eq!(session.command("vCont;c"), "W00");
+    assert_eq!(session.command("vCont?"), "vCont;c;s");
+    assert!(session.stub.stopped());
+}
@@ -0,0 +1,418 @@
+//! Checks PNG screenshots and GIF and APNG recordings by decoding them again: chunk CRCs and
+//! sequence numbers, the zlib streams and the GIF LZW codes, down to every pixel.
+
+use chip_8_interpreter::animation::{Animation, Format};
+use chip_8_interpreter::image;
+
+/// 8-bit RGB pixels in up to `colors` colours, scattered so they don't compress into long runs.
+fn noise(width: usize, height: usize, colors: u32, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..width * height)
+        .flat_map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let color = (state >> 16) % colors;
+            [color as u8, (color * 7) as u8, 255 - color as u8]
+        })
+        .collect()
+}
+
+/// 8-bit RGB pixels in blocks of flat colour, like a scaled up CHIP-8 screen.
+fn blocks(width: usize, height: usize, seed: usize) -> Vec<u8> {
+    (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width / 4, i / width / 4);
+            if (x * 3 + y * 5 + seed) % 7 < 3 {
+                [0xFF, 0xAA, 0x00]
+            } else {
+                [0x10, 0x20, 0x30]
+            }
+        })
+        .collect()
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ if crc & 1 != 0 { 0xEDB8_8320 } else { 0 }
+        })
+    })
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// The chunks of a PNG, after checking the signature and every chunk's CRC.
+fn png_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+    while pos < png.len() {
+        let len = u32_at(png, pos) as usize;
+        let kind: [u8; 4] = png[pos + 4..pos + 8].try_into().unwrap();
+        let data = &png[pos + 8..pos + 8 + len];
+        assert_eq!(
+            u32_at(png, pos + 8 + len),
+            crc32(&png[pos + 4..pos + 8 + len]),
+            "CRC of the {} chunk",
+            String::from_utf8_lossy(&kind)
+        );
+        chunks.push((kind, data.to_vec()));
+        pos += len + 12;
+    }
+    assert_eq!(pos, png.len());
+    assert_eq!(&chunks.last().unwrap().0, b"IEND");
+    chunks
+}
+
+/// Reads bits least significant first, as deflate and GIF pack them.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, len: u32) -> u32 {
+        (0..len).fold(0, |value, i| {
+            let bit = self.data[self.pos / 8] >> (self.pos % 8) & 1;
+            self.pos += 1;
+            value | (bit as u32) << i
+        })
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the symbols in order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        let symbols = (1..16)
+            .flat_map(|len| {
+                (0..lengths.len() as u16).filter(move |symbol| lengths[*symbol as usize] == len)
+            })
+            .collect();
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> u16 {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for count in &self.counts[1..] {
+            code |= bits.read(1) as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return self.symbols[(index + code - first) as usize];
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        panic!("invalid Huffman code");
+    }
+}
+
+/// Inflates a zlib stream of stored and fixed Huffman blocks, checking its header and checksum.
+fn inflate(zlib: &[u8]) -> Vec<u8> {
+    const LENGTHS: [u16; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const LENGTH_EXTRA: [u32; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    const DISTANCES: [u16; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const DISTANCE_EXTRA: [u32; 30] = [
+        0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+        13, 13,
+    ];
+
+    assert_eq!(zlib[0] & 0x0F, 8, "deflate");
+    assert_eq!(
+        u16::from_be_bytes([zlib[0], zlib[1]]) % 31,
+        0,
+        "header check"
+    );
+    assert_eq!(zlib[1] & 0x20, 0, "no preset dictionary");
+
+    let mut lengths = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    let literals = Huffman::new(&lengths);
+    let distances = Huffman::new(&[5; 30]);
+
+    let mut bits = Bits {
+        data: &zlib[2..],
+        pos: 0,
+    };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last = bits.read(1) == 1;
+        match bits.read(2) {
+            0 => {
+                bits.pos = bits.pos.div_ceil(8) * 8;
+                let len = bits.read(16);
+                assert_eq!(bits.read(16), !len & 0xFFFF, "stored block length check");
+                let start = bits.pos / 8;
+                out.extend_from_slice(&bits.data[start..start + len as usize]);
+                bits.pos += len as usize * 8;
+            }
+            1 => loop {
+                let symbol = literals.decode(&mut bits) as usize;
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let i = symbol - 257;
+                        let len = LENGTHS[i] as usize + bits.read(LENGTH_EXTRA[i]) as usize;
+                        let i = distances.decode(&mut bits) as usize;
+                        let distance =
+                            DISTANCES[i] as usize + bits.read(DISTANCE_EXTRA[i]) as usize;
+                        assert!(distance <= out.len(), "copy from before the start");
+                        for _ in 0..len {
+                            out.push(out[out.len() - distance]);
+                        }
+                    }
+                }
+            },
+            kind => panic!("unexpected block type {}", kind),
+        }
+        if last {
+            break;
+        }
+    }
+
+    let end = 2 + bits.pos.div_ceil(8);
+    assert_eq!(zlib.len(), end + 4, "data after the checksum");
+    let (a, b) = out.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    assert_eq!(u32_at(zlib, end), b << 16 | a, "Adler-32");
+    out
+}
+
+/// The RGB pixels of unfiltered PNG scanlines.
+fn unfilter(width: usize, height: usize, scanlines: &[u8]) -> Vec<u8> {
+    assert_eq!(scanlines.len(), height * (width * 3 + 1));
+    scanlines
+        .chunks(width * 3 + 1)
+        .flat_map(|row| {
+            assert_eq!(row[0], 0, "filter type");
+            row[1..].to_vec()
+        })
+        .collect()
+}
+
+/// Decodes GIF LZW codes back into colour indices.
+fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1usize << min_code_size;
+    let end = clear + 1;
+    let mut table: Vec<Vec<u8>> = (0..=end).map(|index| vec![index as u8]).collect();
+    let mut width = min_code_size as u32 + 1;
+    let mut bits = Bits { data, pos: 0 };
+    let mut prev: Option<Vec<u8>> = None;
+    let mut out = Vec::new();
+    loop {
+        let code = bits.read(width) as usize;
+        if code == clear {
+            table.truncate(end + 1);
+            width = min_code_size as u32 + 1;
+            prev = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let entry = match &prev {
+            _ if code < table.len() => {
+                assert!(code < clear || code > end, "code {} is reserved", code);
+                table[code].clone()
+            }
+            Some(prev) if code == table.len() => [&prev[..], &prev[..1]].concat(),
+            _ => panic!("code {} isn't in the table yet", code),
+        };
+        out.extend_from_slice(&entry);
+        if let Some(prev) = prev {
+            if table.len() < 4096 {
+                table.push([&prev[..], &entry[..1]].concat());
+            }
+        }
+        if table.len() == 1 << width && width < 12 {
+            width += 1;
+        }
+        prev = Some(entry);
+    }
+    assert_eq!(data.len(), bits.pos.div_ceil(8), "data after the end code");
+    out
+}
+
+/// The frames of a GIF as their delays in hundredths of a second and RGB pixels.
+fn gif_frames(gif: &[u8]) -> (usize, usize, Vec<(u16, Vec<u8>)>) {
+    let u16_at = |offset: usize| u16::from_le_bytes([gif[offset], gif[offset + 1]]) as usize;
+    // Skips the data sub-blocks at `pos`, returning them joined and the position after them.
+    let sub_blocks = |mut pos: usize| {
+        let mut data = Vec::new();
+        while gif[pos] != 0 {
+            data.extend_from_slice(&gif[pos + 1..pos + 1 + gif[pos] as usize]);
+            pos += gif[pos] as usize + 1;
+        }
+        (data, pos + 1)
+    };
+
+    assert_eq!(&gif[..6], b"GIF89a");
+    let (width, height) = (u16_at(6), u16_at(8));
+    assert_eq!(gif[10] & 0x80, 0, "no global colour table");
+    let mut pos = 13;
+    let mut delay = None;
+    let mut frames = Vec::new();
+    loop {
+        match gif[pos] {
+            0x21 if gif[pos + 1] == 0xF9 => {
+                assert_eq!(gif[pos + 2], 4);
+                delay = Some(u16_at(pos + 4) as u16);
+                pos = sub_blocks(pos + 2).1;
+            }
+            0x21 => pos = sub_blocks(pos + 2).1,
+            0x2C => {
+                assert_eq!((u16_at(pos + 1), u16_at(pos + 3)), (0, 0));
+                assert_eq!((u16_at(pos + 5), u16_at(pos + 7)), (width, height));
+                let flags = gif[pos + 9];
+                assert_eq!(flags & 0x80, 0x80, "local colour table");
+                let table_len = 3 << ((flags & 0x07) + 1);
+                let table = &gif[pos + 10..pos + 10 + table_len];
+                pos += 10 + table_len;
+                let (data, next) = sub_blocks(pos + 1);
+                let indices = lzw_decode(&data, gif[pos]);
+                assert_eq!(indices.len(), width * height);
+                let rgb = indices
+                    .iter()
+                    .flat_map(|index| &table[*index as usize * 3..][..3])
+                    .copied()
+                    .collect();
+                frames.push((delay.take().expect("frame without a delay"), rgb));
+                pos = next;
+            }
+            0x3B => break,
+            byte => panic!("unexpected block {:#04X} at {}", byte, pos),
+        }
+    }
+    assert_eq!(pos + 1, gif.len(), "data after the trailer");
+    (width, height, frames)
+}
+
+#[test]
+fn png_decodes_to_its_pixels() {
+    for (width, height, rgb) in [
+        (128, 64, blocks(128, 64, 0)),
+        (37, 11, noise(37, 11, 256, 1)),
+    ] {
+        let chunks = png_chunks(&image::png(width, height, &rgb));
+        let kinds = chunks.iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        let header = &chunks[0].1;
+        assert_eq!(u32_at(header, 0) as usize, width);
+        assert_eq!(u32_at(header, 4) as usize, height);
+        assert_eq!(&header[8..], [8, 2, 0, 0, 0]);
+        assert_eq!(unfilter(width, height, &inflate(&chunks[1].1)), rgb);
+    }
+
+    // Flat colour compresses.
+    let rgb = blocks(256, 128, 0);
+    assert!(image::png(256, 128, &rgb).len() < rgb.len() / 10);
+}
+
+#[test]
+fn apng_frames_decode_in_sequence() {
+    let (width, height) = (64, 32);
+    let frames = [
+        (blocks(width, height, 0), 3),
+        (noise(width, height, 16, 2), 1),
+        (blocks(width, height, 1), 2),
+    ];
+    let mut animation = Animation::new(Format::Apng, width, height);
+    for (rgb, repeats) in &frames {
+        for _ in 0..*repeats {
+            animation.push(rgb);
+        }
+    }
+    let chunks = png_chunks(&animation.encode());
+
+    assert_eq!(&chunks[0].0, b"IHDR");
+    assert_eq!(&chunks[1].0, b"acTL");
+    assert_eq!(u32_at(&chunks[1].1, 0), 3, "frame count");
+    assert_eq!(u32_at(&chunks[1].1, 4), 0, "loop forever");
+
+    let mut sequence = 0;
+    let mut decoded = Vec::new();
+    for (kind, data) in &chunks[2..chunks.len() - 1] {
+        match kind {
+            b"fcTL" => {
+                assert_eq!(u32_at(data, 0), sequence);
+                assert_eq!(u32_at(data, 4) as usize, width);
+                assert_eq!(u32_at(data, 8) as usize, height);
+                assert_eq!(&data[12..20], [0; 8], "offset");
+                let delay = (
+                    u16::from_be_bytes([data[20], data[21]]),
+                    u16::from_be_bytes([data[22], data[23]]),
+                );
+                decoded.push((delay, Vec::new()));
+                sequence += 1;
+            }
+            b"IDAT" => {
+                assert_eq!(decoded.len(), 1, "IDAT belongs to the first frame");
+                decoded[0].1 = unfilter(width, height, &inflate(data));
+            }
+            b"fdAT" => {
+                assert_eq!(u32_at(data, 0), sequence);
+                sequence += 1;
+                let frame = decoded.last_mut().unwrap();
+                assert!(frame.1.is_empty(), "a frame with two fdAT chunks");
+                frame.1 = unfilter(width, height, &inflate(&data[4..]));
+            }
+            kind => panic!("unexpected {} chunk", String::from_utf8_lossy(kind)),
+        }
+    }
+    assert_eq!(sequence, 5);
+
+    let expected = frames
+        .iter()
+        .map(|(rgb, repeats)| ((*repeats, 60), rgb.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn gif_frames_decode_to_their_pixels() {
+    // Enough pixels in enough colours to fill the LZW table and clear it again.
+    let (width, height) = (128, 96);
+    let frames = [
+        (blocks(width, height, 0), 3),
+        (noise(width, height, 200, 3), 3),
+        (noise(width, height, 2, 4), 3),
+        (blocks(width, height, 2), 6),
+    ];
+    let mut animation = Animation::new(Format::Gif, width, height);
+    for (rgb, repeats) in &frames {
+        for _ in 0..*repeats {
+            animation.push(rgb);
+        }
+    }
+
+    let (gif_width, gif_height, decoded) = gif_frames(&animation.encode());
+    assert_eq!((gif_width, gif_height), (width, height));
+    let expected = frames
+        .iter()
+        .map(|(rgb, repeats)| (*repeats as u16 * 100 / 60, rgb.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, expected);
+}
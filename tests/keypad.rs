@@ -0,0 +1,73 @@
+//! Checks how programs see the keypad: the keys latched for each frame, `FX0A` waiting for a
+//! release and the keys a program polls.
+
+use chip_8_interpreter::movie::Frame;
+use chip_8_interpreter::{asm, Chip8};
+
+/// Loads the program assembled from `source`.
+fn load(source: &str) -> Chip8 {
+    let assembly = asm::assemble(source).unwrap();
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(assembly.rom()).unwrap();
+    chip8
+}
+
+fn run_frames(chip8: &mut Chip8, frames: usize, keys: u16) {
+    let frame = Frame { keys, steps: 20 };
+    for _ in 0..frames {
+        frame.run(chip8).unwrap();
+    }
+}
+
+#[test]
+fn get_key_waits_for_release() {
+    let mut chip8 = load(
+        ": main
+            v0 := key
+            v1 := 1
+            loop again",
+    );
+    run_frames(&mut chip8, 1, 0);
+    run_frames(&mut chip8, 5, 1 << 0xA);
+    assert_eq!(
+        chip8.registers()[0x1],
+        0,
+        "FX0A finished while the key was held"
+    );
+    run_frames(&mut chip8, 1, 0);
+    assert_eq!(chip8.registers()[0x0], 0xA);
+    assert_eq!(chip8.registers()[0x1], 1);
+}
+
+#[test]
+fn skip_if_key_uses_hex_keys() {
+    let mut chip8 = load(
+        ": main
+            v0 := 5
+            if v0 -key then v1 := 1
+            if v0 key then v2 := 1
+            loop again",
+    );
+    run_frames(&mut chip8, 1, 1 << 5);
+    assert_eq!(&chip8.registers()[1..3], [0, 1]);
+}
+
+#[test]
+fn polled_keys_are_reported() {
+    let mut chip8 = load(
+        ": main
+            v0 := 5
+            v1 := 0xC
+            if v0 key then v2 := 1
+            if v1 -key then v2 := 2
+            v3 := key",
+    );
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.take_polled_keys(), 1 << 5 | 1 << 0xC);
+    assert_eq!(chip8.take_polled_keys(), 0);
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    assert_eq!(chip8.take_polled_keys(), 0xFFFF, "FX0A reads every key");
+}
@@ -0,0 +1,76 @@
+//! Checks where the program counter goes at the end of memory.
+
+use chip_8_interpreter::{Chip8, Chip8Error, Quirks};
+
+/// With XO-CHIP's 64 KiB of memory the program counter wraps around like in Octo instead of
+/// overflowing, whether it's stepping, skipping or reading a long `i :=`.
+#[test]
+fn program_counter_wraps_at_the_end_of_memory() {
+    let quirks = Quirks::from_name("xo-chip").unwrap();
+    let mut chip8 = Chip8::with_quirks(quirks);
+
+    chip8.memory_mut()[0xFFFE..].copy_from_slice(&[0x60, 0x05]);
+    chip8.set_program_counter(0xFFFE);
+    chip8.step().unwrap();
+    assert_eq!(chip8.program_counter(), 0);
+
+    chip8.memory_mut()[0xFFFA..].copy_from_slice(&[0x30, 0x05, 0xF0, 0x00, 0x12, 0x34]);
+    chip8.set_program_counter(0xFFFA);
+    chip8.step().unwrap();
+    assert_eq!(chip8.program_counter(), 0);
+
+    chip8.memory_mut()[0xFFFC..].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+    chip8.set_program_counter(0xFFFC);
+    chip8.step().unwrap();
+    assert_eq!(chip8.program_counter(), 0);
+    assert_eq!(chip8.index_register(), 0x1234);
+
+    // Instructions that stay put or report where they are use the address they were fetched
+    // from, not the wrapped program counter.
+    let quirks = Quirks {
+        display_wait: true,
+        ..quirks
+    };
+    let at_end = |word: u16| {
+        let mut chip8 = Chip8::with_quirks(quirks);
+        chip8.memory_mut()[0xFFFE..].copy_from_slice(&word.to_be_bytes());
+        chip8.set_program_counter(0xFFFE);
+        chip8
+    };
+
+    let mut chip8 = at_end(0x00FD);
+    chip8.step().unwrap();
+    assert!(chip8.halted());
+    assert_eq!(chip8.program_counter(), 0xFFFE, "exit");
+
+    let mut chip8 = at_end(0x00EE);
+    assert_eq!(
+        chip8.step(),
+        Err(Chip8Error::StackUnderflow { addr: 0xFFFE }),
+        "return"
+    );
+    assert_eq!(chip8.program_counter(), 0xFFFE);
+
+    let mut chip8 = at_end(0x2300);
+    chip8.step().unwrap();
+    assert_eq!(chip8.stack(), [0], "call");
+    chip8.set_stack(&[0; 16]).unwrap();
+    chip8.set_program_counter(0xFFFE);
+    assert_eq!(
+        chip8.step(),
+        Err(Chip8Error::StackOverflow { addr: 0xFFFE }),
+        "call with a full stack"
+    );
+
+    let mut chip8 = at_end(0xD011);
+    chip8.step().unwrap();
+    assert_eq!(
+        chip8.program_counter(),
+        0xFFFE,
+        "sprite waiting for the vertical blank"
+    );
+
+    let mut chip8 = at_end(0xF00A);
+    chip8.step().unwrap();
+    assert_eq!(chip8.program_counter(), 0xFFFE, "key wait");
+}
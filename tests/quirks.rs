@@ -0,0 +1,35 @@
+//! Checks the quirks presets and overriding single quirks by name.
+
+use chip_8_interpreter::Quirks;
+
+/// The value of every flag, in the order of [`Quirks::FLAG_NAMES`].
+fn flags(mut quirks: Quirks) -> Vec<bool> {
+    Quirks::FLAG_NAMES
+        .iter()
+        .map(|name| *quirks.flag_mut(name).unwrap())
+        .collect()
+}
+
+#[test]
+fn every_flag_name_sets_its_own_quirk() {
+    let before = flags(Quirks::COSMAC_VIP);
+    for (i, name) in Quirks::FLAG_NAMES.iter().enumerate() {
+        let mut quirks = Quirks::COSMAC_VIP;
+        let flag = quirks.flag_mut(name).unwrap();
+        *flag = !*flag;
+        let mut expected = before.clone();
+        expected[i] = !expected[i];
+        assert_eq!(flags(quirks), expected, "{}", name);
+    }
+    assert_eq!(Quirks::default().flag_mut("wrap"), None);
+}
+
+#[test]
+fn presets_differ() {
+    let presets = Quirks::PRESET_NAMES.map(|name| Quirks::from_name(name).unwrap());
+    for (i, a) in presets.iter().enumerate() {
+        for b in &presets[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
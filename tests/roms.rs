@@ -0,0 +1,372 @@
+//! Runs test ROMs and small assembled programs headlessly under the quirks presets and checks the
+//! screen, registers and memory they end up with.
+//!
+//! The bundled ROMs in `roms/` always run, as do the assembled programs checking instructions and
+//! the flags and quirks of every preset. The Timendus CHIP-8 test suite isn't bundled, so its tests are ignored unless
+//! asked for with `cargo test -- --ignored`, and then need `CHIP8_TEST_SUITE` to point at a checkout
+//! of <https://github.com/Timendus/chip8-test-suite>. Their screens are compared against snapshots
+//! in `tests/golden/timendus/`; run once with `UPDATE_GOLDEN=1` to write them, then check by eye
+//! that every test shows a tick before committing.
+
+use chip_8_interpreter::movie::Frame;
+use chip_8_interpreter::state::rom_hash;
+use chip_8_interpreter::{asm, image, Chip8, Quirks};
+
+use std::path::{Path, PathBuf};
+
+const INSTRUCTIONS_PER_FRAME: u16 = 20;
+
+fn run(rom: &[u8], quirks: Quirks, frames: usize) -> Chip8 {
+    let mut chip8 = Chip8::with_quirks(quirks);
+    chip8.load_rom(rom).unwrap();
+    run_frames(&mut chip8, frames, 0);
+    chip8
+}
+
+fn run_frames(chip8: &mut Chip8, frames: usize, keys: u16) {
+    let frame = Frame {
+        keys,
+        steps: INSTRUCTIONS_PER_FRAME,
+    };
+    for _ in 0..frames {
+        frame.run(chip8).unwrap();
+    }
+}
+
+fn assemble_and_run(source: &str) -> Chip8 {
+    assemble_and_run_with(source, Quirks::default())
+}
+
+fn assemble_and_run_with(source: &str, quirks: Quirks) -> Chip8 {
+    let assembly = asm::assemble(source).unwrap();
+    run(assembly.rom(), quirks, 10)
+}
+
+fn presets() -> impl Iterator<Item = (&'static str, Quirks)> {
+    Quirks::PRESET_NAMES
+        .into_iter()
+        .map(|name| (name, Quirks::from_name(name).unwrap()))
+}
+
+fn manifest_path(path: impl AsRef<Path>) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn screen(chip8: &Chip8) -> String {
+    image::ascii(chip8.width(), chip8.height(), chip8.framebuffer())
+}
+
+/// The same FNV-1a hash as for ROMs, over the framebuffer.
+fn screen_hash(chip8: &Chip8) -> u64 {
+    rom_hash(chip8.framebuffer())
+}
+
+/// Checks the screen `rom` ends up with under each preset against `expected`, a hash per entry of
+/// [`Quirks::PRESET_NAMES`].
+fn check_screens(rom_name: &str, frames: usize, expected: [u64; 4]) {
+    let rom = std::fs::read(manifest_path("roms").join(rom_name)).unwrap();
+    for (preset, expected) in Quirks::PRESET_NAMES.iter().zip(expected) {
+        let chip8 = run(&rom, Quirks::from_name(preset).unwrap(), frames);
+        let hash = screen_hash(&chip8);
+        assert_eq!(
+            hash,
+            expected,
+            "{} under {} ended with {:#018x}:\n{}",
+            rom_name,
+            preset,
+            hash,
+            screen(&chip8)
+        );
+    }
+}
+
+#[test]
+fn ibm_logo() {
+    check_screens("ibm-logo.ch8", 60, [0x1f1d341cab07e169; 4]);
+}
+
+#[test]
+fn test_opcode() {
+    // Every opcode shows OK under every preset.
+    check_screens("test_opcode.ch8", 200, [0x8f21671912c12851; 4]);
+}
+
+/// BC_test shifts VX in place, so it reports error 12 under the presets where `8XY6` and `8XYE`
+/// shift VY, and it expects `FX55` and `FX65` to leave I alone, so it reports error 16 under
+/// CHIP-48.
+#[test]
+fn bc_test() {
+    const PASSED: u64 = 0x3f2181ca4969e69f;
+    const ERROR_12: u64 = 0x44752c1d4187d9c5;
+    const ERROR_16: u64 = 0x54aca397cad33fcc;
+    check_screens("BC_test.ch8", 200, [ERROR_12, ERROR_16, PASSED, ERROR_12]);
+}
+
+#[test]
+fn display_takes_y_from_the_third_nibble() {
+    let chip8 = assemble_and_run(
+        ": main
+            v0 := 4
+            v1 := 9
+            v2 := 0
+            i := hex v2
+            sprite v0 v1 1
+            loop again",
+    );
+    let width = chip8.width();
+    let lit = (0..width * chip8.height())
+        .filter(|i| chip8.framebuffer()[*i] != 0)
+        .map(|i| (i % width, i / width))
+        .collect::<Vec<_>>();
+    assert_eq!(lit, [(4, 9), (5, 9), (6, 9), (7, 9)]);
+}
+
+#[test]
+fn font_digits_are_five_bytes_apart() {
+    let chip8 = assemble_and_run(
+        ": main
+            v0 := 7
+            i := hex v0
+            loop again",
+    );
+    assert_eq!(chip8.index_register(), 35);
+}
+
+#[test]
+fn add_and_subtract_write_the_flag_last() {
+    let chip8 = assemble_and_run(
+        ": main
+            v1 := 3
+            vf := 0xFF
+            vf += v1
+            v2 := vf
+            vf := 5
+            vf -= v1
+            v3 := vf
+            vf := 1
+            vf =- v1
+            v4 := vf
+            v5 := 3
+            v5 -= v5
+            v6 := vf
+            loop again",
+    );
+    // Each result is 2, which would be left in VF if the flag were written first.
+    let registers = chip8.registers();
+    // 0xFF + 3 carries.
+    assert_eq!(registers[0x2], 1);
+    // 5 - 3 doesn't borrow.
+    assert_eq!(registers[0x3], 1);
+    // 3 - 1 doesn't borrow.
+    assert_eq!(registers[0x4], 1);
+    // Subtracting a register from itself doesn't borrow either.
+    assert_eq!(registers[0x5], 0);
+    assert_eq!(registers[0x6], 1);
+}
+
+#[test]
+fn store_and_load_registers_in_order() {
+    let chip8 = assemble_and_run(
+        ": main
+            v0 := 1
+            v1 := 2
+            v2 := 3
+            v3 := 4
+            i := 0x300
+            save v3
+            v0 := 0
+            v1 := 0
+            v2 := 0
+            v3 := 0
+            i := 0x300
+            load v2
+            loop again",
+    );
+    assert_eq!(&chip8.memory()[0x300..0x305], [1, 2, 3, 4, 0]);
+    assert_eq!(&chip8.registers()[..4], [1, 2, 3, 0]);
+}
+
+/// The results and VF of the arithmetic instructions, including when VF is one of the operands,
+/// in which case the flag wins.
+#[test]
+fn arithmetic_sets_the_flag() {
+    // Shifts are given the same value in VX and VY so that the shift quirk doesn't matter.
+    let cases = [
+        ("+=", 0xFF, 0x01, 0x00, 1),
+        ("+=", 0x10, 0x20, 0x30, 0),
+        ("-=", 5, 3, 2, 1),
+        ("-=", 3, 5, 0xFE, 0),
+        ("-=", 3, 3, 0, 1),
+        ("=-", 3, 5, 2, 1),
+        ("=-", 5, 3, 0xFE, 0),
+        ("=-", 3, 3, 0, 1),
+        (">>=", 0x05, 0x05, 0x02, 1),
+        (">>=", 0x04, 0x04, 0x02, 0),
+        ("<<=", 0x81, 0x81, 0x02, 1),
+        ("<<=", 0x41, 0x41, 0x82, 0),
+    ];
+    for (preset, quirks) in presets() {
+        for (op, x, y, result, flag) in cases {
+            let source = format!("v1 := {x}\nv2 := {y}\nv1 {op} v2\nloop again");
+            let registers = assemble_and_run_with(&source, quirks).registers();
+            let case = format!("{x:#x} {op} {y:#x} under {preset}");
+            assert_eq!((registers[1], registers[0xF]), (result, flag), "{case}");
+
+            let source = format!("vf := {x}\nv2 := {y}\nvf {op} v2\nloop again");
+            let registers = assemble_and_run_with(&source, quirks).registers();
+            assert_eq!(registers[0xF], flag, "{case} into vf");
+
+            let source = format!("v1 := {x}\nvf := {y}\nv1 {op} vf\nloop again");
+            let registers = assemble_and_run_with(&source, quirks).registers();
+            assert_eq!(
+                (registers[1], registers[0xF]),
+                (result, flag),
+                "{case} from vf"
+            );
+        }
+    }
+}
+
+/// Each preset behaves the way its quirks say, checked one quirk at a time.
+#[test]
+fn presets_follow_their_quirks() {
+    for (preset, quirks) in presets() {
+        let registers =
+            assemble_and_run_with("v1 := 1 v2 := 4 v1 >>= v2 loop again", quirks).registers();
+        let expected = if quirks.shift_ignores_vy { 0 } else { 2 };
+        assert_eq!(registers[1], expected, "shift under {preset}");
+
+        // `jump0 0x300` is `B300`, which adds V3 rather than V0 under the jump quirk.
+        let registers = assemble_and_run_with(
+            "v0 := 4 v3 := 8 jump0 0x300
+            :org 0x304 v5 := 1 loop again
+            :org 0x308 v5 := 2 loop again",
+            quirks,
+        )
+        .registers();
+        let expected = if quirks.jump_uses_vx { 2 } else { 1 };
+        assert_eq!(registers[5], expected, "jump0 under {preset}");
+
+        let chip8 = assemble_and_run_with("i := 0x400 save v1 loop again", quirks);
+        let expected = match (
+            quirks.load_store_increments_i,
+            quirks.load_store_increments_i_by_x,
+        ) {
+            (true, false) => 0x402,
+            (true, true) => 0x401,
+            (false, _) => 0x400,
+        };
+        assert_eq!(chip8.index_register(), expected, "save under {preset}");
+
+        for op in ["|=", "&=", "^="] {
+            let source = format!("vf := 5 v1 := 3 v2 := 6 v1 {op} v2 loop again");
+            let registers = assemble_and_run_with(&source, quirks).registers();
+            let expected = if quirks.logic_resets_vf { 0 } else { 5 };
+            assert_eq!(registers[0xF], expected, "{op} under {preset}");
+        }
+
+        // A row of eight pixels drawn four pixels from the right edge.
+        let chip8 = assemble_and_run_with(
+            "i := row v0 := 60 v1 := 0 sprite v0 v1 1 loop again : row 0xFF",
+            quirks,
+        );
+        let wrapped = chip8.framebuffer()[0] != 0;
+        assert_eq!(wrapped, quirks.sprite_wrap, "sprite wrap under {preset}");
+
+        // Waiting for the vertical blank, the first frame has none to start with and each of
+        // the other two draws one sprite. Otherwise the three instructions of the loop run 20
+        // times in the 60 instructions of three frames.
+        let mut chip8 = Chip8::with_quirks(quirks);
+        let assembly = asm::assemble("loop sprite v0 v0 1 v2 += 1 again").unwrap();
+        chip8.load_rom(assembly.rom()).unwrap();
+        run_frames(&mut chip8, 3, 0);
+        let expected = if quirks.display_wait { 2 } else { 20 };
+        assert_eq!(chip8.registers()[2], expected, "sprites under {preset}");
+
+        let registers =
+            assemble_and_run_with("i := 0xFFF v0 := 1 i += v0 loop again", quirks).registers();
+        let expected = quirks.index_overflow_sets_vf as u8;
+        assert_eq!(registers[0xF], expected, "i += under {preset}");
+
+        let chip8 = Chip8::with_quirks(quirks);
+        let expected = if quirks.extended_memory {
+            0x10000
+        } else {
+            0x1000
+        };
+        assert_eq!(chip8.memory().len(), expected, "memory under {preset}");
+    }
+}
+
+/// Runs a ROM from the Timendus suite under each preset and compares the screen with its snapshot.
+/// `mode` picks what to write to `0x1FF`, which the suite's menus read to skip asking which test or
+/// platform to run.
+fn check_timendus(rom_name: &str, mode: fn(Quirks) -> Option<u8>, keys: u16) {
+    let suite = std::env::var_os("CHIP8_TEST_SUITE")
+        .expect("CHIP8_TEST_SUITE should point at a checkout of the Timendus test suite");
+    let rom = std::fs::read(Path::new(&suite).join("bin").join(rom_name)).unwrap();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    for preset in Quirks::PRESET_NAMES {
+        let quirks = Quirks::from_name(preset).unwrap();
+        let mut chip8 = Chip8::with_quirks(quirks);
+        chip8.load_rom(&rom).unwrap();
+        if let Some(mode) = mode(quirks) {
+            chip8.memory_mut()[0x1FF] = mode;
+        }
+        run_frames(&mut chip8, 600, keys);
+
+        let stem = rom_name.trim_end_matches(".ch8");
+        let golden =
+            manifest_path("tests/golden/timendus").join(format!("{}-{}.txt", stem, preset));
+        let screen = screen(&chip8);
+        if update {
+            std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            std::fs::write(&golden, &screen).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap_or_else(|_| {
+            panic!(
+                "no snapshot at {}, run with UPDATE_GOLDEN=1 to write it",
+                golden.display()
+            )
+        });
+        assert_eq!(screen, expected, "{} under {}", rom_name, preset);
+    }
+}
+
+/// The platform `5-quirks.ch8` should test for a preset.
+fn timendus_platform(quirks: Quirks) -> u8 {
+    if quirks.extended_memory {
+        3
+    } else if quirks.jump_uses_vx {
+        2
+    } else {
+        1
+    }
+}
+
+#[test]
+#[ignore = "needs CHIP8_TEST_SUITE"]
+fn timendus_corax_plus() {
+    check_timendus("3-corax+.ch8", |_| None, 0);
+}
+
+#[test]
+#[ignore = "needs CHIP8_TEST_SUITE"]
+fn timendus_flags() {
+    check_timendus("4-flags.ch8", |_| None, 0);
+}
+
+#[test]
+#[ignore = "needs CHIP8_TEST_SUITE"]
+fn timendus_quirks() {
+    check_timendus("5-quirks.ch8", |quirks| Some(timendus_platform(quirks)), 0);
+}
+
+/// The `EX9E` test, with every key held so that they all light up.
+#[test]
+#[ignore = "needs CHIP8_TEST_SUITE"]
+fn timendus_keypad() {
+    check_timendus("6-keypad.ch8", |_| Some(1), 0xFFFF);
+}
@@ -0,0 +1,44 @@
+//! Checks that save states restore the machine they were taken from.
+
+use chip_8_interpreter::state::SaveState;
+use chip_8_interpreter::{asm, Chip8};
+
+/// A save state carries on with the same random numbers as the machine it was taken from, and
+/// restores its place in the sequence without replaying it, however far along it is.
+#[test]
+fn save_states_keep_the_random_sequence() {
+    let rom = asm::assemble(
+        ": main
+            loop
+                v0 := random 0xFF
+            again",
+    )
+    .unwrap()
+    .rom()
+    .to_vec();
+    let mut chip8 = Chip8::new();
+    chip8.load_rom(&rom).unwrap();
+    for _ in 0..100 {
+        chip8.step().unwrap();
+    }
+    let mut bytes = SaveState::capture(&chip8, &rom).to_bytes();
+
+    let mut restored = SaveState::from_bytes(&bytes)
+        .unwrap()
+        .restore(&rom)
+        .unwrap();
+    for _ in 0..20 {
+        chip8.step().unwrap();
+        restored.step().unwrap();
+        assert_eq!(chip8.registers()[0], restored.registers()[0]);
+    }
+
+    // The position in the sequence sits just before the key wait at the end.
+    let end = bytes.len() - 2;
+    bytes[end - 8..end].copy_from_slice(&u64::MAX.to_le_bytes());
+    let mut far_along = SaveState::from_bytes(&bytes)
+        .unwrap()
+        .restore(&rom)
+        .unwrap();
+    far_along.step().unwrap();
+}
@@ -0,0 +1,109 @@
+//! Checks the pixel art scalers against outputs worked out by hand.
+
+use chip_8_interpreter::upscale::{hq2x, nearest, scale2x, scale3x, Scaler};
+
+/// An image from rows of `#` and `.`, and its width.
+fn image(rows: &[&str]) -> (usize, Vec<u8>) {
+    (rows[0].len(), rows.concat().into_bytes())
+}
+
+fn rows(width: usize, pixels: &[u8]) -> Vec<String> {
+    pixels
+        .chunks(width)
+        .map(|row| String::from_utf8(row.to_vec()).unwrap())
+        .collect()
+}
+
+#[test]
+fn scale2x_keeps_lone_pixels_square() {
+    let (width, pixels) = image(&["...", ".#.", "..."]);
+    assert_eq!(
+        rows(width * 2, &scale2x(width, 3, &pixels)),
+        ["......", "......", "..##..", "..##..", "......", "......"]
+    );
+}
+
+#[test]
+fn scale2x_smooths_staircases() {
+    let (width, pixels) = image(&["##.", ".##", "..#"]);
+    assert_eq!(
+        rows(width * 2, &scale2x(width, 3, &pixels)),
+        ["####..", "#####.", ".#####", "...###", "...###", "....##"]
+    );
+}
+
+#[test]
+fn scale3x_smooths_staircases() {
+    let (width, pixels) = image(&["##.", ".##", "..#"]);
+    assert_eq!(
+        rows(width * 3, &scale3x(width, 3, &pixels)),
+        [
+            "######...",
+            "#######..",
+            "########.",
+            ".########",
+            "...######",
+            "....#####",
+            ".....####",
+            ".....####",
+            "......###",
+        ]
+    );
+}
+
+#[test]
+fn scalers_leave_flat_images_alone() {
+    let (width, pixels) = image(&["####", "####"]);
+    assert!(scale2x(width, 2, &pixels)
+        .iter()
+        .all(|pixel| *pixel == b'#'));
+    assert!(scale3x(width, 2, &pixels)
+        .iter()
+        .all(|pixel| *pixel == b'#'));
+}
+
+#[test]
+fn hq2x_blends_diagonal_corners() {
+    const B: [u8; 3] = [0, 0, 0];
+    const W: [u8; 3] = [255, 255, 255];
+    let scaled = hq2x(3, 3, &[W, B, B, B, W, B, B, B, W]);
+    let red = scaled
+        .chunks(6)
+        .map(|row| row.iter().map(|pixel| pixel[0]).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        red,
+        [
+            [255, 255, 0, 0, 0, 0],
+            [255, 127, 127, 0, 0, 0],
+            [0, 127, 127, 63, 0, 0],
+            [0, 0, 63, 127, 127, 0],
+            [0, 0, 0, 127, 127, 255],
+            [0, 0, 0, 0, 255, 255],
+        ]
+    );
+}
+
+#[test]
+fn scaler_applies_to_rgb() {
+    let rgb = [10, 20, 30, 40, 50, 60];
+    for name in Scaler::NAMES {
+        let scaler = Scaler::from_name(name).unwrap();
+        let scaled = scaler.apply(2, 1, &rgb);
+        assert_eq!(scaled.len(), rgb.len() * scaler.factor() * scaler.factor());
+    }
+    assert_eq!(
+        Scaler::Scale2x.apply(2, 1, &rgb),
+        [10, 20, 30, 10, 20, 30, 40, 50, 60, 40, 50, 60].repeat(2)
+    );
+}
+
+#[test]
+fn nearest_enlarges_into_blocks() {
+    let rgb = [1, 1, 1, 2, 2, 2];
+    assert_eq!(
+        nearest(2, 1, &rgb, 4, 2),
+        [1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2].repeat(2)
+    );
+    assert_eq!(nearest(2, 1, &rgb, 2, 1), rgb);
+}
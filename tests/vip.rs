@@ -0,0 +1,418 @@
+//! Checks the RCA 1802 and CDP1861 against the data sheets, and boots a COSMAC VIP.
+//!
+//! The VIP's CHIP-8 interpreter isn't bundled, so booting it is an ignored test that needs
+//! `CHIP8_VIP_INTERPRETER` to point at the 512-byte image. The other end-to-end test boots a
+//! small 1802 program that drives the display the same way the interpreter does.
+
+use chip_8_interpreter::vip::{
+    Bus, Cdp1802, Cdp1861, CosmacVip, CYCLES_PER_FRAME, DISPLAY_LINES, VIP_HEIGHT, VIP_WIDTH,
+};
+use chip_8_interpreter::{Chip8, Quirks};
+
+use std::path::Path;
+
+/// 64 KiB of RAM with settable `EF` flags.
+struct TestBus {
+    memory: Vec<u8>,
+    flags: [bool; 4],
+    outputs: Vec<(u8, u8)>,
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        0x10 + port
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        self.outputs.push((port, val));
+    }
+
+    fn flag(&self, n: u8) -> bool {
+        self.flags[n as usize - 1]
+    }
+}
+
+/// A CPU just out of reset, so running `program` from `0x0000` with `R0` as the program counter.
+fn cpu_with(program: &[u8]) -> (Cdp1802, TestBus) {
+    let mut memory = vec![0; 0x10000];
+    memory[..program.len()].copy_from_slice(program);
+    let bus = TestBus {
+        memory,
+        flags: [false; 4],
+        outputs: Vec::new(),
+    };
+    (Cdp1802::new(), bus)
+}
+
+/// Runs `steps` instructions and returns the machine cycles they took.
+fn run(cpu: &mut Cdp1802, bus: &mut TestBus, steps: usize) -> u32 {
+    (0..steps).map(|_| cpu.step(bus)).sum()
+}
+
+/// The program counter after running `program` for `steps` instructions.
+fn pc_after(program: &[u8], steps: usize) -> u16 {
+    let (mut cpu, mut bus) = cpu_with(program);
+    run(&mut cpu, &mut bus, steps);
+    cpu.r[cpu.p as usize]
+}
+
+#[test]
+fn short_branches_replace_the_low_byte() {
+    // BR
+    let (mut cpu, mut bus) = cpu_with(&[0x30, 0x20]);
+    assert_eq!(run(&mut cpu, &mut bus, 1), 2);
+    assert_eq!(cpu.r[0], 0x20);
+
+    // LDI then BZ and BNZ, taken or skipping the target byte.
+    assert_eq!(pc_after(&[0xF8, 0x00, 0x32, 0x40], 2), 0x40);
+    assert_eq!(pc_after(&[0xF8, 0x05, 0x32, 0x40], 2), 0x04);
+    assert_eq!(pc_after(&[0xF8, 0x05, 0x3A, 0x40], 2), 0x40);
+    // SKP never branches.
+    assert_eq!(pc_after(&[0x38, 0x40], 1), 0x02);
+    // SEQ then BQ and BNQ.
+    assert_eq!(pc_after(&[0x7B, 0x31, 0x40], 2), 0x40);
+    assert_eq!(pc_after(&[0x7B, 0x39, 0x40], 2), 0x03);
+
+    // B1 to B4 test the EF flags, BN1 to BN4 their inverse.
+    for n in 1..=4u8 {
+        let (mut cpu, mut bus) = cpu_with(&[0x33 + n, 0x40]);
+        bus.flags[n as usize - 1] = true;
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!(cpu.r[0], 0x40, "B{}", n);
+
+        let (mut cpu, mut bus) = cpu_with(&[0x3B + n, 0x40]);
+        bus.flags[n as usize - 1] = true;
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!(cpu.r[0], 0x02, "BN{}", n);
+    }
+}
+
+/// A short branch goes to the page of its target byte, which is the next page when the opcode is
+/// the last byte of a page.
+#[test]
+fn short_branch_at_the_end_of_a_page() {
+    let (mut cpu, mut bus) = cpu_with(&[]);
+    bus.memory[0x1FE..0x200].copy_from_slice(&[0x30, 0x20]);
+    cpu.r[0] = 0x1FE;
+    run(&mut cpu, &mut bus, 1);
+    assert_eq!(cpu.r[0], 0x120);
+
+    let (mut cpu, mut bus) = cpu_with(&[]);
+    bus.memory[0x1FF..0x201].copy_from_slice(&[0x30, 0x20]);
+    cpu.r[0] = 0x1FF;
+    run(&mut cpu, &mut bus, 1);
+    assert_eq!(cpu.r[0], 0x220);
+}
+
+#[test]
+fn long_branches_and_skips_take_three_cycles() {
+    // LBR
+    let (mut cpu, mut bus) = cpu_with(&[0xC0, 0x12, 0x34]);
+    assert_eq!(run(&mut cpu, &mut bus, 1), 3);
+    assert_eq!(cpu.r[0], 0x1234);
+
+    // LDI then LBZ and LBNZ.
+    assert_eq!(pc_after(&[0xF8, 0x00, 0xC2, 0x12, 0x34], 2), 0x1234);
+    assert_eq!(pc_after(&[0xF8, 0x01, 0xC2, 0x12, 0x34], 2), 0x05);
+    assert_eq!(pc_after(&[0xF8, 0x01, 0xCA, 0x12, 0x34], 2), 0x1234);
+
+    // NOP, LSKP, and LSZ and LSNZ after LDI.
+    let (mut cpu, mut bus) = cpu_with(&[0xC4]);
+    assert_eq!(run(&mut cpu, &mut bus, 1), 3);
+    assert_eq!(cpu.r[0], 0x01);
+    let (mut cpu, mut bus) = cpu_with(&[0xC8]);
+    assert_eq!(run(&mut cpu, &mut bus, 1), 3);
+    assert_eq!(cpu.r[0], 0x03);
+    assert_eq!(pc_after(&[0xF8, 0x00, 0xCE], 2), 0x05);
+    assert_eq!(pc_after(&[0xF8, 0x00, 0xC6], 2), 0x03);
+
+    // LSIE skips with interrupts enabled, which they are after a reset.
+    assert_eq!(pc_after(&[0xCC], 1), 0x03);
+    // LSDF after SHL moves a one into DF.
+    assert_eq!(pc_after(&[0xF8, 0x80, 0xFE, 0xCF], 3), 0x06);
+}
+
+/// Runs `op` with `D`, `DF` and `M(R(X))` set up, and returns `D` and `DF` afterwards.
+fn alu(op: &[u8], d: u8, df: bool, m: u8) -> (u8, bool) {
+    let (mut cpu, mut bus) = cpu_with(op);
+    cpu.x = 2;
+    cpu.r[2] = 0x80;
+    bus.memory[0x80] = m;
+    cpu.d = d;
+    cpu.df = df;
+    run(&mut cpu, &mut bus, 1);
+    (cpu.d, cpu.df)
+}
+
+/// DF is the carry of additions and set when subtractions don't borrow.
+#[test]
+fn arithmetic_sets_carry_and_borrow() {
+    let cases = [
+        // ADD and ADC
+        (&[0xF4][..], 0x01, false, 0xFF, (0x00, true)),
+        (&[0xF4], 0x01, true, 0x01, (0x02, false)),
+        (&[0x74], 0x01, true, 0x01, (0x03, false)),
+        // SD: M - D
+        (&[0xF5], 0x03, false, 0x05, (0x02, true)),
+        (&[0xF5], 0x05, true, 0x03, (0xFE, false)),
+        // SM: D - M
+        (&[0xF7], 0x05, false, 0x03, (0x02, true)),
+        (&[0xF7], 0x03, true, 0x05, (0xFE, false)),
+        (&[0xF7], 0x03, false, 0x03, (0x00, true)),
+        // SDB and SMB also subtract the borrow, which is DF clear.
+        (&[0x75], 0x03, false, 0x05, (0x01, true)),
+        (&[0x75], 0x05, false, 0x05, (0xFF, false)),
+        (&[0x75], 0x05, true, 0x05, (0x00, true)),
+        (&[0x77], 0x05, false, 0x03, (0x01, true)),
+        (&[0x77], 0x03, false, 0x03, (0xFF, false)),
+        (&[0x77], 0x03, true, 0x03, (0x00, true)),
+        // SDI and SMI take the byte after the opcode.
+        (&[0xFD, 0x05], 0x03, false, 0x00, (0x02, true)),
+        (&[0xFF, 0x05], 0x03, true, 0x00, (0xFE, false)),
+        // SHR and SHL shift into DF, SHRC and SHLC rotate through it.
+        (&[0xF6], 0x03, false, 0x00, (0x01, true)),
+        (&[0xFE], 0x81, false, 0x00, (0x02, true)),
+        (&[0x76], 0x01, true, 0x00, (0x80, true)),
+        (&[0x76], 0x02, false, 0x00, (0x01, false)),
+        (&[0x7E], 0x80, false, 0x00, (0x00, true)),
+        (&[0x7E], 0x01, true, 0x00, (0x03, false)),
+    ];
+    for (op, d, df, m, expected) in cases {
+        assert_eq!(
+            alu(op, d, df, m),
+            expected,
+            "{:02X?} with D={:#04x} DF={} M={:#04x}",
+            op,
+            d,
+            df,
+            m
+        );
+    }
+}
+
+#[test]
+fn mark_saves_x_and_p() {
+    let (mut cpu, mut bus) = cpu_with(&[0x79]);
+    cpu.x = 3;
+    cpu.r[2] = 0x90;
+    run(&mut cpu, &mut bus, 1);
+    assert_eq!(cpu.t, 0x30);
+    assert_eq!(bus.memory[0x90], 0x30);
+    assert_eq!((cpu.x, cpu.p), (0, 0));
+    assert_eq!(cpu.r[2], 0x8F);
+}
+
+#[test]
+fn ret_and_dis_restore_x_and_p() {
+    for (op, ie) in [(0x70, true), (0x71, false)] {
+        let (mut cpu, mut bus) = cpu_with(&[op]);
+        cpu.ie = !ie;
+        cpu.x = 2;
+        cpu.r[2] = 0x90;
+        bus.memory[0x90] = 0x5A;
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!((cpu.x, cpu.p), (0x5, 0xA), "{:02X}", op);
+        assert_eq!(cpu.r[2], 0x91);
+        assert_eq!(cpu.ie, ie, "{:02X}", op);
+    }
+}
+
+#[test]
+fn input_and_output() {
+    // SEX 2, OUT 3, INP 5
+    let (mut cpu, mut bus) = cpu_with(&[0xE2, 0x63, 0x6D]);
+    cpu.r[2] = 0x80;
+    bus.memory[0x80] = 0x42;
+    run(&mut cpu, &mut bus, 3);
+    assert_eq!(bus.outputs, [(3, 0x42)]);
+    assert_eq!(cpu.r[2], 0x81);
+    assert_eq!(cpu.d, 0x15);
+    assert_eq!(bus.memory[0x81], 0x15);
+}
+
+#[test]
+fn idle_waits_for_dma_or_an_interrupt() {
+    let (mut cpu, mut bus) = cpu_with(&[0x00]);
+    run(&mut cpu, &mut bus, 1);
+    assert!(cpu.idle);
+    assert_eq!(run(&mut cpu, &mut bus, 5), 5);
+    assert_eq!(cpu.r[0], 1);
+
+    bus.memory[0x1234] = 0x99;
+    cpu.r[0] = 0x1234;
+    assert_eq!(cpu.dma_out(&mut bus), 0x99);
+    assert_eq!(cpu.r[0], 0x1235);
+    assert!(!cpu.idle);
+
+    cpu.idle = true;
+    cpu.x = 4;
+    cpu.p = 3;
+    assert!(cpu.interrupt());
+    assert!(!cpu.idle);
+    assert_eq!((cpu.t, cpu.x, cpu.p, cpu.ie), (0x43, 2, 1, false));
+    assert!(!cpu.interrupt(), "interrupts are disabled until RET");
+}
+
+/// What the CDP1861 asked for over one frame, with the CPU answering every request at once.
+struct FrameTiming {
+    /// Machine cycles into the frame at which the interrupt was requested.
+    interrupts: Vec<u32>,
+    /// Machine cycles at which DMA bytes were requested, one entry per byte.
+    dma: Vec<u32>,
+    /// Machine cycles for which `EF1` was asserted.
+    ef1_cycles: u32,
+}
+
+fn frame_timing(video: &mut Cdp1861) -> FrameTiming {
+    let mut timing = FrameTiming {
+        interrupts: Vec::new(),
+        dma: Vec::new(),
+        ef1_cycles: 0,
+    };
+    for cycle in 1..=CYCLES_PER_FRAME {
+        let last = video.tick();
+        assert_eq!(
+            last,
+            cycle == CYCLES_PER_FRAME,
+            "frame ends at cycle {}",
+            cycle
+        );
+        if video.interrupt_pending() {
+            timing.interrupts.push(cycle);
+            video.acknowledge_interrupt();
+        }
+        while video.dma_pending() {
+            timing.dma.push(cycle);
+            video.dma(timing.dma.len() as u8);
+        }
+        timing.ef1_cycles += video.ef1() as u32;
+    }
+    timing
+}
+
+#[test]
+fn video_interrupts_and_fetches_every_line_once_a_frame() {
+    let mut video = Cdp1861::new();
+    video.set_enabled(true);
+    for _ in 0..2 {
+        let timing = frame_timing(&mut video);
+        assert_eq!(timing.interrupts.len(), 1);
+        assert_eq!(timing.dma.len(), 8 * DISPLAY_LINES);
+        // Eight bytes at the start of each line, 14 cycles apart.
+        assert!(timing
+            .dma
+            .chunks(8)
+            .all(|line| line.iter().all(|c| *c == line[0])));
+        assert!(timing
+            .dma
+            .chunks(8)
+            .zip(timing.dma.chunks(8).skip(1))
+            .all(|(a, b)| b[0] - a[0] == 14));
+        // The VIP documentation has the first DMA 29 cycles after the interrupt.
+        assert_eq!(timing.dma[0] - timing.interrupts[0], 29);
+        // Four lines before the display and the last four of it.
+        assert_eq!(timing.ef1_cycles, 8 * 14);
+    }
+    let expected = (1..=8 * DISPLAY_LINES).map(|i| i as u8).collect::<Vec<_>>();
+    assert_eq!(video.frame(), expected);
+
+    video.set_enabled(false);
+    let timing = frame_timing(&mut video);
+    assert!(timing.interrupts.is_empty() && timing.dma.is_empty());
+    assert!(video.frame().iter().all(|byte| *byte == 0));
+}
+
+/// An 1802 program that sets up the display like the VIP's interpreter: `R1` points at an
+/// interrupt routine that saves `T` and `D`, points `R0` at the display page for DMA and returns.
+const DISPLAY_PAGE: u16 = 0x300;
+#[rustfmt::skip]
+const DISPLAY_PROGRAM: &[u8] = &[
+    // 000: switch the program counter to R3, since R0 belongs to DMA.
+    0xF8, 0x00, 0xB3, 0xF8, 0x07, 0xA3, 0xD3,
+    // 007: R1 = interrupt routine, R2 = stack, X = 2, turn the display on and loop.
+    0xF8, 0x00, 0xB1, 0xF8, 0x40, 0xA1,
+    0xF8, 0x0F, 0xB2, 0xF8, 0xF0, 0xA2,
+    0xE2, 0x69, 0x30, 0x15,
+];
+#[rustfmt::skip]
+const INTERRUPT_ROUTINE: &[u8] = &[
+    // 03E: restore D, then X and P.
+    0x72, 0x70,
+    // 040: save T and D, R0 = the display page, and back to 03E.
+    0x22, 0x78, 0x22, 0x52,
+    0xF8, (DISPLAY_PAGE >> 8) as u8, 0xB0, 0xF8, DISPLAY_PAGE as u8, 0xA0,
+    0x30, 0x3E,
+];
+
+#[test]
+fn vip_shows_the_display_page() {
+    let mut interpreter = vec![0; 0x200];
+    interpreter[..DISPLAY_PROGRAM.len()].copy_from_slice(DISPLAY_PROGRAM);
+    interpreter[0x3E..0x3E + INTERRUPT_ROUTINE.len()].copy_from_slice(INTERRUPT_ROUTINE);
+    let page = (0..1024).map(|i| (i * 7 + 3) as u8).collect::<Vec<_>>();
+    let mut rom = vec![0; DISPLAY_PAGE as usize - 0x200];
+    rom.extend_from_slice(&page);
+
+    let mut vip = CosmacVip::new(&interpreter, None).unwrap();
+    vip.load_rom(&rom).unwrap();
+    for _ in 0..3 {
+        vip.run_frame();
+    }
+    assert_eq!(vip.frames(), 3);
+    let cycles = vip.cycles() - 3 * CYCLES_PER_FRAME as u64;
+    assert!(cycles <= 2, "{} cycles past the third frame", cycles);
+
+    let expected = page
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1))
+        .collect::<Vec<_>>();
+    assert_eq!(vip.framebuffer().len(), VIP_WIDTH * VIP_HEIGHT);
+    assert_eq!(vip.framebuffer(), expected);
+    // The interrupt routine left the main loop's X and P alone.
+    assert_eq!((vip.cpu().x, vip.cpu().p), (2, 3));
+}
+
+/// Boots the real interpreter on the IBM logo and compares the display with [`Chip8`]'s, which
+/// the interpreter shows with every row repeated on four lines.
+#[test]
+#[ignore = "needs CHIP8_VIP_INTERPRETER"]
+fn vip_interpreter_draws_the_ibm_logo() {
+    let interpreter = std::env::var_os("CHIP8_VIP_INTERPRETER")
+        .expect("CHIP8_VIP_INTERPRETER should point at the VIP's CHIP-8 interpreter image");
+    let interpreter = std::fs::read(interpreter).unwrap();
+    let rom =
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/ibm-logo.ch8")).unwrap();
+
+    let mut vip = CosmacVip::new(&interpreter, None).unwrap();
+    vip.load_rom(&rom).unwrap();
+    for _ in 0..60 {
+        vip.run_frame();
+    }
+    let mut chip8 = Chip8::with_quirks(Quirks::COSMAC_VIP);
+    chip8.load_rom(&rom).unwrap();
+    for _ in 0..100 {
+        chip8.step().unwrap();
+    }
+
+    let lines = vip.framebuffer();
+    for (y, row) in chip8.framebuffer().chunks(VIP_WIDTH).enumerate() {
+        for line in 0..4 {
+            let start = (y * 4 + line) * VIP_WIDTH;
+            assert_eq!(
+                &lines[start..start + VIP_WIDTH],
+                row,
+                "row {} line {}",
+                y,
+                line
+            );
+        }
+    }
+}